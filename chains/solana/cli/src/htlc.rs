@@ -0,0 +1,177 @@
+//! The two HTLC programs behind one interface, and the printed view of an HTLC.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use train_solana_client::{anchor_htlc, native_htlc, Pubkey};
+
+/// Program holding the HTLCs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Program {
+    /// native_htlc, HTLCs holding SOL.
+    Native,
+    /// anchor_htlc, HTLCs holding SPL Token and Token-2022 tokens.
+    Token,
+}
+
+impl Program {
+    pub fn id(self) -> Pubkey {
+        match self {
+            Program::Native => native_htlc::ID,
+            Program::Token => anchor_htlc::ID,
+        }
+    }
+
+    pub fn find_htlc_address(self, sender: &Pubkey, id: &[u8; 32]) -> (Pubkey, u8) {
+        match self {
+            Program::Native => native_htlc::find_htlc_address(sender, id),
+            Program::Token => anchor_htlc::find_htlc_address(sender, id),
+        }
+    }
+
+    pub fn find_sender_state_address(self, sender: &Pubkey) -> Pubkey {
+        match self {
+            Program::Native => native_htlc::find_sender_state_address(sender).0,
+            Program::Token => anchor_htlc::find_sender_state_address(sender).0,
+        }
+    }
+
+    pub fn decode_htlc(self, data: &[u8]) -> anchor_lang::Result<Htlc> {
+        match self {
+            Program::Native => native_htlc::decode_htlc(data).map(Htlc::Native),
+            Program::Token => anchor_htlc::decode_htlc(data).map(Htlc::Token),
+        }
+    }
+
+    /// Name of the HTLCError variant of a custom error code.
+    pub fn error_name(self, code: u32) -> Option<String> {
+        match self {
+            Program::Native => native_htlc::htlc_error(code).map(|e| format!("{e:?}")),
+            Program::Token => anchor_htlc::htlc_error(code).map(|e| format!("{e:?}")),
+        }
+    }
+}
+
+/// An HTLC account of either program.
+pub enum Htlc {
+    Native(native_htlc::HTLC),
+    Token(anchor_htlc::HTLC),
+}
+
+impl Htlc {
+    pub fn sender(&self) -> Pubkey {
+        match self {
+            Htlc::Native(htlc) => htlc.sender,
+            Htlc::Token(htlc) => htlc.sender,
+        }
+    }
+
+    pub fn src_receiver(&self) -> Pubkey {
+        match self {
+            Htlc::Native(htlc) => htlc.src_receiver,
+            Htlc::Token(htlc) => htlc.src_receiver,
+        }
+    }
+
+    /// The token HTLC, or an error for a native one.
+    pub fn token(&self) -> anyhow::Result<&anchor_htlc::HTLC> {
+        match self {
+            Htlc::Token(htlc) => Ok(htlc),
+            Htlc::Native(_) => anyhow::bail!("not a token HTLC"),
+        }
+    }
+
+    pub fn view(&self, address: Pubkey, id: Option<[u8; 32]>) -> HtlcView {
+        match self {
+            Htlc::Native(htlc) => HtlcView {
+                address: address.to_string(),
+                id: id.map(hex::encode),
+                status: format!("{:?}", htlc.status),
+                sender: htlc.sender.to_string(),
+                src_receiver: htlc.src_receiver.to_string(),
+                hashlock: hex::encode(htlc.hashlock),
+                secret: hex::encode(htlc.secret),
+                amount: htlc.amount,
+                timelock: htlc.timelock,
+                reward: htlc.reward,
+                reward_timelock: htlc.reward_timelock,
+                src_asset: htlc.src_asset.clone(),
+                dst_chain: htlc.dst_chain.clone(),
+                dst_asset: htlc.dst_asset.clone(),
+                dst_address: htlc.dst_address.clone(),
+                token_contract: None,
+                token_program: None,
+                fee_payer: None,
+                hop_chains: htlc.hop_chains.clone(),
+                hop_assets: htlc.hop_assets.clone(),
+                hop_addresses: htlc.hop_addresses.clone(),
+                evm_authorizer: evm_authorizer(&htlc.evm_authorizer),
+                authorized_locker: htlc.authorized_locker.map(|key| key.to_string()),
+            },
+            Htlc::Token(htlc) => HtlcView {
+                address: address.to_string(),
+                id: id.map(hex::encode),
+                status: format!("{:?}", htlc.status),
+                sender: htlc.sender.to_string(),
+                src_receiver: htlc.src_receiver.to_string(),
+                hashlock: hex::encode(htlc.hashlock),
+                secret: hex::encode(htlc.secret),
+                amount: htlc.amount,
+                timelock: htlc.timelock,
+                reward: htlc.reward,
+                reward_timelock: htlc.reward_timelock,
+                src_asset: htlc.src_asset.clone(),
+                dst_chain: htlc.dst_chain.clone(),
+                dst_asset: htlc.dst_asset.clone(),
+                dst_address: htlc.dst_address.clone(),
+                token_contract: Some(htlc.token_contract.to_string()),
+                token_program: Some(htlc.token_program.to_string()),
+                fee_payer: Some(format!("{:?}", htlc.fee_payer)),
+                hop_chains: htlc.hop_chains.clone(),
+                hop_assets: htlc.hop_assets.clone(),
+                hop_addresses: htlc.hop_addresses.clone(),
+                evm_authorizer: evm_authorizer(&htlc.evm_authorizer),
+                authorized_locker: htlc.authorized_locker.map(|key| key.to_string()),
+            },
+        }
+    }
+}
+
+/// An HTLC as printed by show and list. The Id is not stored in the account,
+/// so it is only known when it was given.
+#[derive(Serialize)]
+pub struct HtlcView {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub status: String,
+    pub sender: String,
+    pub src_receiver: String,
+    pub hashlock: String,
+    pub secret: String,
+    pub amount: u64,
+    pub timelock: u64,
+    pub reward: u64,
+    pub reward_timelock: u64,
+    pub src_asset: String,
+    pub dst_chain: String,
+    pub dst_asset: String,
+    pub dst_address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_contract: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_program: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_payer: Option<String>,
+    pub hop_chains: Vec<String>,
+    pub hop_assets: Vec<String>,
+    pub hop_addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evm_authorizer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_locker: Option<String>,
+}
+
+/// The 0x-prefixed Ethereum address, or None for the zero address.
+fn evm_authorizer(address: &[u8; 20]) -> Option<String> {
+    (*address != [0; 20]).then(|| format!("0x{}", hex::encode(address)))
+}
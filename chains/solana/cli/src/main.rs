@@ -0,0 +1,869 @@
+//! train-sol drives the HTLCs of the Train Solana programs: commit, lock, settle
+//! and inspect them on any cluster, e.g. a local test validator.
+
+mod htlc;
+mod rpc;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use htlc::{Htlc, HtlcView, Program};
+use rpc::{Context, Outcome};
+use solana_keypair::{read_keypair_file, Keypair};
+use std::path::{Path, PathBuf};
+use train_solana_client::{anchor_htlc, native_htlc, AccountMeta, Instruction, Pubkey, Signer};
+
+#[derive(Parser)]
+#[command(
+    name = "train-sol",
+    version,
+    about = "Drive the HTLCs of the Train Solana programs"
+)]
+struct Cli {
+    /// RPC URL of the cluster.
+    #[arg(
+        long,
+        short = 'u',
+        global = true,
+        env = "SOLANA_URL",
+        default_value = "http://127.0.0.1:8899"
+    )]
+    url: String,
+    /// Keypair file paying for and signing the transactions [default: ~/.config/solana/id.json].
+    #[arg(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+    /// Program holding the HTLCs.
+    #[arg(long, global = true, value_enum, default_value_t = Program::Native)]
+    program: Program,
+    /// Simulate the transaction instead of sending it.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Print JSON.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Commit funds to a new HTLC whose hashlock is set later.
+    Commit(CommitArgs),
+    /// Create an HTLC with its hashlock.
+    Lock(LockArgs),
+    /// Set the hashlock of a committed HTLC, signed by its sender, its authorized locker or a session delegate.
+    AddLock(AddLockArgs),
+    /// Set the hashlock of a committed HTLC with an off-chain signature of its sender.
+    AddLockSig(AddLockSigArgs),
+    /// Let another key set the hashlock of a committed HTLC of the keypair.
+    SetAuthorizedLocker(SetAuthorizedLockerArgs),
+    /// Let an Ethereum address set the hashlock of a committed HTLC of the keypair.
+    SetEvmAuthorizer(SetEvmAuthorizerArgs),
+    /// Set the hashlock of a committed HTLC with an EIP-712 signature of its EVM authorizer.
+    AddLockSigEvm(AddLockSigEvmArgs),
+    /// Add a reward for redeeming a locked HTLC, signed by its sender or a session delegate.
+    LockReward(LockRewardArgs),
+    /// Redeem a locked HTLC with its secret.
+    Redeem(RedeemArgs),
+    /// Refund an expired HTLC to its sender.
+    Refund(HtlcArgs),
+    /// Show an HTLC.
+    Show(HtlcArgs),
+    /// List the HTLCs of a sender or a receiver.
+    List(ListArgs),
+}
+
+/// Locates an HTLC.
+#[derive(Args)]
+struct HtlcArgs {
+    /// Id of the HTLC, hex.
+    #[arg(value_parser = parse_bytes32)]
+    id: [u8; 32],
+    /// Sender of the HTLC [default: the keypair].
+    #[arg(long)]
+    sender: Option<Pubkey>,
+}
+
+/// Funds of a new HTLC.
+#[derive(Args)]
+struct FundingArgs {
+    /// Id of the HTLC, hex [default: an Id reserved with reserve_id].
+    #[arg(long, value_parser = parse_bytes32)]
+    id: Option<[u8; 32]>,
+    /// Receiver of the funds on Solana.
+    #[arg(long)]
+    receiver: Pubkey,
+    /// Amount in lamports, or in base units of the mint.
+    #[arg(long)]
+    amount: u64,
+    /// Mint of the tokens, required with --program token.
+    #[arg(long)]
+    mint: Option<Pubkey>,
+    /// Who bears the transfer fee of Token-2022 transfer-fee mints.
+    #[arg(long, value_enum, default_value_t = FeePayer::Receiver)]
+    fee_payer: FeePayer,
+    /// Asset locked on Solana, as named on the destination side.
+    #[arg(long)]
+    src_asset: String,
+    /// Destination chain.
+    #[arg(long)]
+    dst_chain: String,
+    /// Asset received on the destination chain.
+    #[arg(long)]
+    dst_asset: String,
+    /// Address receiving on the destination chain.
+    #[arg(long)]
+    dst_address: String,
+}
+
+#[derive(Args)]
+struct CommitArgs {
+    #[command(flatten)]
+    funding: FundingArgs,
+    /// Unix timestamp after which the sender can refund.
+    #[arg(long)]
+    timelock: u64,
+    /// Chains of the route, repeated once per hop.
+    #[arg(long = "hop-chain")]
+    hop_chains: Vec<String>,
+    /// Assets of the route, repeated once per hop.
+    #[arg(long = "hop-asset")]
+    hop_assets: Vec<String>,
+    /// Addresses of the route, repeated once per hop.
+    #[arg(long = "hop-address")]
+    hop_addresses: Vec<String>,
+    /// Key that may set the hashlock with add-lock besides the sender.
+    #[arg(long)]
+    authorized_locker: Option<Pubkey>,
+}
+
+#[derive(Args)]
+struct LockArgs {
+    #[command(flatten)]
+    funding: FundingArgs,
+    /// sha256 of the secret, hex.
+    #[arg(long, value_parser = parse_bytes32)]
+    hashlock: [u8; 32],
+    /// Unix timestamp after which the sender can refund.
+    #[arg(long)]
+    timelock: u64,
+}
+
+#[derive(Args)]
+struct AddLockArgs {
+    #[command(flatten)]
+    htlc: HtlcArgs,
+    /// sha256 of the secret, hex.
+    #[arg(long, value_parser = parse_bytes32)]
+    hashlock: [u8; 32],
+    /// Unix timestamp after which the sender can refund.
+    #[arg(long)]
+    timelock: u64,
+    /// Sign through the session the sender created for the keypair.
+    #[arg(long)]
+    session: bool,
+}
+
+#[derive(Args)]
+struct AddLockSigArgs {
+    /// Id of the HTLC, hex.
+    #[arg(value_parser = parse_bytes32)]
+    id: [u8; 32],
+    /// Keypair file of the sender of the HTLC, signing the lock off-chain.
+    #[arg(long)]
+    signer: PathBuf,
+    /// sha256 of the secret, hex.
+    #[arg(long, value_parser = parse_bytes32)]
+    hashlock: [u8; 32],
+    /// Unix timestamp after which the sender can refund.
+    #[arg(long)]
+    timelock: u64,
+    /// Unix timestamp after which the signature is no longer accepted.
+    #[arg(long, required_unless_present = "legacy")]
+    deadline: Option<u64>,
+    /// Genesis hash the program is built with, base58. Defaults to the genesis
+    /// hash of the cluster; 11111111111111111111111111111111 for programs built
+    /// without a cluster feature, e.g. on a local validator.
+    #[arg(long, value_parser = parse_genesis_hash)]
+    genesis_hash: Option<[u8; 32]>,
+    /// Sign the legacy message, bound to neither the program, the cluster nor
+    /// a deadline, and call add_lock_sig_legacy.
+    #[arg(long, conflicts_with_all = ["deadline", "genesis_hash"])]
+    legacy: bool,
+}
+
+#[derive(Args)]
+struct SetAuthorizedLockerArgs {
+    /// Id of the HTLC, hex.
+    #[arg(value_parser = parse_bytes32)]
+    id: [u8; 32],
+    /// Key allowed to call add-lock [default: none, removing the current one].
+    #[arg(long)]
+    locker: Option<Pubkey>,
+}
+
+#[derive(Args)]
+struct SetEvmAuthorizerArgs {
+    /// Id of the HTLC, hex.
+    #[arg(value_parser = parse_bytes32)]
+    id: [u8; 32],
+    /// Ethereum address, hex. The zero address removes the authorizer.
+    #[arg(long, value_parser = parse_evm_address)]
+    address: [u8; 20],
+}
+
+#[derive(Args)]
+struct AddLockSigEvmArgs {
+    #[command(flatten)]
+    htlc: HtlcArgs,
+    /// sha256 of the secret, hex.
+    #[arg(long, value_parser = parse_bytes32)]
+    hashlock: [u8; 32],
+    /// Unix timestamp after which the sender can refund.
+    #[arg(long)]
+    timelock: u64,
+    /// Unix timestamp after which the signature is no longer accepted.
+    #[arg(long)]
+    deadline: u64,
+    /// r || s || v signature of eth_signTypedData_v4, hex.
+    #[arg(long, value_parser = parse_evm_signature)]
+    signature: ([u8; 32], [u8; 32], u8),
+}
+
+#[derive(Args)]
+struct LockRewardArgs {
+    #[command(flatten)]
+    htlc: HtlcArgs,
+    /// Reward in lamports, or in base units of the mint.
+    #[arg(long)]
+    reward: u64,
+    /// Unix timestamp after which anyone redeeming gets the reward.
+    #[arg(long)]
+    reward_timelock: u64,
+    /// Sign through the session the sender created for the keypair, paying
+    /// the reward from its budget.
+    #[arg(long)]
+    session: bool,
+}
+
+#[derive(Args)]
+struct RedeemArgs {
+    #[command(flatten)]
+    htlc: HtlcArgs,
+    /// Secret of the hashlock, hex.
+    #[arg(long, value_parser = parse_bytes32)]
+    secret: [u8; 32],
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("party").required(true).args(["sender", "receiver"])))]
+struct ListArgs {
+    /// List the HTLCs of this sender.
+    #[arg(long)]
+    sender: Option<Pubkey>,
+    /// List the HTLCs paying this receiver.
+    #[arg(long)]
+    receiver: Option<Pubkey>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FeePayer {
+    Receiver,
+    Sender,
+}
+
+impl From<FeePayer> for anchor_htlc::FeePayer {
+    fn from(fee_payer: FeePayer) -> Self {
+        match fee_payer {
+            FeePayer::Receiver => anchor_htlc::FeePayer::Receiver,
+            FeePayer::Sender => anchor_htlc::FeePayer::Sender,
+        }
+    }
+}
+
+fn parse_bytes32(s: &str) -> Result<[u8; 32], String> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected 32 bytes, got {}", bytes.len()))
+}
+
+fn parse_genesis_hash(s: &str) -> Result<[u8; 32], String> {
+    s.parse::<Pubkey>()
+        .map(|hash| hash.to_bytes())
+        .map_err(|err| err.to_string())
+}
+
+fn parse_evm_address(s: &str) -> Result<[u8; 20], String> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("expected 20 bytes, got {}", bytes.len()))
+}
+
+fn parse_evm_signature(s: &str) -> Result<([u8; 32], [u8; 32], u8), String> {
+    let bytes = hex::decode(s.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+    if bytes.len() != 65 {
+        return Err(format!("expected 65 bytes, got {}", bytes.len()));
+    }
+    Ok((
+        bytes[..32].try_into().unwrap(),
+        bytes[32..64].try_into().unwrap(),
+        bytes[64],
+    ))
+}
+
+fn read_keypair(path: &Path) -> Result<Keypair> {
+    read_keypair_file(path).map_err(|err| anyhow!("reading keypair {}: {err}", path.display()))
+}
+
+fn default_keypair_path() -> Result<PathBuf> {
+    let home = std::env::var("HOME").context("HOME is not set, pass --keypair")?;
+    Ok(Path::new(&home).join(".config/solana/id.json"))
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keypair_path = match cli.keypair {
+        Some(path) => path,
+        None => default_keypair_path()?,
+    };
+    let ctx = Context::new(
+        cli.url,
+        read_keypair(&keypair_path)?,
+        cli.program,
+        cli.dry_run,
+        cli.json,
+    );
+
+    let outcome = match cli.command {
+        Command::Commit(args) => commit(&ctx, args)?,
+        Command::Lock(args) => lock(&ctx, args)?,
+        Command::AddLock(args) => add_lock(&ctx, args)?,
+        Command::AddLockSig(args) => add_lock_sig(&ctx, args)?,
+        Command::SetAuthorizedLocker(args) => set_authorized_locker(&ctx, args)?,
+        Command::SetEvmAuthorizer(args) => set_evm_authorizer(&ctx, args)?,
+        Command::AddLockSigEvm(args) => add_lock_sig_evm(&ctx, args)?,
+        Command::LockReward(args) => lock_reward(&ctx, args)?,
+        Command::Redeem(args) => redeem(&ctx, args)?,
+        Command::Refund(args) => refund(&ctx, args)?,
+        Command::Show(args) => return show(&ctx, args),
+        Command::List(args) => return list(&ctx, args),
+    };
+    ctx.print(&outcome)?;
+    if let Some(error) = outcome.error {
+        bail!("transaction failed: {error}");
+    }
+    Ok(())
+}
+
+impl HtlcArgs {
+    fn sender(&self, ctx: &Context) -> Pubkey {
+        self.sender.unwrap_or_else(|| ctx.payer.pubkey())
+    }
+}
+
+/// The Id of a new HTLC, and the reserve_id instruction when it is reserved.
+fn new_id(ctx: &Context, id: Option<[u8; 32]>) -> Result<([u8; 32], Option<Instruction>)> {
+    let sender = ctx.payer.pubkey();
+    Ok(match id {
+        Some(id) => (id, None),
+        None => {
+            let reserve = match ctx.program {
+                Program::Native => native_htlc::reserve_id(&sender),
+                Program::Token => anchor_htlc::reserve_id(&sender),
+            };
+            (ctx.next_reserved_id(&sender)?, Some(reserve))
+        }
+    })
+}
+
+/// Mint and token program of a new token HTLC, and the hook accounts of the deposit.
+fn deposit_token(
+    ctx: &Context,
+    funding: &FundingArgs,
+    id: &[u8; 32],
+) -> Result<(Pubkey, Pubkey, Vec<AccountMeta>)> {
+    let mint = funding
+        .mint
+        .context("--mint is required with --program token")?;
+    let token_program = ctx.token_program_of(&mint)?;
+    let sender = ctx.payer.pubkey();
+    let hook_accounts = hook_accounts(
+        ctx,
+        &mint,
+        &token_program,
+        &sender,
+        &[(
+            anchor_htlc::associated_token_address(&sender, &mint, &token_program),
+            anchor_htlc::find_htlc_token_account_address(&sender, id).0,
+            funding.amount,
+        )],
+    )?;
+    Ok((mint, token_program, hook_accounts))
+}
+
+fn hook_accounts(
+    ctx: &Context,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    authority: &Pubkey,
+    transfers: &[(Pubkey, Pubkey, u64)],
+) -> Result<Vec<AccountMeta>> {
+    // Only Token-2022 mints can have a transfer hook.
+    if *token_program != anchor_spl::token_2022::ID {
+        return Ok(vec![]);
+    }
+    ctx.transfer_hook_accounts(mint, authority, transfers)
+}
+
+fn commit(ctx: &Context, args: CommitArgs) -> Result<Outcome> {
+    let sender = ctx.payer.pubkey();
+    let funding = &args.funding;
+    let (id, reserve) = new_id(ctx, funding.id)?;
+    let mut instructions: Vec<Instruction> = reserve.into_iter().collect();
+    instructions.push(match ctx.program {
+        Program::Native => native_htlc::commit(
+            &sender,
+            native_htlc::instruction::Commit {
+                Id: id,
+                hopChains: args.hop_chains,
+                hopAssets: args.hop_assets,
+                hopAddresses: args.hop_addresses,
+                dst_chain: funding.dst_chain.clone(),
+                dst_asset: funding.dst_asset.clone(),
+                dst_address: funding.dst_address.clone(),
+                src_asset: funding.src_asset.clone(),
+                src_receiver: funding.receiver,
+                timelock: args.timelock,
+                amount: funding.amount,
+                authorized_locker: args.authorized_locker,
+            },
+        ),
+        Program::Token => {
+            let (mint, token_program, hook_accounts) = deposit_token(ctx, funding, &id)?;
+            let mut ix = anchor_htlc::commit(
+                &sender,
+                &mint,
+                &token_program,
+                anchor_htlc::instruction::Commit {
+                    Id: id,
+                    hopChains: args.hop_chains,
+                    hopAssets: args.hop_assets,
+                    hopAddress: args.hop_addresses,
+                    dst_chain: funding.dst_chain.clone(),
+                    dst_asset: funding.dst_asset.clone(),
+                    dst_address: funding.dst_address.clone(),
+                    src_asset: funding.src_asset.clone(),
+                    src_receiver: funding.receiver,
+                    timelock: args.timelock,
+                    amount: funding.amount,
+                    fee_payer: funding.fee_payer.into(),
+                    authorized_locker: args.authorized_locker,
+                },
+            );
+            ix.accounts.extend(hook_accounts);
+            ix
+        }
+    });
+    let htlc = ctx.program.find_htlc_address(&sender, &id).0;
+    ctx.execute(&id, &htlc, &instructions)
+}
+
+fn lock(ctx: &Context, args: LockArgs) -> Result<Outcome> {
+    let sender = ctx.payer.pubkey();
+    let funding = &args.funding;
+    let (id, reserve) = new_id(ctx, funding.id)?;
+    let mut instructions: Vec<Instruction> = reserve.into_iter().collect();
+    instructions.push(match ctx.program {
+        Program::Native => native_htlc::lock(
+            &sender,
+            native_htlc::instruction::Lock {
+                Id: id,
+                hashlock: args.hashlock,
+                timelock: args.timelock,
+                amount: funding.amount,
+                dst_chain: funding.dst_chain.clone(),
+                dst_address: funding.dst_address.clone(),
+                dst_asset: funding.dst_asset.clone(),
+                src_asset: funding.src_asset.clone(),
+                src_receiver: funding.receiver,
+            },
+        ),
+        Program::Token => {
+            let (mint, token_program, hook_accounts) = deposit_token(ctx, funding, &id)?;
+            let mut ix = anchor_htlc::lock(
+                &sender,
+                &mint,
+                &token_program,
+                anchor_htlc::instruction::Lock {
+                    Id: id,
+                    hashlock: args.hashlock,
+                    timelock: args.timelock,
+                    dst_chain: funding.dst_chain.clone(),
+                    dst_address: funding.dst_address.clone(),
+                    dst_asset: funding.dst_asset.clone(),
+                    src_asset: funding.src_asset.clone(),
+                    src_receiver: funding.receiver,
+                    amount: funding.amount,
+                    fee_payer: funding.fee_payer.into(),
+                },
+            );
+            ix.accounts.extend(hook_accounts);
+            ix
+        }
+    });
+    let htlc = ctx.program.find_htlc_address(&sender, &id).0;
+    ctx.execute(&id, &htlc, &instructions)
+}
+
+fn add_lock(ctx: &Context, args: AddLockArgs) -> Result<Outcome> {
+    let sender = args.htlc.sender(ctx);
+    let locker = ctx.payer.pubkey();
+    let id = args.htlc.id;
+    let ix = match ctx.program {
+        Program::Native => {
+            let build = if args.session {
+                native_htlc::add_lock_by_session
+            } else {
+                native_htlc::add_lock_as
+            };
+            build(
+                &locker,
+                &sender,
+                native_htlc::instruction::AddLock {
+                    Id: id,
+                    hashlock: args.hashlock,
+                    timelock: args.timelock,
+                },
+            )
+        }
+        Program::Token => {
+            let build = if args.session {
+                anchor_htlc::add_lock_by_session
+            } else {
+                anchor_htlc::add_lock_as
+            };
+            build(
+                &locker,
+                &sender,
+                anchor_htlc::instruction::AddLock {
+                    Id: id,
+                    hashlock: args.hashlock,
+                    timelock: args.timelock,
+                },
+            )
+        }
+    };
+    let htlc = ctx.program.find_htlc_address(&sender, &id).0;
+    ctx.execute(&id, &htlc, &[ix])
+}
+
+fn add_lock_sig(ctx: &Context, args: AddLockSigArgs) -> Result<Outcome> {
+    let signer = read_keypair(&args.signer)?;
+    let payer = ctx.payer.pubkey();
+    let (id, hashlock, timelock) = (args.id, args.hashlock, args.timelock);
+    let instructions = match (args.deadline, ctx.program) {
+        (None, Program::Native) => {
+            native_htlc::signed_add_lock_sig_legacy(&payer, &signer, id, hashlock, timelock)?
+        }
+        (None, Program::Token) => {
+            anchor_htlc::signed_add_lock_sig_legacy(&payer, &signer, id, hashlock, timelock)?
+        }
+        (Some(deadline), program) => {
+            let genesis_hash = match args.genesis_hash {
+                Some(genesis_hash) => genesis_hash,
+                None => ctx.genesis_hash()?,
+            };
+            match program {
+                Program::Native => native_htlc::signed_add_lock_sig(
+                    &payer,
+                    &signer,
+                    genesis_hash,
+                    id,
+                    hashlock,
+                    timelock,
+                    deadline,
+                )?,
+                Program::Token => anchor_htlc::signed_add_lock_sig(
+                    &payer,
+                    &signer,
+                    genesis_hash,
+                    id,
+                    hashlock,
+                    timelock,
+                    deadline,
+                )?,
+            }
+        }
+    };
+    let htlc = ctx.program.find_htlc_address(&signer.pubkey(), &args.id).0;
+    ctx.execute(&args.id, &htlc, &instructions)
+}
+
+fn set_authorized_locker(ctx: &Context, args: SetAuthorizedLockerArgs) -> Result<Outcome> {
+    let sender = ctx.payer.pubkey();
+    let ix = match ctx.program {
+        Program::Native => native_htlc::set_authorized_locker(
+            &sender,
+            native_htlc::instruction::SetAuthorizedLocker {
+                Id: args.id,
+                authorized_locker: args.locker,
+            },
+        ),
+        Program::Token => anchor_htlc::set_authorized_locker(
+            &sender,
+            anchor_htlc::instruction::SetAuthorizedLocker {
+                Id: args.id,
+                authorized_locker: args.locker,
+            },
+        ),
+    };
+    let htlc = ctx.program.find_htlc_address(&sender, &args.id).0;
+    ctx.execute(&args.id, &htlc, &[ix])
+}
+
+fn set_evm_authorizer(ctx: &Context, args: SetEvmAuthorizerArgs) -> Result<Outcome> {
+    let sender = ctx.payer.pubkey();
+    let ix = match ctx.program {
+        Program::Native => native_htlc::set_evm_authorizer(
+            &sender,
+            native_htlc::instruction::SetEvmAuthorizer {
+                Id: args.id,
+                evm_authorizer: args.address,
+            },
+        ),
+        Program::Token => anchor_htlc::set_evm_authorizer(
+            &sender,
+            anchor_htlc::instruction::SetEvmAuthorizer {
+                Id: args.id,
+                evm_authorizer: args.address,
+            },
+        ),
+    };
+    let htlc = ctx.program.find_htlc_address(&sender, &args.id).0;
+    ctx.execute(&args.id, &htlc, &[ix])
+}
+
+fn add_lock_sig_evm(ctx: &Context, args: AddLockSigEvmArgs) -> Result<Outcome> {
+    let sender = args.htlc.sender(ctx);
+    let id = args.htlc.id;
+    let payer = ctx.payer.pubkey();
+    let (r, s, v) = args.signature;
+    let ix = match ctx.program {
+        Program::Native => native_htlc::add_lock_sig_evm(
+            &payer,
+            &sender,
+            native_htlc::instruction::AddLockSigEvm {
+                Id: id,
+                hashlock: args.hashlock,
+                timelock: args.timelock,
+                deadline: args.deadline,
+                r,
+                s,
+                v,
+            },
+        ),
+        Program::Token => anchor_htlc::add_lock_sig_evm(
+            &payer,
+            &sender,
+            anchor_htlc::instruction::AddLockSigEvm {
+                Id: id,
+                hashlock: args.hashlock,
+                timelock: args.timelock,
+                deadline: args.deadline,
+                r,
+                s,
+                v,
+            },
+        ),
+    };
+    let htlc = ctx.program.find_htlc_address(&sender, &id).0;
+    ctx.execute(&id, &htlc, &[ix])
+}
+
+fn lock_reward(ctx: &Context, args: LockRewardArgs) -> Result<Outcome> {
+    let sender = args.htlc.sender(ctx);
+    let delegate = ctx.payer.pubkey();
+    let id = args.htlc.id;
+    let address = ctx.program.find_htlc_address(&sender, &id).0;
+    let ix = match ctx.program {
+        Program::Native => {
+            let reward = native_htlc::instruction::LockReward {
+                Id: id,
+                reward_timelock: args.reward_timelock,
+                reward: args.reward,
+            };
+            if args.session {
+                native_htlc::lock_reward_by_session(&delegate, &sender, reward)
+            } else {
+                native_htlc::lock_reward(&sender, reward)
+            }
+        }
+        Program::Token => {
+            let htlc = ctx.fetch_htlc(&address)?;
+            let htlc = htlc.token()?;
+            // a session moves the reward as SPL delegate of the sender token account
+            let transfer_authority = if args.session {
+                anchor_htlc::find_session_address(&sender, &delegate).0
+            } else {
+                sender
+            };
+            let hook_accounts = hook_accounts(
+                ctx,
+                &htlc.token_contract,
+                &htlc.token_program,
+                &transfer_authority,
+                &[(
+                    anchor_htlc::associated_token_address(
+                        &sender,
+                        &htlc.token_contract,
+                        &htlc.token_program,
+                    ),
+                    htlc.token_wallet,
+                    args.reward,
+                )],
+            )?;
+            let reward = anchor_htlc::instruction::LockReward {
+                Id: id,
+                reward_timelock: args.reward_timelock,
+                reward: args.reward,
+            };
+            let mut ix = if args.session {
+                anchor_htlc::lock_reward_by_session(
+                    &delegate,
+                    &sender,
+                    &htlc.token_contract,
+                    &htlc.token_program,
+                    reward,
+                )
+            } else {
+                anchor_htlc::lock_reward(&sender, &htlc.token_contract, &htlc.token_program, reward)
+            };
+            ix.accounts.extend(hook_accounts);
+            ix
+        }
+    };
+    ctx.execute(&id, &address, &[ix])
+}
+
+fn redeem(ctx: &Context, args: RedeemArgs) -> Result<Outcome> {
+    let sender = args.htlc.sender(ctx);
+    let id = args.htlc.id;
+    let user_signing = ctx.payer.pubkey();
+    let (address, htlc_bump) = ctx.program.find_htlc_address(&sender, &id);
+    let htlc = ctx.fetch_htlc(&address)?;
+    let ix = match &htlc {
+        Htlc::Native(htlc) => native_htlc::redeem(
+            &user_signing,
+            &sender,
+            &htlc.src_receiver,
+            native_htlc::instruction::Redeem {
+                Id: id,
+                secret: args.secret,
+            },
+        ),
+        Htlc::Token(htlc) => {
+            let ata = |owner: &Pubkey| {
+                anchor_htlc::associated_token_address(
+                    owner,
+                    &htlc.token_contract,
+                    &htlc.token_program,
+                )
+            };
+            // The reward goes back to the sender before the reward timelock and
+            // to the caller after it, resolve the hook accounts for both.
+            let mut transfers = vec![(htlc.token_wallet, ata(&htlc.src_receiver), htlc.amount)];
+            if htlc.reward != 0 {
+                transfers.push((htlc.token_wallet, ata(&sender), htlc.reward));
+                transfers.push((htlc.token_wallet, ata(&user_signing), htlc.reward));
+            }
+            let hook_accounts = hook_accounts(
+                ctx,
+                &htlc.token_contract,
+                &htlc.token_program,
+                &address,
+                &transfers,
+            )?;
+            let mut ix = anchor_htlc::redeem(
+                &user_signing,
+                &sender,
+                &htlc.src_receiver,
+                &htlc.token_contract,
+                &htlc.token_program,
+                anchor_htlc::instruction::Redeem {
+                    Id: id,
+                    secret: args.secret,
+                    htlc_bump,
+                },
+            );
+            ix.accounts.extend(hook_accounts);
+            ix
+        }
+    };
+    ctx.execute(&id, &address, &[ix])
+}
+
+fn refund(ctx: &Context, args: HtlcArgs) -> Result<Outcome> {
+    let sender = args.sender(ctx);
+    let id = args.id;
+    let user_signing = ctx.payer.pubkey();
+    let (address, htlc_bump) = ctx.program.find_htlc_address(&sender, &id);
+    let ix = match ctx.program {
+        Program::Native => native_htlc::refund(
+            &user_signing,
+            &sender,
+            native_htlc::instruction::Refund { Id: id },
+        ),
+        Program::Token => {
+            let htlc = ctx.fetch_htlc(&address)?;
+            let htlc = htlc.token()?;
+            let hook_accounts = hook_accounts(
+                ctx,
+                &htlc.token_contract,
+                &htlc.token_program,
+                &address,
+                &[(
+                    htlc.token_wallet,
+                    anchor_htlc::associated_token_address(
+                        &sender,
+                        &htlc.token_contract,
+                        &htlc.token_program,
+                    ),
+                    htlc.amount + htlc.reward,
+                )],
+            )?;
+            let mut ix = anchor_htlc::refund(
+                &user_signing,
+                &sender,
+                &htlc.token_contract,
+                &htlc.token_program,
+                anchor_htlc::instruction::Refund { Id: id, htlc_bump },
+            );
+            ix.accounts.extend(hook_accounts);
+            ix
+        }
+    };
+    ctx.execute(&id, &address, &[ix])
+}
+
+fn show(ctx: &Context, args: HtlcArgs) -> Result<()> {
+    let sender = args.sender(ctx);
+    let address = ctx.program.find_htlc_address(&sender, &args.id).0;
+    let htlc = ctx.fetch_htlc(&address)?;
+    ctx.print(&htlc.view(address, Some(args.id)))
+}
+
+fn list(ctx: &Context, args: ListArgs) -> Result<()> {
+    let views: Vec<HtlcView> = ctx
+        .fetch_all_htlcs()?
+        .into_iter()
+        .filter(|(_, htlc)| {
+            args.sender.is_none_or(|sender| htlc.sender() == sender)
+                && args
+                    .receiver
+                    .is_none_or(|receiver| htlc.src_receiver() == receiver)
+        })
+        .map(|(address, htlc)| htlc.view(address, None))
+        .collect();
+    ctx.print(&views)
+}
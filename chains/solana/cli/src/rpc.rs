@@ -0,0 +1,265 @@
+//! Cluster access: sending or simulating transactions, and reading HTLCs.
+
+use crate::htlc::{Htlc, Program};
+use anchor_lang::Discriminator;
+use anyhow::{anyhow, Context as _, Result};
+use serde::Serialize;
+use serde_json::Value;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_commitment_config::CommitmentConfig;
+use solana_keypair::Keypair;
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::{
+    client_error::{Error as ClientError, ErrorKind as ClientErrorKind},
+    config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    filter::{Memcmp, RpcFilterType},
+    request::{RpcError, RpcResponseErrorData},
+};
+use solana_transaction::Transaction;
+use solana_transaction_error::TransactionError;
+use train_solana_client::anchor_htlc::resolve_transfer_hook_accounts;
+use train_solana_client::{
+    anchor_htlc, native_htlc, AccountMeta, Instruction, InstructionError, Pubkey, Signer,
+};
+
+/// Result of a transaction, sent or simulated.
+#[derive(Serialize)]
+pub struct Outcome {
+    pub program: String,
+    pub id: String,
+    pub htlc: String,
+    pub simulated: bool,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+pub struct Context {
+    pub rpc: RpcClient,
+    pub payer: Keypair,
+    pub program: Program,
+    pub dry_run: bool,
+    pub json: bool,
+}
+
+impl Context {
+    pub fn new(url: String, payer: Keypair, program: Program, dry_run: bool, json: bool) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(url, CommitmentConfig::confirmed()),
+            payer,
+            program,
+            dry_run,
+            json,
+        }
+    }
+
+    /// Sends the instructions in one transaction paid by the payer, or simulates
+    /// it with --dry-run. A failed transaction is reported in the outcome.
+    pub fn execute(
+        &self,
+        id: &[u8; 32],
+        htlc: &Pubkey,
+        instructions: &[Instruction],
+    ) -> Result<Outcome> {
+        let blockhash = self
+            .rpc
+            .get_latest_blockhash()
+            .context("getting a recent blockhash")?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            blockhash,
+        );
+
+        let mut outcome = Outcome {
+            program: format!("{:?}", self.program).to_lowercase(),
+            id: hex::encode(id),
+            htlc: htlc.to_string(),
+            simulated: self.dry_run,
+            signature: None,
+            error: None,
+            units_consumed: None,
+            logs: vec![],
+        };
+        if self.dry_run {
+            let result = self
+                .rpc
+                .simulate_transaction(&transaction)
+                .context("simulating the transaction")?
+                .value;
+            outcome.error = result.err.map(|err| self.describe(&err));
+            outcome.units_consumed = result.units_consumed;
+            outcome.logs = result.logs.unwrap_or_default();
+        } else {
+            match self.rpc.send_and_confirm_transaction(&transaction) {
+                Ok(signature) => outcome.signature = Some(signature.to_string()),
+                Err(err) => {
+                    outcome.error = Some(match err.get_transaction_error() {
+                        Some(err) => self.describe(&err),
+                        None => err.to_string(),
+                    });
+                    outcome.logs = preflight_logs(&err);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Describes a transaction error, naming the HTLCError of custom codes.
+    fn describe(&self, err: &TransactionError) -> String {
+        match err {
+            TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+                match self.program.error_name(*code) {
+                    Some(name) => format!("instruction {index}: {name} ({code})"),
+                    None => err.to_string(),
+                }
+            }
+            _ => err.to_string(),
+        }
+    }
+
+    pub fn fetch_htlc(&self, address: &Pubkey) -> Result<Htlc> {
+        let data = self
+            .rpc
+            .get_account_data(address)
+            .with_context(|| format!("no HTLC at {address}"))?;
+        self.program
+            .decode_htlc(&data)
+            .map_err(|err| anyhow!("{address} is not an HTLC: {err}"))
+    }
+
+    /// Reads every HTLC of the program, filtered by their discriminator.
+    pub fn fetch_all_htlcs(&self) -> Result<Vec<(Pubkey, Htlc)>> {
+        let discriminator = match self.program {
+            Program::Native => native_htlc::HTLC::DISCRIMINATOR,
+            Program::Token => anchor_htlc::HTLC::DISCRIMINATOR,
+        };
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                0,
+                discriminator.to_vec(),
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(&self.program.id(), config)
+            .context("listing the HTLC accounts")?;
+        Ok(accounts
+            .into_iter()
+            .filter_map(|(address, account)| {
+                let htlc = self.program.decode_htlc(&account.data).ok()?;
+                Some((address, htlc))
+            })
+            .collect())
+    }
+
+    /// Next Id reserve_id hands out to `sender`.
+    pub fn next_reserved_id(&self, sender: &Pubkey) -> Result<[u8; 32]> {
+        let address = self.program.find_sender_state_address(sender);
+        let nonce = match self.rpc.get_account_data(&address) {
+            Ok(data) => match self.program {
+                Program::Native => native_htlc::decode_sender_state(&data)?.nonce,
+                Program::Token => anchor_htlc::decode_sender_state(&data)?.nonce,
+            },
+            // No SenderState yet, reserve_id creates it with nonce 0.
+            Err(_) => 0,
+        };
+        Ok(match self.program {
+            Program::Native => native_htlc::derive_htlc_id(sender, nonce),
+            Program::Token => anchor_htlc::derive_htlc_id(sender, nonce),
+        })
+    }
+
+    /// Genesis hash of the cluster, which the add_lock_sig message binds.
+    pub fn genesis_hash(&self) -> Result<[u8; 32]> {
+        Ok(self
+            .rpc
+            .get_genesis_hash()
+            .context("getting the genesis hash")?
+            .to_bytes())
+    }
+
+    /// The token program owning `mint`.
+    pub fn token_program_of(&self, mint: &Pubkey) -> Result<Pubkey> {
+        Ok(self
+            .rpc
+            .get_account(mint)
+            .with_context(|| format!("no mint at {mint}"))?
+            .owner)
+    }
+
+    /// Resolves the transfer hook accounts of `mint` for the (source, destination,
+    /// amount) transfers made by `authority`. Empty for mints without a hook.
+    pub fn transfer_hook_accounts(
+        &self,
+        mint: &Pubkey,
+        authority: &Pubkey,
+        transfers: &[(Pubkey, Pubkey, u64)],
+    ) -> Result<Vec<AccountMeta>> {
+        let fetch =
+            |address: Pubkey| std::future::ready(Ok(self.rpc.get_account_data(&address).ok()));
+        futures::executor::block_on(resolve_transfer_hook_accounts(
+            mint, authority, transfers, fetch,
+        ))
+        .map_err(|err| anyhow!("resolving the transfer hook accounts of {mint}: {err}"))
+    }
+
+    /// Prints `value` as JSON with --json, else as `key: value` lines.
+    pub fn print(&self, value: &impl Serialize) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&value)?);
+            return Ok(());
+        }
+        match value {
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    print_fields(item);
+                }
+            }
+            value => print_fields(&value),
+        }
+        Ok(())
+    }
+}
+
+fn print_fields(value: &Value) {
+    let Value::Object(fields) = value else {
+        println!("{value}");
+        return;
+    };
+    for (key, value) in fields {
+        match value {
+            Value::Null => {}
+            Value::String(s) => println!("{key}: {s}"),
+            Value::Array(items) if key == "logs" => {
+                println!("{key}:");
+                for item in items {
+                    println!("  {}", item.as_str().unwrap_or_default());
+                }
+            }
+            value => println!("{key}: {value}"),
+        }
+    }
+}
+
+/// Logs of a transaction rejected by the preflight simulation.
+fn preflight_logs(err: &ClientError) -> Vec<String> {
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => result.logs.clone().unwrap_or_default(),
+        _ => vec![],
+    }
+}
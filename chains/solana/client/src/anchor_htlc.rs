@@ -0,0 +1,735 @@
+//! Client of the anchor_htlc program, HTLCs holding SPL Token and Token-2022 tokens.
+//!
+//! The builders take the arguments of an instruction as the struct generated by
+//! Anchor in [`instruction`] and derive every account from them. Token accounts of
+//! users are their associated token accounts for `token_program`.
+//!
+//! For mints with a transfer hook, append the accounts resolved with
+//! [`resolve_transfer_hook_accounts`] to the `accounts` of
+//! commit, lock, lock_reward, redeem and refund, and of the batch instructions.
+
+use crate::{
+    build_instruction, custom_error_code, hash_secret, lock_sig, AccountMeta, Instruction,
+    InstructionError, Pubkey, Signer, SignerError,
+};
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use anchor_lang::AccountDeserialize;
+use anchor_spl::associated_token::{self, get_associated_token_address_with_program_id};
+
+pub use anchor_htlc::{
+    accounts, check_ed25519_data, derive_htlc_id, find_htlc_address,
+    find_htlc_token_account_address, find_secret_record_address, find_sender_state_address,
+    find_session_address, instruction, FeePayer, HTLCError, HtlcStatus, LegacyHTLC, LockEntry,
+    SecretRecord, SenderState, Session, HTLC, HTLC_SEED, HTLC_TOKEN_ACCOUNT_SEED, ID,
+    MAX_ADDRESS_LEN, MAX_ASSET_LEN, MAX_CHAIN_LEN, MAX_HOPS, SECRET_RECORD_SEED, SENDER_STATE_SEED,
+    SESSION_ADD_LOCK, SESSION_LOCK_REWARD, SESSION_SEED,
+};
+
+pub mod transfer_hook;
+pub use transfer_hook::resolve_transfer_hook_accounts;
+
+/// Derives the HTLC PDA created with the legacy [Id] seeds, read by migrate.
+pub fn find_legacy_htlc_address(id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[id], &ID)
+}
+
+/// Derives the token account PDA of a legacy HTLC: [HTLC_TOKEN_ACCOUNT_SEED, Id].
+pub fn find_legacy_htlc_token_account_address(id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[HTLC_TOKEN_ACCOUNT_SEED, id], &ID)
+}
+
+/// Associated token account of `owner` for `token_contract` under `token_program`.
+pub fn associated_token_address(
+    owner: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, token_contract, token_program)
+}
+
+/// Decodes an HTLC account, checking its discriminator.
+pub fn decode_htlc(data: &[u8]) -> anchor_lang::Result<HTLC> {
+    HTLC::try_deserialize(&mut &data[..])
+}
+
+/// Decodes a SenderState account, checking its discriminator.
+pub fn decode_sender_state(data: &[u8]) -> anchor_lang::Result<SenderState> {
+    SenderState::try_deserialize(&mut &data[..])
+}
+
+/// Decodes a Session account, checking its discriminator.
+pub fn decode_session(data: &[u8]) -> anchor_lang::Result<Session> {
+    Session::try_deserialize(&mut &data[..])
+}
+
+/// Decodes a SecretRecord account, checking its discriminator.
+pub fn decode_secret_record(data: &[u8]) -> anchor_lang::Result<SecretRecord> {
+    SecretRecord::try_deserialize(&mut &data[..])
+}
+
+/// Maps a custom error code returned by the program back to HTLCError.
+pub fn htlc_error(code: u32) -> Option<HTLCError> {
+    code.checked_sub(crate::ERROR_CODE_OFFSET)
+        .and_then(|code| HTLCError::try_from(code).ok())
+}
+
+/// Maps the error of a failed instruction back to HTLCError.
+pub fn htlc_error_from_instruction_error(error: &InstructionError) -> Option<HTLCError> {
+    custom_error_code(error).and_then(htlc_error)
+}
+
+/// Builds commit, `sender` pays and signs.
+pub fn commit(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::Commit,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::Commit {
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+            token_contract: *token_contract,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            token_program: *token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds lock, `sender` pays and signs.
+pub fn lock(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::Lock,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::Lock {
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+            token_contract: *token_contract,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            token_program: *token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds lock_reward, signed by the sender of the HTLC.
+pub fn lock_reward(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::LockReward,
+) -> Instruction {
+    build_lock_reward(sender, sender, None, token_contract, token_program, args)
+}
+
+/// Builds lock_reward signed by `delegate` through its session with `sender`,
+/// the reward is paid from the sender token account the session is approved for.
+pub fn lock_reward_by_session(
+    delegate: &Pubkey,
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::LockReward,
+) -> Instruction {
+    let session = find_session_address(sender, delegate).0;
+    build_lock_reward(
+        delegate,
+        sender,
+        Some(session),
+        token_contract,
+        token_program,
+        args,
+    )
+}
+
+fn build_lock_reward(
+    authority: &Pubkey,
+    sender: &Pubkey,
+    session: Option<Pubkey>,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::LockReward,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::LockReward {
+            authority: *authority,
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            session: session,
+            htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+            token_contract: *token_contract,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds add_lock, signed by the sender of the HTLC.
+pub fn add_lock(sender: &Pubkey, args: instruction::AddLock) -> Instruction {
+    add_lock_as(sender, sender, args)
+}
+
+/// Builds add_lock signed by `locker`, the sender or the authorized_locker of
+/// the HTLC of `sender`.
+pub fn add_lock_as(locker: &Pubkey, sender: &Pubkey, args: instruction::AddLock) -> Instruction {
+    build_add_lock(locker, sender, None, args)
+}
+
+/// Builds add_lock signed by `delegate` through its session with `sender`.
+pub fn add_lock_by_session(
+    delegate: &Pubkey,
+    sender: &Pubkey,
+    args: instruction::AddLock,
+) -> Instruction {
+    let session = find_session_address(sender, delegate).0;
+    build_add_lock(delegate, sender, Some(session), args)
+}
+
+fn build_add_lock(
+    locker: &Pubkey,
+    sender: &Pubkey,
+    session: Option<Pubkey>,
+    args: instruction::AddLock,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::AddLock {
+            locker: *locker,
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            session: session,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds add_lock_sig. The transaction must carry an Ed25519Program
+/// instruction verifying the signature of `sender`, `payer` signs.
+pub fn add_lock_sig(payer: &Pubkey, sender: &Pubkey, args: instruction::AddLockSig) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::AddLockSig {
+            payer: *payer,
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            ix_sysvar: sysvar::instructions::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// The domain of the add_lock_sig message of this program on the cluster whose
+/// genesis hash is `genesis_hash`, e.g. from the getGenesisHash RPC method.
+pub fn add_lock_sig_domain(genesis_hash: [u8; 32]) -> lock_sig::Domain {
+    lock_sig::Domain {
+        program_id: ID,
+        genesis_hash,
+    }
+}
+
+/// Signs the lock with `sender`, valid until `deadline` on the cluster of
+/// `genesis_hash`, and builds the Ed25519Program instruction and add_lock_sig.
+/// The Ed25519Program instruction can go anywhere in the transaction, e.g.
+/// after compute budget instructions.
+pub fn signed_add_lock_sig(
+    payer: &Pubkey,
+    sender: &dyn Signer,
+    genesis_hash: [u8; 32],
+    id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> Result<[Instruction; 2], SignerError> {
+    let (signature, ed25519_instruction) = lock_sig::sign_add_lock(
+        sender,
+        &add_lock_sig_domain(genesis_hash),
+        &id,
+        &hashlock,
+        timelock,
+        deadline,
+    )?;
+    let instruction = add_lock_sig(
+        payer,
+        &sender.try_pubkey()?,
+        instruction::AddLockSig {
+            Id: id,
+            hashlock,
+            timelock,
+            deadline,
+            signature,
+        },
+    );
+    Ok([ed25519_instruction, instruction])
+}
+
+/// Builds add_lock_sig_legacy. The transaction must carry an Ed25519Program
+/// instruction verifying the legacy signature of `sender`, `payer` signs.
+pub fn add_lock_sig_legacy(
+    payer: &Pubkey,
+    sender: &Pubkey,
+    args: instruction::AddLockSigLegacy,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::AddLockSig {
+            payer: *payer,
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            ix_sysvar: sysvar::instructions::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Signs the legacy message with `sender` and builds the Ed25519Program
+/// instruction and add_lock_sig_legacy, for senders that have not migrated yet.
+pub fn signed_add_lock_sig_legacy(
+    payer: &Pubkey,
+    sender: &dyn Signer,
+    id: [u8; 32],
+    hashlock: [u8; 32],
+    timelock: u64,
+) -> Result<[Instruction; 2], SignerError> {
+    let (signature, ed25519_instruction) =
+        lock_sig::legacy::sign_add_lock(sender, &id, &hashlock, timelock)?;
+    let instruction = add_lock_sig_legacy(
+        payer,
+        &sender.try_pubkey()?,
+        instruction::AddLockSigLegacy {
+            Id: id,
+            hashlock,
+            timelock,
+            signature,
+        },
+    );
+    Ok([ed25519_instruction, instruction])
+}
+
+/// Builds set_authorized_locker, `sender` signs.
+pub fn set_authorized_locker(
+    sender: &Pubkey,
+    args: instruction::SetAuthorizedLocker,
+) -> Instruction {
+    let accounts = accounts::SetAuthorizedLocker {
+        sender: *sender,
+        htlc: find_htlc_address(sender, &args.Id).0,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds create_session, approving the session as delegate of the sender token
+/// account for the budget, `sender` pays and signs. The token account must have
+/// no other delegate with an allowance left.
+pub fn create_session(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::CreateSession,
+) -> Instruction {
+    let accounts = accounts::CreateSession {
+        sender: *sender,
+        session: find_session_address(sender, &args.delegate).0,
+        token_contract: *token_contract,
+        sender_token_account: associated_token_address(sender, token_contract, token_program),
+        system_program: system_program::ID,
+        token_program: *token_program,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds revoke_session, `sender` signs and gets the rent back.
+pub fn revoke_session(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::RevokeSession,
+) -> Instruction {
+    let accounts = accounts::RevokeSession {
+        sender: *sender,
+        session: find_session_address(sender, &args.delegate).0,
+        sender_token_account: associated_token_address(sender, token_contract, token_program),
+        token_program: *token_program,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds set_evm_authorizer, `sender` signs.
+pub fn set_evm_authorizer(sender: &Pubkey, args: instruction::SetEvmAuthorizer) -> Instruction {
+    let accounts = accounts::SetEvmAuthorizer {
+        sender: *sender,
+        htlc: find_htlc_address(sender, &args.Id).0,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// The EIP-712 digest the evm_authorizer of an HTLC signs for add_lock_sig_evm,
+/// valid until `deadline` on the cluster of `genesis_hash`.
+pub fn add_lock_evm_digest(
+    genesis_hash: [u8; 32],
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> [u8; 32] {
+    lock_sig::eip712::add_lock_digest(
+        &add_lock_sig_domain(genesis_hash),
+        id,
+        hashlock,
+        timelock,
+        deadline,
+    )
+}
+
+/// Builds add_lock_sig_evm with the signature of [`add_lock_evm_digest`] by the
+/// evm_authorizer of the HTLC, `payer` signs.
+pub fn add_lock_sig_evm(
+    payer: &Pubkey,
+    sender: &Pubkey,
+    args: instruction::AddLockSigEvm,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::AddLockSigEvm {
+            payer: *payer,
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds redeem, `user_signing` signs and gets the reward if it is due.
+/// The token accounts of the src_receiver and `user_signing` are created if needed.
+pub fn redeem(
+    user_signing: &Pubkey,
+    sender: &Pubkey,
+    src_receiver: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::Redeem,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::Redeem {
+            user_signing: *user_signing,
+            sender: *sender,
+            src_receiver: *src_receiver,
+            token_contract: *token_contract,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            secret_record: find_secret_record_address(&hash_secret(&args.secret)).0,
+            htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            src_receiver_token_account: associated_token_address(
+                src_receiver,
+                token_contract,
+                token_program,
+            ),
+            reward_token_account: associated_token_address(
+                user_signing,
+                token_contract,
+                token_program,
+            ),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds refund, `user_signing` signs.
+pub fn refund(
+    user_signing: &Pubkey,
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::Refund,
+) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::Refund {
+            user_signing: *user_signing,
+            htlc: find_htlc_address(sender, &args.Id).0,
+            htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+            sender: *sender,
+            token_contract: *token_contract,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            rent: sysvar::rent::ID,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds batch_lock, `sender` pays and signs. The htlc and htlc_token_account
+/// of each entry are passed in the remaining accounts.
+pub fn batch_lock(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::BatchLock,
+) -> Instruction {
+    let remaining = args
+        .entries
+        .iter()
+        .flat_map(|entry| {
+            [
+                AccountMeta::new(find_htlc_address(sender, &entry.Id).0, false),
+                AccountMeta::new(find_htlc_token_account_address(sender, &entry.Id).0, false),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::BatchLock {
+            sender: *sender,
+            token_contract: *token_contract,
+            sender_token_account: associated_token_address(sender, token_contract, token_program),
+            token_program: *token_program,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    let mut ix = build_instruction(ID, accounts, args);
+    ix.accounts.extend(remaining);
+    ix
+}
+
+/// Builds batch_redeem, `user_signing` signs and gets the rewards that are due.
+/// `htlcs` holds the sender and src_receiver of each entry, in order. The token
+/// accounts of the src_receivers must exist, the one of `user_signing` is
+/// created if needed.
+pub fn batch_redeem(
+    user_signing: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    htlcs: &[(Pubkey, Pubkey)],
+    args: instruction::BatchRedeem,
+) -> Instruction {
+    let remaining = args
+        .entries
+        .iter()
+        .zip(htlcs)
+        .flat_map(|((id, secret), (sender, src_receiver))| {
+            let secret_record = find_secret_record_address(&hash_secret(secret)).0;
+            redeem_entry_accounts(id, sender, src_receiver, token_contract, token_program)
+                .into_iter()
+                .chain([AccountMeta::new(secret_record, false)])
+        })
+        .collect::<Vec<_>>();
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::BatchRedeem {
+            user_signing: *user_signing,
+            token_contract: *token_contract,
+            reward_token_account: associated_token_address(
+                user_signing,
+                token_contract,
+                token_program,
+            ),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    let mut ix = build_instruction(ID, accounts, args);
+    ix.accounts.extend(remaining);
+    ix
+}
+
+/// Builds redeem_by_hashlock, `user_signing` signs and gets the rewards that are
+/// due. `htlcs` holds the sender and src_receiver of each HTLC, in order, and the
+/// token accounts of the src_receivers must exist. `proof` is the address of an
+/// HTLC already redeemed with the secret, used when `args.secret` is None.
+pub fn redeem_by_hashlock(
+    user_signing: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    proof: Option<Pubkey>,
+    htlcs: &[(Pubkey, Pubkey)],
+    args: instruction::RedeemByHashlock,
+) -> Instruction {
+    let remaining = args
+        .Ids
+        .iter()
+        .zip(htlcs)
+        .flat_map(|(id, (sender, src_receiver))| {
+            redeem_entry_accounts(id, sender, src_receiver, token_contract, token_program)
+        })
+        .collect::<Vec<_>>();
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::RedeemByHashlock {
+            user_signing: *user_signing,
+            token_contract: *token_contract,
+            secret_record: find_secret_record_address(&args.hashlock).0,
+            proof: proof,
+            reward_token_account: associated_token_address(
+                user_signing,
+                token_contract,
+                token_program,
+            ),
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+    );
+    let mut ix = build_instruction(ID, accounts, args);
+    ix.accounts.extend(remaining);
+    ix
+}
+
+/// The accounts of an entry of batch_redeem or redeem_by_hashlock, batch_redeem
+/// adds the secret_record.
+fn redeem_entry_accounts(
+    id: &[u8; 32],
+    sender: &Pubkey,
+    src_receiver: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+) -> [AccountMeta; 6] {
+    [
+        AccountMeta::new(*sender, false),
+        AccountMeta::new_readonly(*src_receiver, false),
+        AccountMeta::new(find_htlc_address(sender, id).0, false),
+        AccountMeta::new(find_htlc_token_account_address(sender, id).0, false),
+        AccountMeta::new(
+            associated_token_address(sender, token_contract, token_program),
+            false,
+        ),
+        AccountMeta::new(
+            associated_token_address(src_receiver, token_contract, token_program),
+            false,
+        ),
+    ]
+}
+
+/// Builds batch_refund, `user_signing` signs. `senders` holds the sender of each
+/// HTLC, in order.
+pub fn batch_refund(
+    user_signing: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    senders: &[Pubkey],
+    args: instruction::BatchRefund,
+) -> Instruction {
+    let remaining = args
+        .Ids
+        .iter()
+        .zip(senders)
+        .flat_map(|(id, sender)| {
+            [
+                AccountMeta::new(*sender, false),
+                AccountMeta::new(find_htlc_address(sender, id).0, false),
+                AccountMeta::new(find_htlc_token_account_address(sender, id).0, false),
+                AccountMeta::new(
+                    associated_token_address(sender, token_contract, token_program),
+                    false,
+                ),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::BatchRefund {
+            user_signing: *user_signing,
+            token_contract: *token_contract,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            rent: sysvar::rent::ID,
+        }
+    );
+    let mut ix = build_instruction(ID, accounts, args);
+    ix.accounts.extend(remaining);
+    ix
+}
+
+/// Builds reserve_id, `sender` pays and signs.
+pub fn reserve_id(sender: &Pubkey) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::ReserveId {
+            sender: *sender,
+            sender_state: find_sender_state_address(sender).0,
+            system_program: system_program::ID,
+        }
+    );
+    build_instruction(ID, accounts, instruction::ReserveId {})
+}
+
+/// Builds migrate, `sender` pays and signs.
+pub fn migrate(
+    sender: &Pubkey,
+    token_contract: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::Migrate,
+) -> Instruction {
+    let accounts = accounts::Migrate {
+        sender: *sender,
+        legacy_htlc: find_legacy_htlc_address(&args.Id).0,
+        legacy_htlc_token_account: find_legacy_htlc_token_account_address(&args.Id).0,
+        htlc: find_htlc_address(sender, &args.Id).0,
+        htlc_token_account: find_htlc_token_account_address(sender, &args.Id).0,
+        token_contract: *token_contract,
+        token_program: *token_program,
+        system_program: system_program::ID,
+        rent: sysvar::rent::ID,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds close, `sender` signs and gets the rent back.
+pub fn close(sender: &Pubkey, args: instruction::Close) -> Instruction {
+    let accounts = event_cpi_accounts!(
+        ID,
+        accounts::Close {
+            sender: *sender,
+            htlc: find_htlc_address(sender, &args.Id).0,
+        }
+    );
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds getSecret, meant to be simulated to read the secret of a hashlock.
+pub fn get_secret(args: instruction::GetSecret) -> Instruction {
+    let accounts = accounts::GetSecret {
+        secret_record: find_secret_record_address(&args.hashlock).0,
+    };
+    build_instruction(ID, accounts, args)
+}
+
+/// Builds getDetails, meant to be simulated to read the HTLC.
+pub fn get_details(sender: &Pubkey, args: instruction::GetDetails) -> Instruction {
+    let accounts = accounts::GetDetails {
+        sender: *sender,
+        htlc: find_htlc_address(sender, &args.Id).0,
+    };
+    build_instruction(ID, accounts, args)
+}
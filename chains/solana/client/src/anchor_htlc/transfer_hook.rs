@@ -0,0 +1,71 @@
+//! Off-chain helper to resolve the transfer hook accounts of a Token-2022 mint.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    offchain::{add_extra_account_metas, AccountDataResult, AccountFetchError},
+};
+use std::future::Future;
+
+/// Resolves the accounts to pass as `remaining_accounts` to commit, lock,
+/// lock_reward, redeem and refund when `mint` has a transfer hook.
+///
+/// `transfers` lists the (source, destination, amount) transfers the instruction
+/// makes with `authority`: the sender for deposits, the HTLC PDA for payouts.
+/// redeem with a reward makes two transfers, to the src_receiver and to the reward
+/// recipient. The extra accounts of all transfers are merged, and a mint without a
+/// transfer hook resolves to no accounts.
+///
+/// `fetch_account_data_fn` returns the data of an account, so any RPC client can be
+/// used, e.g.
+///
+/// ```rust,ignore
+/// let accounts = resolve_transfer_hook_accounts(&mint, &htlc, &[(htlc_token_account, receiver_ata, amount)], |address| {
+///     client.get_account(&address).map_ok(|account| Some(account.data))
+/// })
+/// .await?;
+/// ```
+pub async fn resolve_transfer_hook_accounts<F, Fut>(
+    mint: &Pubkey,
+    authority: &Pubkey,
+    transfers: &[(Pubkey, Pubkey, u64)],
+    fetch_account_data_fn: F,
+) -> Result<Vec<AccountMeta>, AccountFetchError>
+where
+    F: Fn(Pubkey) -> Fut,
+    Fut: Future<Output = AccountDataResult>,
+{
+    let mut accounts: Vec<AccountMeta> = Vec::new();
+    for (source, destination, amount) in transfers {
+        let mut instruction = spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::ID,
+            source,
+            mint,
+            destination,
+            authority,
+            &[],
+            *amount,
+            0,
+        )?;
+        let base_len = instruction.accounts.len();
+        add_extra_account_metas(
+            &mut instruction,
+            source,
+            mint,
+            destination,
+            authority,
+            *amount,
+            &fetch_account_data_fn,
+        )
+        .await?;
+
+        for meta in instruction.accounts.drain(base_len..) {
+            match accounts.iter_mut().find(|a| a.pubkey == meta.pubkey) {
+                Some(account) => account.is_writable |= meta.is_writable,
+                None => accounts.push(meta),
+            }
+        }
+    }
+    Ok(accounts)
+}
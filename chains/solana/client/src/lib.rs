@@ -0,0 +1,71 @@
+//! Rust client for the Train Solana HTLC programs.
+//!
+//! * [`native_htlc`] - HTLCs holding SOL, program `sol`.
+//! * [`anchor_htlc`] - HTLCs holding SPL Token and Token-2022 tokens, program `native`.
+//!
+//! Each module has typed instruction builders for every instruction of its
+//! program, the PDA and token account derivation, decoding of the program
+//! accounts, and the mapping of error codes back to `HTLCError`.
+//! [`lock_sig`] builds and signs the add_lock_sig message.
+//!
+//! Build with the `event-cpi` feature when the programs are deployed with it, the
+//! builders then add the event authority and program accounts.
+
+pub use anchor_lang::solana_program::instruction::{AccountMeta, Instruction, InstructionError};
+pub use anchor_lang::solana_program::pubkey::Pubkey;
+pub use solana_signer::{Signer, SignerError};
+pub use train_lock_sig as lock_sig;
+
+/// Offset of custom program errors, `HTLCError` variants are numbered from it.
+pub const ERROR_CODE_OFFSET: u32 = anchor_lang::error::ERROR_CODE_OFFSET;
+
+/// Returns the custom error code of a failed instruction, if the program returned one.
+pub fn custom_error_code(error: &InstructionError) -> Option<u32> {
+    match error {
+        InstructionError::Custom(code) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Returns the hashlock a secret opens, sha256(secret). The SecretRecord of
+/// both programs is derived from it.
+pub fn hash_secret(secret: &[u8; 32]) -> [u8; 32] {
+    anchor_lang::solana_program::hash::hash(secret).to_bytes()
+}
+
+/// Seeds of the event authority PDA used by `emit_cpi!`.
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+/// Derives the event authority PDA of `program_id`.
+pub fn find_event_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], program_id)
+}
+
+/// Builds the accounts of an instruction, adding the event authority and program
+/// accounts when the programs are built with `event-cpi`.
+macro_rules! event_cpi_accounts {
+    ($program_id:expr, $($accounts:ident)::+ { $($field:ident: $value:expr),* $(,)? }) => {
+        $($accounts)::+ {
+            $($field: $value,)*
+            #[cfg(feature = "event-cpi")]
+            event_authority: $crate::find_event_authority_address(&$program_id).0,
+            #[cfg(feature = "event-cpi")]
+            program: $program_id,
+        }
+    };
+}
+
+pub mod anchor_htlc;
+pub mod native_htlc;
+
+fn build_instruction(
+    program_id: Pubkey,
+    accounts: impl anchor_lang::ToAccountMetas,
+    args: impl anchor_lang::InstructionData,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: accounts.to_account_metas(None),
+        data: args.data(),
+    }
+}
@@ -0,0 +1,306 @@
+use anchor_lang::{AccountSerialize, Discriminator};
+use train_solana_client::{anchor_htlc, hash_secret, native_htlc, InstructionError, Pubkey};
+
+const ID: [u8; 32] = [7; 32];
+
+#[test]
+fn derives_the_program_pdas() {
+    let sender = Pubkey::new_unique();
+    let (htlc, bump) = native_htlc::find_htlc_address(&sender, &ID);
+    assert_eq!(
+        Pubkey::create_program_address(
+            &[native_htlc::HTLC_SEED, sender.as_ref(), &ID, &[bump]],
+            &native_htlc::ID
+        )
+        .unwrap(),
+        htlc
+    );
+
+    let (token_account, bump) = anchor_htlc::find_htlc_token_account_address(&sender, &ID);
+    assert_eq!(
+        Pubkey::create_program_address(
+            &[
+                anchor_htlc::HTLC_TOKEN_ACCOUNT_SEED,
+                sender.as_ref(),
+                &ID,
+                &[bump]
+            ],
+            &anchor_htlc::ID
+        )
+        .unwrap(),
+        token_account
+    );
+    assert_ne!(
+        anchor_htlc::find_legacy_htlc_token_account_address(&ID).0,
+        token_account
+    );
+
+    let hashlock = hash_secret(&[1; 32]);
+    let (record, bump) = anchor_htlc::find_secret_record_address(&hashlock);
+    assert_eq!(
+        Pubkey::create_program_address(
+            &[anchor_htlc::SECRET_RECORD_SEED, &hashlock, &[bump]],
+            &anchor_htlc::ID
+        )
+        .unwrap(),
+        record
+    );
+}
+
+#[test]
+fn builds_native_redeem() {
+    let user_signing = Pubkey::new_unique();
+    let sender = Pubkey::new_unique();
+    let src_receiver = Pubkey::new_unique();
+    let ix = native_htlc::redeem(
+        &user_signing,
+        &sender,
+        &src_receiver,
+        native_htlc::instruction::Redeem {
+            Id: ID,
+            secret: [1; 32],
+        },
+    );
+
+    assert_eq!(ix.program_id, native_htlc::ID);
+    assert_eq!(ix.accounts[0].pubkey, user_signing);
+    assert!(ix.accounts[0].is_signer);
+    assert_eq!(ix.accounts[1].pubkey, sender);
+    assert_eq!(ix.accounts[2].pubkey, src_receiver);
+    assert_eq!(
+        ix.accounts[3].pubkey,
+        native_htlc::find_htlc_address(&sender, &ID).0
+    );
+    assert_eq!(
+        &ix.data[..8],
+        <native_htlc::instruction::Redeem as Discriminator>::DISCRIMINATOR
+    );
+    assert_eq!(&ix.data[8..40], &ID);
+}
+
+#[test]
+fn builds_add_lock_for_the_sender_or_its_locker() {
+    let sender = Pubkey::new_unique();
+    let locker = Pubkey::new_unique();
+    let args = || native_htlc::instruction::AddLock {
+        Id: ID,
+        hashlock: [1; 32],
+        timelock: 1_700_000_900,
+    };
+
+    let ix = native_htlc::add_lock_as(&locker, &sender, args());
+    assert_eq!(ix.accounts[0].pubkey, locker);
+    assert!(ix.accounts[0].is_signer);
+    assert_eq!(ix.accounts[1].pubkey, sender);
+    assert!(!ix.accounts[1].is_signer);
+    assert_eq!(
+        ix.accounts[2].pubkey,
+        native_htlc::find_htlc_address(&sender, &ID).0
+    );
+
+    let ix = native_htlc::add_lock(&sender, args());
+    assert_eq!(ix.accounts[0].pubkey, sender);
+    assert_eq!(ix.accounts[1].pubkey, sender);
+}
+
+#[test]
+fn builds_add_lock_through_a_session() {
+    let sender = Pubkey::new_unique();
+    let delegate = Pubkey::new_unique();
+    let args = || native_htlc::instruction::AddLock {
+        Id: ID,
+        hashlock: [1; 32],
+        timelock: 1_700_000_900,
+    };
+
+    let ix = native_htlc::add_lock_by_session(&delegate, &sender, args());
+    assert_eq!(ix.accounts[0].pubkey, delegate);
+    assert!(ix.accounts[0].is_signer);
+    assert_eq!(
+        ix.accounts[3].pubkey,
+        native_htlc::find_session_address(&sender, &delegate).0
+    );
+    assert!(ix.accounts[3].is_writable);
+
+    // without a session the optional account is the program id
+    let ix = native_htlc::add_lock(&sender, args());
+    assert_eq!(ix.accounts[3].pubkey, native_htlc::ID);
+    assert!(!ix.accounts[3].is_writable);
+}
+
+#[test]
+fn builds_token_redeem_with_associated_token_accounts() {
+    let user_signing = Pubkey::new_unique();
+    let sender = Pubkey::new_unique();
+    let src_receiver = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let token_program = anchor_spl::token_2022::ID;
+    let (_, htlc_bump) = anchor_htlc::find_htlc_address(&sender, &ID);
+    let ix = anchor_htlc::redeem(
+        &user_signing,
+        &sender,
+        &src_receiver,
+        &mint,
+        &token_program,
+        anchor_htlc::instruction::Redeem {
+            Id: ID,
+            secret: [1; 32],
+            htlc_bump,
+        },
+    );
+
+    let keys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+    assert!(keys.contains(&anchor_htlc::associated_token_address(
+        &src_receiver,
+        &mint,
+        &token_program
+    )));
+    assert!(keys.contains(&anchor_htlc::associated_token_address(
+        &user_signing,
+        &mint,
+        &token_program
+    )));
+    assert!(keys.contains(&anchor_htlc::find_htlc_token_account_address(&sender, &ID).0));
+    assert!(keys.contains(&token_program));
+}
+
+#[test]
+fn builds_batch_redeem_with_the_accounts_of_each_entry() {
+    let user_signing = Pubkey::new_unique();
+    let htlcs = [
+        (Pubkey::new_unique(), Pubkey::new_unique()),
+        (Pubkey::new_unique(), Pubkey::new_unique()),
+    ];
+    let ix = native_htlc::batch_redeem(
+        &user_signing,
+        &htlcs,
+        native_htlc::instruction::BatchRedeem {
+            entries: vec![(ID, [1; 32]), ([8; 32], [2; 32])],
+            best_effort: true,
+        },
+    );
+
+    let remaining = &ix.accounts[ix.accounts.len() - 8..];
+    for (chunk, ((sender, src_receiver), (id, secret))) in remaining
+        .chunks(4)
+        .zip(htlcs.iter().zip([(ID, [1; 32]), ([8; 32], [2; 32])]))
+    {
+        assert_eq!(chunk[0].pubkey, *sender);
+        assert_eq!(chunk[1].pubkey, *src_receiver);
+        assert_eq!(
+            chunk[2].pubkey,
+            native_htlc::find_htlc_address(sender, &id).0
+        );
+        assert_eq!(
+            chunk[3].pubkey,
+            native_htlc::find_secret_record_address(&hash_secret(&secret)).0
+        );
+        assert!(chunk.iter().all(|meta| meta.is_writable && !meta.is_signer));
+    }
+
+    let mint = Pubkey::new_unique();
+    let token_program = anchor_spl::token::ID;
+    let senders = [htlcs[0].0, htlcs[1].0];
+    let ix = anchor_htlc::batch_refund(
+        &user_signing,
+        &mint,
+        &token_program,
+        &senders,
+        anchor_htlc::instruction::BatchRefund {
+            Ids: vec![ID, [8; 32]],
+            best_effort: false,
+        },
+    );
+    let remaining = &ix.accounts[ix.accounts.len() - 8..];
+    assert_eq!(remaining[4].pubkey, senders[1]);
+    assert_eq!(
+        remaining[6].pubkey,
+        anchor_htlc::find_htlc_token_account_address(&senders[1], &[8; 32]).0
+    );
+    assert_eq!(
+        remaining[7].pubkey,
+        anchor_htlc::associated_token_address(&senders[1], &mint, &token_program)
+    );
+}
+
+#[test]
+fn builds_redeem_by_hashlock_with_an_optional_proof() {
+    let user_signing = Pubkey::new_unique();
+    let htlcs = [(Pubkey::new_unique(), Pubkey::new_unique())];
+    let args = || native_htlc::instruction::RedeemByHashlock {
+        hashlock: [3; 32],
+        secret: None,
+        Ids: vec![ID],
+        best_effort: false,
+    };
+
+    let proof = native_htlc::find_htlc_address(&Pubkey::new_unique(), &[9; 32]).0;
+    let ix = native_htlc::redeem_by_hashlock(&user_signing, Some(proof), &htlcs, args());
+    assert_eq!(
+        ix.accounts[1].pubkey,
+        native_htlc::find_secret_record_address(&[3; 32]).0
+    );
+    assert_eq!(ix.accounts[2].pubkey, proof);
+    assert!(!ix.accounts[2].is_writable);
+    assert_eq!(
+        ix.accounts[ix.accounts.len() - 1].pubkey,
+        native_htlc::find_htlc_address(&htlcs[0].0, &ID).0
+    );
+
+    // Anchor takes the program id in place of a missing optional account.
+    let ix = native_htlc::redeem_by_hashlock(&user_signing, None, &htlcs, args());
+    assert_eq!(ix.accounts[2].pubkey, native_htlc::ID);
+}
+
+#[test]
+fn decodes_htlc_accounts() {
+    let sender = Pubkey::new_unique();
+    let htlc = native_htlc::HTLC {
+        sender,
+        amount: 1_000,
+        timelock: 1_800,
+        status: native_htlc::HtlcStatus::Locked,
+        hop_chains: vec!["ETHEREUM".to_string()],
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    htlc.try_serialize(&mut data).unwrap();
+    let decoded = native_htlc::decode_htlc(&data).unwrap();
+    assert_eq!(decoded.sender, sender);
+    assert_eq!(decoded.amount, 1_000);
+    assert_eq!(decoded.status, native_htlc::HtlcStatus::Locked);
+    assert_eq!(decoded.hop_chains, vec!["ETHEREUM".to_string()]);
+    assert!(anchor_htlc::decode_htlc(&data).is_err());
+
+    let htlc = anchor_htlc::HTLC {
+        sender,
+        token_program: anchor_spl::token::ID,
+        fee_payer: anchor_htlc::FeePayer::Sender,
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    htlc.try_serialize(&mut data).unwrap();
+    let decoded = anchor_htlc::decode_htlc(&data).unwrap();
+    assert_eq!(decoded.token_program, anchor_spl::token::ID);
+    assert_eq!(decoded.fee_payer, anchor_htlc::FeePayer::Sender);
+}
+
+#[test]
+fn maps_error_codes() {
+    let code: u32 = native_htlc::HTLCError::HashlockNoMatch.into();
+    assert!(matches!(
+        native_htlc::htlc_error(code),
+        Some(native_htlc::HTLCError::HashlockNoMatch)
+    ));
+    let code: u32 = anchor_htlc::HTLCError::WrongTokenProgram.into();
+    assert!(matches!(
+        anchor_htlc::htlc_error_from_instruction_error(&InstructionError::Custom(code)),
+        Some(anchor_htlc::HTLCError::WrongTokenProgram)
+    ));
+    assert!(native_htlc::htlc_error(0).is_none());
+    assert!(native_htlc::htlc_error(u32::MAX).is_none());
+    assert!(
+        native_htlc::htlc_error_from_instruction_error(&InstructionError::InvalidArgument)
+            .is_none()
+    );
+}
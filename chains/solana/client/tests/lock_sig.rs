@@ -0,0 +1,211 @@
+//! The add_lock_sig messages signed off-chain pass the checks of both programs.
+
+use anchor_lang::solana_program::ed25519_program;
+use solana_signature::Signature;
+use train_solana_client::{
+    anchor_htlc, lock_sig, native_htlc, Instruction, Pubkey, Signer, SignerError,
+};
+
+const ID: [u8; 32] = [0x11; 32];
+const HASHLOCK: &str = "9f72ea0cf49536e3c66c787f705186df9a4378083753ae9536d65b3ad7fcddc4";
+const TIMELOCK: u64 = 1_700_000_900;
+const DEADLINE: u64 = 1_700_000_600;
+/// Golden vectors of lock_sig for the native program on devnet, signed with the
+/// ed25519 key whose seed is 0x00..0x1f.
+const PUBKEY: &str = "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8";
+const SIGNATURE: &str = "3ed4eaaeced5be4c5c921f96b56b48266fa12632a983954be8b7c423f824fead5bd0e523bdfaec12dbe681a42d94724efd2605d386dbb27d01cc089590fc0c0b";
+const LEGACY_SIGNATURE: &str = "adca1261008e57730b60e3ce4cfd716d29a1c23a17f27c1a523eb99dd36a116b63e94511641fd3b7d8498513c69d43166e378b00d6e2a1263539c3028678ba00";
+
+/// Signer returning the golden signatures, for the golden messages only.
+struct GoldenSigner;
+
+impl Signer for GoldenSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(Pubkey::new_from_array(bytes32(PUBKEY)))
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        if message == native_message(TIMELOCK) {
+            Ok(Signature::from(signature()))
+        } else {
+            assert_eq!(message, legacy_message());
+            Ok(Signature::from(legacy_signature()))
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+fn bytes32(hex_str: &str) -> [u8; 32] {
+    hex::decode(hex_str).unwrap().try_into().unwrap()
+}
+
+fn signature() -> [u8; 64] {
+    hex::decode(SIGNATURE).unwrap().try_into().unwrap()
+}
+
+fn legacy_signature() -> [u8; 64] {
+    hex::decode(LEGACY_SIGNATURE).unwrap().try_into().unwrap()
+}
+
+fn native_message(timelock: u64) -> Vec<u8> {
+    lock_sig::add_lock_message(
+        &native_htlc::add_lock_sig_domain(lock_sig::DEVNET_GENESIS_HASH),
+        &ID,
+        &bytes32(HASHLOCK),
+        timelock,
+        DEADLINE,
+        &bytes32(PUBKEY),
+    )
+}
+
+fn legacy_message() -> Vec<u8> {
+    lock_sig::legacy::add_lock_message(&ID, &bytes32(HASHLOCK), TIMELOCK, &bytes32(PUBKEY))
+}
+
+fn signed_native_lock() -> [Instruction; 2] {
+    native_htlc::signed_add_lock_sig(
+        &Pubkey::new_unique(),
+        &GoldenSigner,
+        lock_sig::DEVNET_GENESIS_HASH,
+        ID,
+        bytes32(HASHLOCK),
+        TIMELOCK,
+        DEADLINE,
+    )
+    .unwrap()
+}
+
+#[test]
+fn native_htlc_accepts_the_signed_lock() {
+    let [ed25519_ix, add_lock_sig_ix] = signed_native_lock();
+    assert_eq!(ed25519_ix.program_id, ed25519_program::ID);
+    assert_eq!(add_lock_sig_ix.accounts[1].pubkey, GoldenSigner.pubkey());
+
+    let message = native_message(TIMELOCK);
+    assert_eq!(ed25519_ix.data.len(), 16 + 64 + 32 + message.len());
+    native_htlc::check_ed25519_data(&ed25519_ix.data, &bytes32(PUBKEY), &message, &signature())
+        .unwrap();
+
+    let other_message = native_message(TIMELOCK + 1);
+    assert!(native_htlc::check_ed25519_data(
+        &ed25519_ix.data,
+        &bytes32(PUBKEY),
+        &other_message,
+        &signature()
+    )
+    .is_err());
+}
+
+#[test]
+fn signatures_are_separated_by_program_and_cluster() {
+    let message = |domain| {
+        lock_sig::add_lock_message(
+            &domain,
+            &ID,
+            &bytes32(HASHLOCK),
+            TIMELOCK,
+            DEADLINE,
+            &bytes32(PUBKEY),
+        )
+    };
+    let devnet = lock_sig::DEVNET_GENESIS_HASH;
+    let golden = message(native_htlc::add_lock_sig_domain(devnet));
+    assert_ne!(message(anchor_htlc::add_lock_sig_domain(devnet)), golden);
+    assert_ne!(
+        message(native_htlc::add_lock_sig_domain(
+            lock_sig::MAINNET_BETA_GENESIS_HASH
+        )),
+        golden
+    );
+}
+
+#[test]
+fn both_programs_accept_the_legacy_lock() {
+    let payer = Pubkey::new_unique();
+    let [ed25519_ix, add_lock_sig_ix] = native_htlc::signed_add_lock_sig_legacy(
+        &payer,
+        &GoldenSigner,
+        ID,
+        bytes32(HASHLOCK),
+        TIMELOCK,
+    )
+    .unwrap();
+    assert_eq!(add_lock_sig_ix.program_id, native_htlc::ID);
+    native_htlc::check_ed25519_data(
+        &ed25519_ix.data,
+        &bytes32(PUBKEY),
+        &legacy_message(),
+        &legacy_signature(),
+    )
+    .unwrap();
+
+    let [ed25519_ix, add_lock_sig_ix] = anchor_htlc::signed_add_lock_sig_legacy(
+        &payer,
+        &GoldenSigner,
+        ID,
+        bytes32(HASHLOCK),
+        TIMELOCK,
+    )
+    .unwrap();
+    assert_eq!(add_lock_sig_ix.program_id, anchor_htlc::ID);
+    anchor_htlc::check_ed25519_data(
+        &ed25519_ix.data,
+        &bytes32(PUBKEY),
+        &legacy_message(),
+        &legacy_signature(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn messages_longer_than_u16_are_rejected_without_panicking() {
+    let data = [0; 16 + 32 + 64];
+    let message = vec![0; u16::MAX as usize + 1];
+    let err = native_htlc::check_ed25519_data(&data, &[0; 32], &message, &[0; 64]).unwrap_err();
+    assert_eq!(err, native_htlc::HTLCError::SigVerificationFailed.into());
+    let err = anchor_htlc::check_ed25519_data(&data, &[0; 32], &message, &[0; 64]).unwrap_err();
+    assert_eq!(err, anchor_htlc::HTLCError::SigVerificationFailed.into());
+}
+
+#[test]
+fn truncated_data_is_rejected_without_panicking() {
+    let [ed25519_ix, _] = signed_native_lock();
+    let message = native_message(TIMELOCK);
+    for len in [0, 1, 2, 15, 16, 48, 112, ed25519_ix.data.len() - 1] {
+        let data = &ed25519_ix.data[..len];
+        let err = native_htlc::check_ed25519_data(data, &bytes32(PUBKEY), &message, &signature())
+            .unwrap_err();
+        assert_eq!(err, native_htlc::HTLCError::SigVerificationFailed.into());
+        let err = anchor_htlc::check_ed25519_data(data, &bytes32(PUBKEY), &message, &signature())
+            .unwrap_err();
+        assert_eq!(err, anchor_htlc::HTLCError::SigVerificationFailed.into());
+    }
+}
+
+#[test]
+fn evm_digests_are_separated_by_program() {
+    // Golden digests of lock_sig's eip712 tests, on devnet.
+    assert_eq!(
+        hex::encode(native_htlc::add_lock_evm_digest(
+            lock_sig::DEVNET_GENESIS_HASH,
+            &ID,
+            &bytes32(HASHLOCK),
+            TIMELOCK,
+            DEADLINE
+        )),
+        "44ba7a5e52de7c8bbae2395b272e844665c1452de2520879ae162c55c4608dcc"
+    );
+    assert_eq!(
+        hex::encode(anchor_htlc::add_lock_evm_digest(
+            lock_sig::DEVNET_GENESIS_HASH,
+            &ID,
+            &bytes32(HASHLOCK),
+            TIMELOCK,
+            DEADLINE
+        )),
+        "3996c61672b1b8d88e204404bea74ddff2766ee3b98b898ef933e898246854fd"
+    );
+}
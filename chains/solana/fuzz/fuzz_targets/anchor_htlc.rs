@@ -0,0 +1,8 @@
+//! Arbitrary sequences of anchor_htlc instructions, see `train_fuzz::token`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use train_fuzz::{token, Step};
+
+fuzz_target!(|steps: Vec<Step<token::Action>>| token::run(steps));
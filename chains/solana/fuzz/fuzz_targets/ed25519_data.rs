@@ -0,0 +1,22 @@
+//! check_ed25519_data of both programs on arbitrary Ed25519Program instruction
+//! data. It must return an error, never panic, on malformed data.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use train_solana_client::{anchor_htlc, native_htlc};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    data: Vec<u8>,
+    pubkey: [u8; 32],
+    msg: Vec<u8>,
+    sig: ([u8; 32], [u8; 32]),
+}
+
+fuzz_target!(|input: Input| {
+    let sig = [input.sig.0, input.sig.1].concat();
+    let _ = native_htlc::check_ed25519_data(&input.data, &input.pubkey, &input.msg, &sig);
+    let _ = anchor_htlc::check_ed25519_data(&input.data, &input.pubkey, &input.msg, &sig);
+});
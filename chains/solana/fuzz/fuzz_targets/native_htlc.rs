@@ -0,0 +1,8 @@
+//! Arbitrary sequences of native_htlc instructions, see `train_fuzz::native`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use train_fuzz::{native, Step};
+
+fuzz_target!(|steps: Vec<Step<native::Action>>| native::run(steps));
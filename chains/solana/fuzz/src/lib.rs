@@ -0,0 +1,674 @@
+//! Fuzzing harness of the native_htlc and anchor_htlc programs over an
+//! in-process SVM (LiteSVM).
+//!
+//! A fuzz input is a sequence of [`Step`]s: an instruction built from arbitrary
+//! arguments and users, then [`Mutation`]s substituting, swapping or removing
+//! its accounts. After every transaction [`World::execute`] asserts that
+//!
+//! - no program panics,
+//! - no lamports or tokens are created, and none leave the tracked accounts,
+//! - a user only gains funds as the sender, src_receiver or signer of an HTLC
+//!   settled or closed in that transaction, or as the sender of a session
+//!   revoked in it,
+//! - a settled HTLC never changes again, so it settles at most once.
+//!
+//! The programs run natively as builtins of the SVM, as in the SVM tests, so a
+//! panic in a program crashes the fuzzer and no SBF build is needed.
+
+pub mod native;
+pub mod token;
+
+use anchor_lang::prelude::{AccountInfo, Clock};
+use anchor_lang::solana_program::{entrypoint::ProgramResult, program_error::ProgramError};
+use arbitrary::Arbitrary;
+use litesvm::LiteSVM;
+use sha2::{Digest, Sha256};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_program_runtime::invoke_context::BuiltinFunctionWithContext;
+use solana_program_test::{processor, tokio, ProgramTest};
+use solana_sdk_ids::native_loader;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use std::panic::AssertUnwindSafe;
+use std::sync::Once;
+use train_lock_sig as lock_sig;
+use train_solana_client::{anchor_htlc, native_htlc, Instruction, Pubkey};
+
+/// Users acting as senders, receivers and third parties.
+pub const USERS: usize = 3;
+/// HTLC Ids and secrets in play, few so that the steps meet on the same HTLCs.
+pub const IDS: u8 = 3;
+/// Steps run per input.
+pub const MAX_STEPS: usize = 32;
+/// Entries run per batch instruction.
+pub const MAX_BATCH: usize = 4;
+/// Unix time the clock starts at.
+pub const START: i64 = 1_700_000_000;
+/// Genesis hash the programs bind add_lock_sig to when built natively
+/// without a cluster feature, as for fuzzing.
+pub const GENESIS_HASH: [u8; 32] = sol::GENESIS_HASH;
+const USER_LAMPORTS: u64 = 100_000_000_000;
+
+/// One of the USERS users.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct User(u8);
+
+impl User {
+    pub fn index(self) -> usize {
+        self.0 as usize % USERS
+    }
+}
+
+/// One of the IDS Ids.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct Id(u8);
+
+impl Id {
+    pub fn bytes(self) -> [u8; 32] {
+        [self.0 % IDS; 32]
+    }
+}
+
+/// One of the IDS secrets, not tied to an Id.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct Secret(u8);
+
+impl Secret {
+    pub fn bytes(self) -> [u8; 32] {
+        [0x80 + self.0 % IDS; 32]
+    }
+
+    pub fn hashlock(self) -> [u8; 32] {
+        Sha256::digest(self.bytes()).into()
+    }
+}
+
+/// A timelock relative to the clock, or any u64.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum Timelock {
+    Relative(i32),
+    Absolute(u64),
+}
+
+impl Timelock {
+    pub fn resolve(self, now: i64) -> u64 {
+        match self {
+            Timelock::Relative(offset) => (now + offset as i64).max(0) as u64,
+            Timelock::Absolute(timelock) => timelock,
+        }
+    }
+}
+
+/// An amount the users can afford, or any u64.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum Amount {
+    Small(u16),
+    Any(u64),
+}
+
+impl Amount {
+    pub fn value(self) -> u64 {
+        match self {
+            Amount::Small(amount) => amount as u64 * 1_000,
+            Amount::Any(amount) => amount,
+        }
+    }
+}
+
+/// Length of a string argument, up to one past the longest allowed.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct Len(u8);
+
+impl Len {
+    pub fn string(self, max: usize) -> String {
+        "x".repeat(self.0 as usize % (max + 2))
+    }
+}
+
+/// How the Ed25519Program instruction in front of add_lock_sig is built.
+#[derive(Arbitrary, Debug, Clone)]
+pub enum Precompile {
+    /// Signed by the given user over the add_lock_sig message.
+    Signed,
+    /// Signed, then one byte of the instruction data flipped.
+    Corrupted { index: u16, mask: u8 },
+    /// Arbitrary instruction data.
+    Raw(Vec<u8>),
+    /// No Ed25519Program instruction.
+    Missing,
+}
+
+impl Precompile {
+    /// The instructions to put in front of add_lock_sig, and the signature it carries.
+    pub fn build(&self, signer: &Keypair, message: &[u8]) -> (Vec<Instruction>, [u8; 64]) {
+        let signature: [u8; 64] = signer.sign_message(message).into();
+        let mut instruction =
+            lock_sig::new_ed25519_instruction(&signer.pubkey(), &signature, message);
+        match self {
+            Precompile::Signed => {}
+            Precompile::Corrupted { index, mask } => {
+                let index = *index as usize % instruction.data.len();
+                instruction.data[index] ^= mask;
+            }
+            Precompile::Raw(data) => instruction.data = data.clone(),
+            Precompile::Missing => return (vec![], signature),
+        }
+        (vec![instruction], signature)
+    }
+}
+
+/// The add_lock_sig message `signer` signs in `domain`, valid until `deadline`,
+/// or the legacy message of add_lock_sig_legacy without a deadline.
+pub fn add_lock_message(
+    domain: lock_sig::Domain,
+    signer: &Keypair,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: Option<u64>,
+) -> Vec<u8> {
+    let signer = signer.pubkey().to_bytes();
+    match deadline {
+        Some(deadline) => {
+            lock_sig::add_lock_message(&domain, id, hashlock, timelock, deadline, &signer)
+        }
+        None => lock_sig::legacy::add_lock_message(id, hashlock, timelock, &signer),
+    }
+}
+
+/// How the secp256k1 signature of add_lock_sig_evm is built.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum EvmSignature {
+    /// Signed by the Ethereum key of the given user over the add_lock_sig_evm arguments.
+    Signed,
+    /// Signed, then one byte of r || s || v flipped.
+    Corrupted { index: u8, mask: u8 },
+    /// Arbitrary r, s and v.
+    Raw([u8; 32], [u8; 32], u8),
+}
+
+impl EvmSignature {
+    /// The (r, s, v) of `digest` to pass to add_lock_sig_evm.
+    pub fn build(self, signer: User, digest: &[u8; 32]) -> ([u8; 32], [u8; 32], u8) {
+        let (signature, recovery_id) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(digest), &evm_key(signer));
+        let mut bytes = [0; 65];
+        bytes[..64].copy_from_slice(&signature.serialize());
+        bytes[64] = recovery_id.serialize() + 27;
+        match self {
+            EvmSignature::Signed => {}
+            EvmSignature::Corrupted { index, mask } => bytes[index as usize % 65] ^= mask,
+            EvmSignature::Raw(r, s, v) => return (r, s, v),
+        }
+        (
+            bytes[..32].try_into().unwrap(),
+            bytes[32..64].try_into().unwrap(),
+            bytes[64],
+        )
+    }
+}
+
+/// The Ethereum key of a user.
+pub fn evm_key(user: User) -> libsecp256k1::SecretKey {
+    libsecp256k1::SecretKey::parse(&[user.index() as u8 + 1; 32]).expect("valid secret key")
+}
+
+/// The Ethereum address of a user, or zero for None.
+pub fn evm_address(user: Option<User>) -> [u8; 20] {
+    user.map_or([0; 20], |user| {
+        let pubkey = libsecp256k1::PublicKey::from_secret_key(&evm_key(user)).serialize();
+        lock_sig::eip712::evm_address(pubkey[1..].try_into().unwrap())
+    })
+}
+
+/// A change to the account list of the program instruction.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum Mutation {
+    /// Replaces an account with one of the known accounts.
+    Substitute {
+        index: u8,
+        account: u16,
+    },
+    Swap {
+        a: u8,
+        b: u8,
+    },
+    Remove {
+        index: u8,
+    },
+    ToggleWritable {
+        index: u8,
+    },
+}
+
+/// An action on a program followed by mutations of its accounts.
+#[derive(Arbitrary, Debug)]
+pub struct Step<A> {
+    pub action: A,
+    pub mutations: Vec<Mutation>,
+}
+
+/// Settlement state of an HTLC, common to both programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    Redeemed,
+    Refunded,
+}
+
+/// The fields of an HTLC the invariants look at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HtlcState {
+    pub status: Status,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub amount: u64,
+    pub reward: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Snapshot {
+    lamports: Vec<u64>,
+    tokens: Vec<u64>,
+    supplies: Vec<u64>,
+    htlcs: Vec<Option<HtlcState>>,
+    sessions: Vec<bool>,
+}
+
+pub struct World {
+    pub svm: LiteSVM,
+    pub users: Vec<Keypair>,
+    /// Pays the transaction fees, so they stay out of the tracked lamports.
+    pub fee_payer: Keypair,
+    /// Accounts whose lamports are tracked.
+    accounts: Vec<Pubkey>,
+    /// Accounts a Substitute mutation picks from: the tracked accounts, the
+    /// programs and the sysvars.
+    known: Vec<Pubkey>,
+    htlcs: Vec<Pubkey>,
+    /// Session accounts, with the user who created them.
+    sessions: Vec<(Pubkey, usize)>,
+    /// Token accounts, with the user owning them if any.
+    token_accounts: Vec<(Pubkey, Option<usize>)>,
+    pub mints: Vec<Pubkey>,
+    decode: fn(&[u8]) -> Option<HtlcState>,
+}
+
+/// Adds both programs as builtins running their entrypoints natively, their
+/// syscalls going through the stubs of solana-program-test.
+fn add_programs(svm: &mut LiteSVM) {
+    static SYSCALL_STUBS: Once = Once::new();
+    SYSCALL_STUBS.call_once(|| {
+        // The stubs are installed when a ProgramTest bank is set up, which
+        // also turns on its debug logs.
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(ProgramTest::default().start());
+        solana_logger::setup_with_default("off");
+        // The CPI stub panics on a CPI the runtime fails with
+        // PrivilegeEscalation, e.g. once a mutation made an account the
+        // program creates read-only. The entrypoints turn it into an error.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !is_privilege_escalation(info.payload_as_str()) {
+                hook(info)
+            }
+        }));
+    });
+    let native_htlc: Option<BuiltinFunctionWithContext> = processor!(native_htlc_entry);
+    let anchor_htlc: Option<BuiltinFunctionWithContext> = processor!(anchor_htlc_entry);
+    for (program_id, entrypoint) in [
+        (native_htlc::ID, native_htlc),
+        (anchor_htlc::ID, anchor_htlc),
+    ] {
+        svm.add_builtin(program_id, entrypoint.unwrap());
+        // Owned by the native loader, the runtime calls the builtin itself.
+        svm.set_account(
+            program_id,
+            Account {
+                lamports: 1,
+                data: vec![],
+                owner: native_loader::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    }
+}
+
+fn is_privilege_escalation(panic: Option<&str>) -> bool {
+    panic.is_some_and(|message| message.ends_with(": PrivilegeEscalation"))
+}
+
+/// Runs an entrypoint, failing the instruction where the CPI stub panics with
+/// PrivilegeEscalation. Any other panic has already aborted the fuzzer.
+fn run_entry(entry: impl FnOnce() -> ProgramResult) -> ProgramResult {
+    std::panic::catch_unwind(AssertUnwindSafe(entry)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<String>().map(String::as_str);
+        if !is_privilege_escalation(message) {
+            std::panic::resume_unwind(payload)
+        }
+        // InstructionError::PrivilegeEscalation has no ProgramError.
+        Err(ProgramError::InvalidArgument)
+    })
+}
+
+// The Anchor entrypoints take accounts that live as long as their AccountInfos.
+// Leaking them, as the SVM tests do, would trip the leak detection of the
+// fuzzer, so the slice is borrowed for longer instead.
+fn native_htlc_entry<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: the entrypoint does not keep the accounts past the call.
+    let accounts: &'a [AccountInfo<'a>] = unsafe { std::mem::transmute(accounts) };
+    run_entry(|| sol::entry(program_id, accounts, data))
+}
+
+fn anchor_htlc_entry<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: the entrypoint does not keep the accounts past the call.
+    let accounts: &'a [AccountInfo<'a>] = unsafe { std::mem::transmute(accounts) };
+    run_entry(|| ::native::entry(program_id, accounts, data))
+}
+
+impl World {
+    /// Loads both programs and funds the users, HTLCs are read with `decode`.
+    pub fn new(decode: fn(&[u8]) -> Option<HtlcState>) -> Self {
+        thread_local! {
+            /// An SVM with both programs, cloned for every World since
+            /// setting one up takes most of the time of a short input.
+            static SVM: LiteSVM = {
+                let mut svm = LiteSVM::new();
+                add_programs(&mut svm);
+                svm
+            };
+        }
+        let mut svm = SVM.with(LiteSVM::clone);
+
+        let mut clock = svm.get_sysvar::<Clock>();
+        clock.unix_timestamp = START;
+        svm.set_sysvar::<Clock>(&clock);
+
+        let users: Vec<Keypair> = (0..USERS).map(|_| Keypair::new()).collect();
+        let fee_payer = Keypair::new();
+        for user in users.iter().chain([&fee_payer]) {
+            svm.airdrop(&user.pubkey(), USER_LAMPORTS).unwrap();
+        }
+        let mut world = Self {
+            svm,
+            accounts: users.iter().map(|user| user.pubkey()).collect(),
+            known: vec![
+                native_htlc::ID,
+                anchor_htlc::ID,
+                anchor_lang::system_program::ID,
+                anchor_lang::solana_program::sysvar::instructions::ID,
+                anchor_lang::solana_program::sysvar::rent::ID,
+                train_solana_client::find_event_authority_address(&native_htlc::ID).0,
+                train_solana_client::find_event_authority_address(&anchor_htlc::ID).0,
+            ],
+            users,
+            fee_payer,
+            htlcs: vec![],
+            sessions: vec![],
+            token_accounts: vec![],
+            mints: vec![],
+            decode,
+        };
+        world.known.extend(world.accounts.clone());
+        world
+    }
+
+    pub fn user(&self, user: User) -> &Keypair {
+        &self.users[user.index()]
+    }
+
+    pub fn now(&self) -> i64 {
+        self.svm.get_sysvar::<Clock>().unix_timestamp
+    }
+
+    pub fn warp(&mut self, seconds: u16) {
+        let mut clock = self.svm.get_sysvar::<Clock>();
+        clock.unix_timestamp += seconds as i64;
+        clock.slot += 1;
+        self.svm.set_sysvar::<Clock>(&clock);
+    }
+
+    /// Tracks the lamports of `address`.
+    pub fn track(&mut self, address: Pubkey) {
+        self.accounts.push(address);
+        self.known.push(address);
+    }
+
+    pub fn track_htlc(&mut self, address: Pubkey) {
+        self.track(address);
+        self.htlcs.push(address);
+    }
+
+    /// Tracks the lamports of a Session of `sender`, whose rent and budget
+    /// return to the sender when it is revoked.
+    pub fn track_session(&mut self, address: Pubkey, sender: usize) {
+        self.track(address);
+        self.sessions.push((address, sender));
+    }
+
+    /// Tracks the lamports and the tokens of a token account, owned by `user` if any.
+    pub fn track_token_account(&mut self, address: Pubkey, user: Option<usize>) {
+        self.track(address);
+        self.token_accounts.push((address, user));
+    }
+
+    pub fn track_mint(&mut self, address: Pubkey) {
+        self.known.push(address);
+        self.mints.push(address);
+    }
+
+    /// Sends setup instructions paid by the fee payer, outside of the invariants.
+    pub fn setup(&mut self, instructions: &[Instruction], signers: &[&Keypair]) {
+        let mut all = vec![&self.fee_payer];
+        all.extend(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.fee_payer.pubkey()),
+            &all,
+            self.svm.latest_blockhash(),
+        );
+        self.svm
+            .send_transaction(transaction)
+            .unwrap_or_else(|failed| panic!("setup failed: {:?}", failed.err));
+        self.svm.expire_blockhash();
+    }
+
+    /// Applies `mutations` to the last instruction, sends the transaction and
+    /// checks the invariants.
+    pub fn execute(&mut self, mut instructions: Vec<Instruction>, mutations: &[Mutation]) {
+        if let Some(instruction) = instructions.last_mut() {
+            for mutation in mutations {
+                self.mutate(instruction, *mutation);
+            }
+        }
+
+        // Only users can sign, other signer accounts lose the flag.
+        let mut signers = vec![&self.fee_payer];
+        for meta in instructions
+            .iter_mut()
+            .flat_map(|ix| ix.accounts.iter_mut())
+        {
+            if !meta.is_signer {
+                continue;
+            }
+            match self.users.iter().find(|user| user.pubkey() == meta.pubkey) {
+                Some(user) if !signers.iter().any(|s| s.pubkey() == user.pubkey()) => {
+                    signers.push(user)
+                }
+                Some(_) => {}
+                None => meta.is_signer = false,
+            }
+        }
+        let signer_keys: Vec<Pubkey> = signers[1..].iter().map(|s| s.pubkey()).collect();
+
+        let mut transaction =
+            Transaction::new_with_payer(&instructions, Some(&self.fee_payer.pubkey()));
+        if transaction
+            .try_sign(&signers, self.svm.latest_blockhash())
+            .is_err()
+        {
+            return;
+        }
+
+        let before = self.snapshot();
+        let result = self.svm.send_transaction(transaction);
+        self.svm.expire_blockhash();
+        let logs = match &result {
+            Ok(meta) => &meta.logs,
+            Err(failed) => &failed.meta.logs,
+        };
+        assert!(
+            !logs.iter().any(|log| log.contains("panicked")),
+            "program panicked\n{}",
+            logs.join("\n")
+        );
+
+        let after = self.snapshot();
+        match result {
+            Ok(_) => self.check(&before, &after, &signer_keys),
+            Err(_) => assert_eq!(before, after, "a failed transaction changed accounts"),
+        }
+    }
+
+    fn mutate(&self, instruction: &mut Instruction, mutation: Mutation) {
+        let accounts = &mut instruction.accounts;
+        if accounts.is_empty() {
+            return;
+        }
+        let len = accounts.len();
+        match mutation {
+            Mutation::Substitute { index, account } => {
+                accounts[index as usize % len].pubkey =
+                    self.known[account as usize % self.known.len()];
+            }
+            Mutation::Swap { a, b } => accounts.swap(a as usize % len, b as usize % len),
+            Mutation::Remove { index } => {
+                accounts.remove(index as usize % len);
+            }
+            Mutation::ToggleWritable { index } => {
+                let meta = &mut accounts[index as usize % len];
+                meta.is_writable = !meta.is_writable;
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lamports: self
+                .accounts
+                .iter()
+                .map(|address| self.svm.get_balance(address).unwrap_or(0))
+                .collect(),
+            tokens: self
+                .token_accounts
+                .iter()
+                .map(|(address, _)| self.token_balance(address))
+                .collect(),
+            supplies: self.mints.iter().map(|mint| self.supply(mint)).collect(),
+            htlcs: self
+                .htlcs
+                .iter()
+                .map(|address| {
+                    let account = self.svm.get_account(address)?;
+                    (self.decode)(&account.data)
+                })
+                .collect(),
+            sessions: self
+                .sessions
+                .iter()
+                .map(|(address, _)| {
+                    self.svm
+                        .get_account(address)
+                        .is_some_and(|account| account.lamports > 0)
+                })
+                .collect(),
+        }
+    }
+
+    fn token_balance(&self, address: &Pubkey) -> u64 {
+        self.svm
+            .get_account(address)
+            .and_then(|account| token::unpack_token_account(&account.data))
+            .map_or(0, |account| account.amount)
+    }
+
+    fn supply(&self, mint: &Pubkey) -> u64 {
+        self.svm
+            .get_account(mint)
+            .and_then(|account| token::unpack_mint(&account.data))
+            .map_or(0, |mint| mint.supply)
+    }
+
+    fn check(&self, before: &Snapshot, after: &Snapshot, signers: &[Pubkey]) {
+        assert_eq!(
+            before.lamports.iter().sum::<u64>(),
+            after.lamports.iter().sum::<u64>(),
+            "lamports were created or left the tracked accounts"
+        );
+        assert_eq!(before.supplies, after.supplies, "the supply changed");
+        assert_eq!(
+            before.tokens.iter().sum::<u64>(),
+            after.tokens.iter().sum::<u64>(),
+            "tokens were created or left the tracked accounts"
+        );
+
+        // Users who may gain funds in this transaction.
+        let mut payees = vec![];
+        for (address, (was, is)) in self.htlcs.iter().zip(before.htlcs.iter().zip(&after.htlcs)) {
+            match (was, is) {
+                (Some(was), Some(is)) if was.status != Status::Pending => {
+                    assert_eq!(was, is, "settled HTLC {address} changed")
+                }
+                (Some(was), Some(is)) if is.status != Status::Pending => {
+                    payees.extend([was.sender, was.src_receiver])
+                }
+                (Some(was), None) => {
+                    assert_ne!(was.status, Status::Pending, "pending HTLC {address} closed");
+                    payees.push(was.sender);
+                }
+                _ => {}
+            }
+        }
+        for ((_, sender), (was, is)) in self
+            .sessions
+            .iter()
+            .zip(before.sessions.iter().zip(&after.sessions))
+        {
+            if *was && !*is {
+                payees.push(self.users[*sender].pubkey());
+            }
+        }
+        if !payees.is_empty() {
+            payees.extend(signers);
+        }
+
+        for (i, user) in self.users.iter().enumerate() {
+            let user = user.pubkey();
+            if after.lamports[i] > before.lamports[i] {
+                assert!(payees.contains(&user), "{user} gained lamports");
+            }
+        }
+        for (i, (address, owner)) in self.token_accounts.iter().enumerate() {
+            if let Some(owner) = owner {
+                let user = self.users[*owner].pubkey();
+                if after.tokens[i] > before.tokens[i] {
+                    assert!(payees.contains(&user), "{user} gained tokens in {address}");
+                }
+            }
+        }
+    }
+}
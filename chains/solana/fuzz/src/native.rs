@@ -0,0 +1,539 @@
+//! Steps against native_htlc.
+
+use crate::{
+    add_lock_message, evm_address, Amount, EvmSignature, HtlcState, Id, Len, Precompile, Secret,
+    Status, Step, Timelock, User, World, GENESIS_HASH, IDS, MAX_BATCH, MAX_STEPS, USERS,
+};
+use arbitrary::Arbitrary;
+use solana_signer::Signer;
+use train_solana_client::native_htlc::{
+    self, instruction, HtlcStatus, LockEntry, MAX_ADDRESS_LEN, MAX_ASSET_LEN, MAX_CHAIN_LEN,
+    MAX_HOPS,
+};
+use train_solana_client::Instruction;
+
+#[derive(Arbitrary, Debug)]
+pub enum Action {
+    Commit {
+        sender: User,
+        id: Id,
+        receiver: User,
+        timelock: Timelock,
+        amount: Amount,
+        hops: u8,
+        dst_chain: Len,
+        dst_address: Len,
+        authorized_locker: Option<User>,
+    },
+    Lock {
+        sender: User,
+        id: Id,
+        receiver: User,
+        secret: Secret,
+        timelock: Timelock,
+        amount: Amount,
+        src_asset: Len,
+    },
+    AddLock {
+        /// Signs instead of the sender if set.
+        locker: Option<User>,
+        /// Signs through the session of the sender naming the locker.
+        by_session: bool,
+        sender: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+    },
+    SetAuthorizedLocker {
+        sender: User,
+        id: Id,
+        locker: Option<User>,
+    },
+    AddLockSig {
+        payer: User,
+        sender: User,
+        signer: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+        /// None signs the legacy message for add_lock_sig_legacy.
+        deadline: Option<Timelock>,
+        precompile: Precompile,
+    },
+    SetEvmAuthorizer {
+        sender: User,
+        id: Id,
+        authorizer: Option<User>,
+    },
+    AddLockSigEvm {
+        payer: User,
+        sender: User,
+        signer: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+        deadline: Timelock,
+        signature: EvmSignature,
+    },
+    LockReward {
+        /// Signs through its session with the sender if set.
+        delegate: Option<User>,
+        sender: User,
+        id: Id,
+        reward_timelock: Timelock,
+        reward: Amount,
+    },
+    Redeem {
+        by: User,
+        sender: User,
+        receiver: User,
+        id: Id,
+        secret: Secret,
+    },
+    Refund {
+        by: User,
+        sender: User,
+        id: Id,
+    },
+    BatchLock {
+        sender: User,
+        /// The Id, src_receiver, secret, timelock and amount of each HTLC.
+        entries: Vec<(Id, User, Secret, Timelock, Amount)>,
+        best_effort: bool,
+    },
+    BatchRedeem {
+        by: User,
+        /// The sender, src_receiver, Id and secret of each HTLC.
+        entries: Vec<(User, User, Id, Secret)>,
+        best_effort: bool,
+    },
+    RedeemByHashlock {
+        by: User,
+        hashlock: Secret,
+        secret: Option<Secret>,
+        /// The sender and Id of the HTLC given as proof of the secret.
+        proof: Option<(User, Id)>,
+        /// The sender, src_receiver and Id of each HTLC.
+        entries: Vec<(User, User, Id)>,
+        best_effort: bool,
+    },
+    BatchRefund {
+        by: User,
+        /// The sender and Id of each HTLC.
+        entries: Vec<(User, Id)>,
+        best_effort: bool,
+    },
+    CreateSession {
+        sender: User,
+        delegate: User,
+        expiry: Timelock,
+        max_amount: Amount,
+        permissions: u8,
+    },
+    RevokeSession {
+        sender: User,
+        delegate: User,
+    },
+    ReserveId {
+        sender: User,
+    },
+    Close {
+        sender: User,
+        id: Id,
+    },
+    Warp {
+        seconds: u16,
+    },
+}
+
+fn decode(data: &[u8]) -> Option<HtlcState> {
+    let htlc = native_htlc::decode_htlc(data).ok()?;
+    Some(HtlcState {
+        status: match htlc.status {
+            HtlcStatus::Committed | HtlcStatus::Locked => Status::Pending,
+            HtlcStatus::Redeemed => Status::Redeemed,
+            HtlcStatus::Refunded => Status::Refunded,
+        },
+        sender: htlc.sender,
+        src_receiver: htlc.src_receiver,
+        amount: htlc.amount,
+        reward: htlc.reward,
+    })
+}
+
+/// A World tracking the HTLCs and SenderStates of every user.
+pub fn world() -> World {
+    let mut world = World::new(decode);
+    for user in 0..USERS {
+        let sender = world.users[user].pubkey();
+        world.track(native_htlc::find_sender_state_address(&sender).0);
+        for delegate in 0..USERS {
+            let delegate = world.users[delegate].pubkey();
+            world.track_session(
+                native_htlc::find_session_address(&sender, &delegate).0,
+                user,
+            );
+        }
+        for id in 0..IDS {
+            world.track_htlc(native_htlc::find_htlc_address(&sender, &[id; 32]).0);
+        }
+    }
+    // The redeems of every hashlock an HTLC can have write these.
+    for secret in 0..IDS {
+        world.track(native_htlc::find_secret_record_address(&Secret(secret).hashlock()).0);
+    }
+    world
+}
+
+/// Runs the steps against a fresh World.
+pub fn run(steps: Vec<Step<Action>>) {
+    let mut world = world();
+    for step in steps.into_iter().take(MAX_STEPS) {
+        if let Action::Warp { seconds } = step.action {
+            world.warp(seconds);
+            continue;
+        }
+        let instructions = step.action.instructions(&world);
+        world.execute(instructions, &step.mutations);
+    }
+}
+
+impl Action {
+    fn instructions(&self, world: &World) -> Vec<Instruction> {
+        let key = |user: &User| world.user(*user).pubkey();
+        let now = world.now();
+        match self {
+            Action::Commit {
+                sender,
+                id,
+                receiver,
+                timelock,
+                amount,
+                hops,
+                dst_chain,
+                dst_address,
+                authorized_locker,
+            } => {
+                let hops = *hops as usize % (MAX_HOPS + 2);
+                vec![native_htlc::commit(
+                    &key(sender),
+                    instruction::Commit {
+                        Id: id.bytes(),
+                        hopChains: vec!["hop".to_string(); hops],
+                        hopAssets: vec!["ETH".to_string(); hops],
+                        hopAddresses: vec!["0x0".to_string(); hops],
+                        dst_chain: dst_chain.string(MAX_CHAIN_LEN),
+                        dst_asset: "ETH".to_string(),
+                        dst_address: dst_address.string(MAX_ADDRESS_LEN),
+                        src_asset: "SOL".to_string(),
+                        src_receiver: key(receiver),
+                        timelock: timelock.resolve(now),
+                        amount: amount.value(),
+                        authorized_locker: authorized_locker.map(|user| key(&user)),
+                    },
+                )]
+            }
+            Action::Lock {
+                sender,
+                id,
+                receiver,
+                secret,
+                timelock,
+                amount,
+                src_asset,
+            } => vec![native_htlc::lock(
+                &key(sender),
+                instruction::Lock {
+                    Id: id.bytes(),
+                    hashlock: secret.hashlock(),
+                    timelock: timelock.resolve(now),
+                    amount: amount.value(),
+                    dst_chain: "ETHEREUM".to_string(),
+                    dst_address: "0x0".to_string(),
+                    dst_asset: "ETH".to_string(),
+                    src_asset: src_asset.string(MAX_ASSET_LEN),
+                    src_receiver: key(receiver),
+                },
+            )],
+            Action::AddLock {
+                locker,
+                by_session,
+                sender,
+                id,
+                secret,
+                timelock,
+            } => {
+                let build = if *by_session {
+                    native_htlc::add_lock_by_session
+                } else {
+                    native_htlc::add_lock_as
+                };
+                vec![build(
+                    &key(&locker.unwrap_or(*sender)),
+                    &key(sender),
+                    instruction::AddLock {
+                        Id: id.bytes(),
+                        hashlock: secret.hashlock(),
+                        timelock: timelock.resolve(now),
+                    },
+                )]
+            }
+            Action::AddLockSig {
+                payer,
+                sender,
+                signer,
+                id,
+                secret,
+                timelock,
+                deadline,
+                precompile,
+            } => {
+                let timelock = timelock.resolve(now);
+                let deadline = deadline.map(|deadline| deadline.resolve(now));
+                let message = add_lock_message(
+                    native_htlc::add_lock_sig_domain(GENESIS_HASH),
+                    world.user(*signer),
+                    &id.bytes(),
+                    &secret.hashlock(),
+                    timelock,
+                    deadline,
+                );
+                let (mut instructions, signature) = precompile.build(world.user(*signer), &message);
+                instructions.push(match deadline {
+                    Some(deadline) => native_htlc::add_lock_sig(
+                        &key(payer),
+                        &key(sender),
+                        instruction::AddLockSig {
+                            Id: id.bytes(),
+                            hashlock: secret.hashlock(),
+                            timelock,
+                            deadline,
+                            signature,
+                        },
+                    ),
+                    None => native_htlc::add_lock_sig_legacy(
+                        &key(payer),
+                        &key(sender),
+                        instruction::AddLockSigLegacy {
+                            Id: id.bytes(),
+                            hashlock: secret.hashlock(),
+                            timelock,
+                            signature,
+                        },
+                    ),
+                });
+                instructions
+            }
+            Action::SetAuthorizedLocker { sender, id, locker } => {
+                vec![native_htlc::set_authorized_locker(
+                    &key(sender),
+                    instruction::SetAuthorizedLocker {
+                        Id: id.bytes(),
+                        authorized_locker: locker.map(|user| key(&user)),
+                    },
+                )]
+            }
+            Action::SetEvmAuthorizer {
+                sender,
+                id,
+                authorizer,
+            } => vec![native_htlc::set_evm_authorizer(
+                &key(sender),
+                instruction::SetEvmAuthorizer {
+                    Id: id.bytes(),
+                    evm_authorizer: evm_address(*authorizer),
+                },
+            )],
+            Action::AddLockSigEvm {
+                payer,
+                sender,
+                signer,
+                id,
+                secret,
+                timelock,
+                deadline,
+                signature,
+            } => {
+                let timelock = timelock.resolve(now);
+                let deadline = deadline.resolve(now);
+                let digest = native_htlc::add_lock_evm_digest(
+                    GENESIS_HASH,
+                    &id.bytes(),
+                    &secret.hashlock(),
+                    timelock,
+                    deadline,
+                );
+                let (r, s, v) = signature.build(*signer, &digest);
+                vec![native_htlc::add_lock_sig_evm(
+                    &key(payer),
+                    &key(sender),
+                    instruction::AddLockSigEvm {
+                        Id: id.bytes(),
+                        hashlock: secret.hashlock(),
+                        timelock,
+                        deadline,
+                        r,
+                        s,
+                        v,
+                    },
+                )]
+            }
+            Action::LockReward {
+                delegate,
+                sender,
+                id,
+                reward_timelock,
+                reward,
+            } => {
+                let args = instruction::LockReward {
+                    Id: id.bytes(),
+                    reward_timelock: reward_timelock.resolve(now),
+                    reward: reward.value(),
+                };
+                vec![match delegate {
+                    Some(delegate) => {
+                        native_htlc::lock_reward_by_session(&key(delegate), &key(sender), args)
+                    }
+                    None => native_htlc::lock_reward(&key(sender), args),
+                }]
+            }
+            Action::CreateSession {
+                sender,
+                delegate,
+                expiry,
+                max_amount,
+                permissions,
+            } => vec![native_htlc::create_session(
+                &key(sender),
+                instruction::CreateSession {
+                    delegate: key(delegate),
+                    expiry: expiry.resolve(now),
+                    max_amount: max_amount.value(),
+                    permissions: *permissions,
+                },
+            )],
+            Action::RevokeSession { sender, delegate } => vec![native_htlc::revoke_session(
+                &key(sender),
+                instruction::RevokeSession {
+                    delegate: key(delegate),
+                },
+            )],
+            Action::Redeem {
+                by,
+                sender,
+                receiver,
+                id,
+                secret,
+            } => vec![native_htlc::redeem(
+                &key(by),
+                &key(sender),
+                &key(receiver),
+                instruction::Redeem {
+                    Id: id.bytes(),
+                    secret: secret.bytes(),
+                },
+            )],
+            Action::Refund { by, sender, id } => vec![native_htlc::refund(
+                &key(by),
+                &key(sender),
+                instruction::Refund { Id: id.bytes() },
+            )],
+            Action::BatchLock {
+                sender,
+                entries,
+                best_effort,
+            } => vec![native_htlc::batch_lock(
+                &key(sender),
+                instruction::BatchLock {
+                    entries: entries
+                        .iter()
+                        .take(MAX_BATCH)
+                        .map(|(id, receiver, secret, timelock, amount)| LockEntry {
+                            Id: id.bytes(),
+                            hashlock: secret.hashlock(),
+                            timelock: timelock.resolve(now),
+                            amount: amount.value(),
+                            dst_chain: "ETHEREUM".to_string(),
+                            dst_address: "0x0".to_string(),
+                            dst_asset: "ETH".to_string(),
+                            src_asset: "SOL".to_string(),
+                            src_receiver: key(receiver),
+                        })
+                        .collect(),
+                    best_effort: *best_effort,
+                },
+            )],
+            Action::BatchRedeem {
+                by,
+                entries,
+                best_effort,
+            } => {
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![native_htlc::batch_redeem(
+                    &key(by),
+                    &entries
+                        .iter()
+                        .map(|(sender, receiver, _, _)| (key(sender), key(receiver)))
+                        .collect::<Vec<_>>(),
+                    instruction::BatchRedeem {
+                        entries: entries
+                            .iter()
+                            .map(|(_, _, id, secret)| (id.bytes(), secret.bytes()))
+                            .collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::RedeemByHashlock {
+                by,
+                hashlock,
+                secret,
+                proof,
+                entries,
+                best_effort,
+            } => {
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![native_htlc::redeem_by_hashlock(
+                    &key(by),
+                    proof.as_ref().map(|(sender, id)| {
+                        native_htlc::find_htlc_address(&key(sender), &id.bytes()).0
+                    }),
+                    &entries
+                        .iter()
+                        .map(|(sender, receiver, _)| (key(sender), key(receiver)))
+                        .collect::<Vec<_>>(),
+                    instruction::RedeemByHashlock {
+                        hashlock: hashlock.hashlock(),
+                        secret: secret.map(Secret::bytes),
+                        Ids: entries.iter().map(|(_, _, id)| id.bytes()).collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::BatchRefund {
+                by,
+                entries,
+                best_effort,
+            } => {
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![native_htlc::batch_refund(
+                    &key(by),
+                    &entries
+                        .iter()
+                        .map(|(sender, _)| key(sender))
+                        .collect::<Vec<_>>(),
+                    instruction::BatchRefund {
+                        Ids: entries.iter().map(|(_, id)| id.bytes()).collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::ReserveId { sender } => vec![native_htlc::reserve_id(&key(sender))],
+            Action::Close { sender, id } => vec![native_htlc::close(
+                &key(sender),
+                instruction::Close { Id: id.bytes() },
+            )],
+            Action::Warp { .. } => vec![],
+        }
+    }
+}
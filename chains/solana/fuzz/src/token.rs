@@ -0,0 +1,723 @@
+//! Steps against anchor_htlc, with an SPL Token and a Token-2022 mint.
+
+use crate::{
+    add_lock_message, evm_address, Amount, EvmSignature, HtlcState, Id, Len, Precompile, Secret,
+    Status, Step, Timelock, User, World, GENESIS_HASH, IDS, MAX_BATCH, MAX_STEPS, USERS,
+};
+use anchor_lang::solana_program::{program_option::COption, program_pack::Pack};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::StateWithExtensions,
+    state::{Account as TokenAccount, Mint},
+};
+use arbitrary::Arbitrary;
+use solana_account::Account;
+use solana_signer::Signer;
+use spl_associated_token_account_client::instruction::create_associated_token_account;
+use train_solana_client::anchor_htlc::{
+    self, instruction, FeePayer, HtlcStatus, LockEntry, MAX_ADDRESS_LEN, MAX_ASSET_LEN,
+    MAX_CHAIN_LEN, MAX_HOPS,
+};
+use train_solana_client::{Instruction, Pubkey};
+
+/// Token program of each mint of the World.
+pub const TOKEN_PROGRAMS: [Pubkey; 2] = [spl_token::ID, spl_token_2022::ID];
+/// Tokens minted to every user on each mint.
+const MINTED: u64 = 1_000_000_000;
+
+/// One of the mints, with its token program.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct MintChoice(u8);
+
+impl MintChoice {
+    fn index(self) -> usize {
+        self.0 as usize % TOKEN_PROGRAMS.len()
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+pub enum Action {
+    Commit {
+        sender: User,
+        id: Id,
+        receiver: User,
+        mint: MintChoice,
+        timelock: Timelock,
+        amount: Amount,
+        hops: u8,
+        dst_chain: Len,
+        sender_pays_fee: bool,
+        authorized_locker: Option<User>,
+    },
+    Lock {
+        sender: User,
+        id: Id,
+        receiver: User,
+        mint: MintChoice,
+        secret: Secret,
+        timelock: Timelock,
+        amount: Amount,
+        dst_address: Len,
+        sender_pays_fee: bool,
+    },
+    AddLock {
+        /// Signs instead of the sender if set.
+        locker: Option<User>,
+        /// Signs through the session of the sender naming the locker.
+        by_session: bool,
+        sender: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+    },
+    SetAuthorizedLocker {
+        sender: User,
+        id: Id,
+        locker: Option<User>,
+    },
+    AddLockSig {
+        payer: User,
+        sender: User,
+        signer: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+        /// None signs the legacy message for add_lock_sig_legacy.
+        deadline: Option<Timelock>,
+        precompile: Precompile,
+    },
+    SetEvmAuthorizer {
+        sender: User,
+        id: Id,
+        authorizer: Option<User>,
+    },
+    AddLockSigEvm {
+        payer: User,
+        sender: User,
+        signer: User,
+        id: Id,
+        secret: Secret,
+        timelock: Timelock,
+        deadline: Timelock,
+        signature: EvmSignature,
+    },
+    LockReward {
+        /// Signs through its session with the sender if set.
+        delegate: Option<User>,
+        sender: User,
+        id: Id,
+        mint: MintChoice,
+        reward_timelock: Timelock,
+        reward: Amount,
+    },
+    Redeem {
+        by: User,
+        sender: User,
+        receiver: User,
+        id: Id,
+        mint: MintChoice,
+        secret: Secret,
+    },
+    Refund {
+        by: User,
+        sender: User,
+        id: Id,
+        mint: MintChoice,
+    },
+    BatchLock {
+        sender: User,
+        mint: MintChoice,
+        /// The Id, src_receiver, secret, timelock and amount of each HTLC.
+        entries: Vec<(Id, User, Secret, Timelock, Amount)>,
+        sender_pays_fee: bool,
+        best_effort: bool,
+    },
+    BatchRedeem {
+        by: User,
+        mint: MintChoice,
+        /// The sender, src_receiver, Id and secret of each HTLC.
+        entries: Vec<(User, User, Id, Secret)>,
+        best_effort: bool,
+    },
+    RedeemByHashlock {
+        by: User,
+        mint: MintChoice,
+        hashlock: Secret,
+        secret: Option<Secret>,
+        /// The sender and Id of the HTLC given as proof of the secret.
+        proof: Option<(User, Id)>,
+        /// The sender, src_receiver and Id of each HTLC.
+        entries: Vec<(User, User, Id)>,
+        best_effort: bool,
+    },
+    BatchRefund {
+        by: User,
+        mint: MintChoice,
+        /// The sender and Id of each HTLC.
+        entries: Vec<(User, Id)>,
+        best_effort: bool,
+    },
+    CreateSession {
+        sender: User,
+        delegate: User,
+        mint: MintChoice,
+        expiry: Timelock,
+        max_amount: Amount,
+        permissions: u8,
+    },
+    RevokeSession {
+        sender: User,
+        delegate: User,
+        mint: MintChoice,
+    },
+    ReserveId {
+        sender: User,
+    },
+    Close {
+        sender: User,
+        id: Id,
+    },
+    Warp {
+        seconds: u16,
+    },
+}
+
+pub(crate) fn unpack_token_account(data: &[u8]) -> Option<TokenAccount> {
+    StateWithExtensions::<TokenAccount>::unpack(data)
+        .ok()
+        .map(|state| state.base)
+}
+
+pub(crate) fn unpack_mint(data: &[u8]) -> Option<Mint> {
+    StateWithExtensions::<Mint>::unpack(data)
+        .ok()
+        .map(|state| state.base)
+}
+
+fn decode(data: &[u8]) -> Option<HtlcState> {
+    let htlc = anchor_htlc::decode_htlc(data).ok()?;
+    Some(HtlcState {
+        status: match htlc.status {
+            HtlcStatus::Committed | HtlcStatus::Locked => Status::Pending,
+            HtlcStatus::Redeemed => Status::Redeemed,
+            HtlcStatus::Refunded => Status::Refunded,
+        },
+        sender: htlc.sender,
+        src_receiver: htlc.src_receiver,
+        amount: htlc.amount,
+        reward: htlc.reward,
+    })
+}
+
+/// A World with one mint per token program, MINTED tokens in the associated
+/// token account of every user, and tracking the HTLCs, their token accounts
+/// and the SenderStates of every user.
+pub fn world() -> World {
+    let mut world = World::new(decode);
+    let authority = world.fee_payer.pubkey();
+    for token_program in TOKEN_PROGRAMS {
+        let mint = Pubkey::new_unique();
+        let mut data = vec![0; Mint::LEN];
+        Mint {
+            mint_authority: COption::Some(authority),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut data);
+        let lamports = world.svm.minimum_balance_for_rent_exemption(data.len());
+        world
+            .svm
+            .set_account(
+                mint,
+                Account {
+                    lamports,
+                    data,
+                    owner: token_program,
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            )
+            .unwrap();
+        world.track_mint(mint);
+
+        for user in 0..USERS {
+            let owner = world.users[user].pubkey();
+            let ata = anchor_htlc::associated_token_address(&owner, &mint, &token_program);
+            let create = create_associated_token_account(&authority, &owner, &mint, &token_program);
+            let mint_to = spl_token_2022::instruction::mint_to(
+                &token_program,
+                &mint,
+                &ata,
+                &authority,
+                &[],
+                MINTED,
+            )
+            .unwrap();
+            world.setup(&[create, mint_to], &[]);
+            world.track_token_account(ata, Some(user));
+        }
+    }
+
+    for user in 0..USERS {
+        let sender = world.users[user].pubkey();
+        world.track(anchor_htlc::find_sender_state_address(&sender).0);
+        for delegate in 0..USERS {
+            let delegate = world.users[delegate].pubkey();
+            world.track_session(
+                anchor_htlc::find_session_address(&sender, &delegate).0,
+                user,
+            );
+        }
+        for id in 0..IDS {
+            world.track_htlc(anchor_htlc::find_htlc_address(&sender, &[id; 32]).0);
+            world.track_token_account(
+                anchor_htlc::find_htlc_token_account_address(&sender, &[id; 32]).0,
+                None,
+            );
+        }
+    }
+    // The redeems of every hashlock an HTLC can have write these.
+    for secret in 0..IDS {
+        world.track(anchor_htlc::find_secret_record_address(&Secret(secret).hashlock()).0);
+    }
+    world
+}
+
+/// Runs the steps against a fresh World.
+pub fn run(steps: Vec<Step<Action>>) {
+    let mut world = world();
+    for step in steps.into_iter().take(MAX_STEPS) {
+        if let Action::Warp { seconds } = step.action {
+            world.warp(seconds);
+            continue;
+        }
+        let instructions = step.action.instructions(&world);
+        world.execute(instructions, &step.mutations);
+    }
+}
+
+fn fee_payer(sender_pays_fee: bool) -> FeePayer {
+    if sender_pays_fee {
+        FeePayer::Sender
+    } else {
+        FeePayer::Receiver
+    }
+}
+
+impl Action {
+    fn instructions(&self, world: &World) -> Vec<Instruction> {
+        let key = |user: &User| world.user(*user).pubkey();
+        let mint = |mint: &MintChoice| (world.mints[mint.index()], TOKEN_PROGRAMS[mint.index()]);
+        let now = world.now();
+        match self {
+            Action::Commit {
+                sender,
+                id,
+                receiver,
+                mint: choice,
+                timelock,
+                amount,
+                hops,
+                dst_chain,
+                sender_pays_fee,
+                authorized_locker,
+            } => {
+                let (mint, token_program) = mint(choice);
+                let hops = *hops as usize % (MAX_HOPS + 2);
+                vec![anchor_htlc::commit(
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::Commit {
+                        Id: id.bytes(),
+                        hopChains: vec!["hop".to_string(); hops],
+                        hopAssets: vec!["ETH".to_string(); hops],
+                        hopAddress: vec!["0x0".to_string(); hops],
+                        dst_chain: dst_chain.string(MAX_CHAIN_LEN),
+                        dst_asset: "ETH".to_string(),
+                        dst_address: "0x0".to_string(),
+                        src_asset: "USDC".to_string(),
+                        src_receiver: key(receiver),
+                        timelock: timelock.resolve(now),
+                        amount: amount.value(),
+                        fee_payer: fee_payer(*sender_pays_fee),
+                        authorized_locker: authorized_locker.map(|user| key(&user)),
+                    },
+                )]
+            }
+            Action::Lock {
+                sender,
+                id,
+                receiver,
+                mint: choice,
+                secret,
+                timelock,
+                amount,
+                dst_address,
+                sender_pays_fee,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::lock(
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::Lock {
+                        Id: id.bytes(),
+                        hashlock: secret.hashlock(),
+                        timelock: timelock.resolve(now),
+                        dst_chain: "ETHEREUM".to_string(),
+                        dst_address: dst_address.string(MAX_ADDRESS_LEN),
+                        dst_asset: "ETH".to_string(),
+                        src_asset: "x".repeat(MAX_ASSET_LEN),
+                        src_receiver: key(receiver),
+                        amount: amount.value(),
+                        fee_payer: fee_payer(*sender_pays_fee),
+                    },
+                )]
+            }
+            Action::AddLock {
+                locker,
+                by_session,
+                sender,
+                id,
+                secret,
+                timelock,
+            } => {
+                let build = if *by_session {
+                    anchor_htlc::add_lock_by_session
+                } else {
+                    anchor_htlc::add_lock_as
+                };
+                vec![build(
+                    &key(&locker.unwrap_or(*sender)),
+                    &key(sender),
+                    instruction::AddLock {
+                        Id: id.bytes(),
+                        hashlock: secret.hashlock(),
+                        timelock: timelock.resolve(now),
+                    },
+                )]
+            }
+            Action::AddLockSig {
+                payer,
+                sender,
+                signer,
+                id,
+                secret,
+                timelock,
+                deadline,
+                precompile,
+            } => {
+                let timelock = timelock.resolve(now);
+                let deadline = deadline.map(|deadline| deadline.resolve(now));
+                let message = add_lock_message(
+                    anchor_htlc::add_lock_sig_domain(GENESIS_HASH),
+                    world.user(*signer),
+                    &id.bytes(),
+                    &secret.hashlock(),
+                    timelock,
+                    deadline,
+                );
+                let (mut instructions, signature) = precompile.build(world.user(*signer), &message);
+                instructions.push(match deadline {
+                    Some(deadline) => anchor_htlc::add_lock_sig(
+                        &key(payer),
+                        &key(sender),
+                        instruction::AddLockSig {
+                            Id: id.bytes(),
+                            hashlock: secret.hashlock(),
+                            timelock,
+                            deadline,
+                            signature,
+                        },
+                    ),
+                    None => anchor_htlc::add_lock_sig_legacy(
+                        &key(payer),
+                        &key(sender),
+                        instruction::AddLockSigLegacy {
+                            Id: id.bytes(),
+                            hashlock: secret.hashlock(),
+                            timelock,
+                            signature,
+                        },
+                    ),
+                });
+                instructions
+            }
+            Action::SetAuthorizedLocker { sender, id, locker } => {
+                vec![anchor_htlc::set_authorized_locker(
+                    &key(sender),
+                    instruction::SetAuthorizedLocker {
+                        Id: id.bytes(),
+                        authorized_locker: locker.map(|user| key(&user)),
+                    },
+                )]
+            }
+            Action::SetEvmAuthorizer {
+                sender,
+                id,
+                authorizer,
+            } => vec![anchor_htlc::set_evm_authorizer(
+                &key(sender),
+                instruction::SetEvmAuthorizer {
+                    Id: id.bytes(),
+                    evm_authorizer: evm_address(*authorizer),
+                },
+            )],
+            Action::AddLockSigEvm {
+                payer,
+                sender,
+                signer,
+                id,
+                secret,
+                timelock,
+                deadline,
+                signature,
+            } => {
+                let timelock = timelock.resolve(now);
+                let deadline = deadline.resolve(now);
+                let digest = anchor_htlc::add_lock_evm_digest(
+                    GENESIS_HASH,
+                    &id.bytes(),
+                    &secret.hashlock(),
+                    timelock,
+                    deadline,
+                );
+                let (r, s, v) = signature.build(*signer, &digest);
+                vec![anchor_htlc::add_lock_sig_evm(
+                    &key(payer),
+                    &key(sender),
+                    instruction::AddLockSigEvm {
+                        Id: id.bytes(),
+                        hashlock: secret.hashlock(),
+                        timelock,
+                        deadline,
+                        r,
+                        s,
+                        v,
+                    },
+                )]
+            }
+            Action::LockReward {
+                delegate,
+                sender,
+                id,
+                mint: choice,
+                reward_timelock,
+                reward,
+            } => {
+                let (mint, token_program) = mint(choice);
+                let args = instruction::LockReward {
+                    Id: id.bytes(),
+                    reward_timelock: reward_timelock.resolve(now),
+                    reward: reward.value(),
+                };
+                vec![match delegate {
+                    Some(delegate) => anchor_htlc::lock_reward_by_session(
+                        &key(delegate),
+                        &key(sender),
+                        &mint,
+                        &token_program,
+                        args,
+                    ),
+                    None => anchor_htlc::lock_reward(&key(sender), &mint, &token_program, args),
+                }]
+            }
+            Action::CreateSession {
+                sender,
+                delegate,
+                mint: choice,
+                expiry,
+                max_amount,
+                permissions,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::create_session(
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::CreateSession {
+                        delegate: key(delegate),
+                        expiry: expiry.resolve(now),
+                        max_amount: max_amount.value(),
+                        permissions: *permissions,
+                    },
+                )]
+            }
+            Action::RevokeSession {
+                sender,
+                delegate,
+                mint: choice,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::revoke_session(
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::RevokeSession {
+                        delegate: key(delegate),
+                    },
+                )]
+            }
+            Action::Redeem {
+                by,
+                sender,
+                receiver,
+                id,
+                mint: choice,
+                secret,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::redeem(
+                    &key(by),
+                    &key(sender),
+                    &key(receiver),
+                    &mint,
+                    &token_program,
+                    instruction::Redeem {
+                        Id: id.bytes(),
+                        secret: secret.bytes(),
+                        htlc_bump: anchor_htlc::find_htlc_address(&key(sender), &id.bytes()).1,
+                    },
+                )]
+            }
+            Action::Refund {
+                by,
+                sender,
+                id,
+                mint: choice,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::refund(
+                    &key(by),
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::Refund {
+                        Id: id.bytes(),
+                        htlc_bump: anchor_htlc::find_htlc_address(&key(sender), &id.bytes()).1,
+                    },
+                )]
+            }
+            Action::BatchLock {
+                sender,
+                mint: choice,
+                entries,
+                sender_pays_fee,
+                best_effort,
+            } => {
+                let (mint, token_program) = mint(choice);
+                vec![anchor_htlc::batch_lock(
+                    &key(sender),
+                    &mint,
+                    &token_program,
+                    instruction::BatchLock {
+                        entries: entries
+                            .iter()
+                            .take(MAX_BATCH)
+                            .map(|(id, receiver, secret, timelock, amount)| LockEntry {
+                                Id: id.bytes(),
+                                hashlock: secret.hashlock(),
+                                timelock: timelock.resolve(now),
+                                dst_chain: "ETHEREUM".to_string(),
+                                dst_address: "0x0".to_string(),
+                                dst_asset: "ETH".to_string(),
+                                src_asset: "USDC".to_string(),
+                                src_receiver: key(receiver),
+                                amount: amount.value(),
+                                fee_payer: fee_payer(*sender_pays_fee),
+                            })
+                            .collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::BatchRedeem {
+                by,
+                mint: choice,
+                entries,
+                best_effort,
+            } => {
+                let (mint, token_program) = mint(choice);
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![anchor_htlc::batch_redeem(
+                    &key(by),
+                    &mint,
+                    &token_program,
+                    &entries
+                        .iter()
+                        .map(|(sender, receiver, _, _)| (key(sender), key(receiver)))
+                        .collect::<Vec<_>>(),
+                    instruction::BatchRedeem {
+                        entries: entries
+                            .iter()
+                            .map(|(_, _, id, secret)| (id.bytes(), secret.bytes()))
+                            .collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::RedeemByHashlock {
+                by,
+                mint: choice,
+                hashlock,
+                secret,
+                proof,
+                entries,
+                best_effort,
+            } => {
+                let (mint, token_program) = mint(choice);
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![anchor_htlc::redeem_by_hashlock(
+                    &key(by),
+                    &mint,
+                    &token_program,
+                    proof.as_ref().map(|(sender, id)| {
+                        anchor_htlc::find_htlc_address(&key(sender), &id.bytes()).0
+                    }),
+                    &entries
+                        .iter()
+                        .map(|(sender, receiver, _)| (key(sender), key(receiver)))
+                        .collect::<Vec<_>>(),
+                    instruction::RedeemByHashlock {
+                        hashlock: hashlock.hashlock(),
+                        secret: secret.map(Secret::bytes),
+                        Ids: entries.iter().map(|(_, _, id)| id.bytes()).collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::BatchRefund {
+                by,
+                mint: choice,
+                entries,
+                best_effort,
+            } => {
+                let (mint, token_program) = mint(choice);
+                let entries = &entries[..entries.len().min(MAX_BATCH)];
+                vec![anchor_htlc::batch_refund(
+                    &key(by),
+                    &mint,
+                    &token_program,
+                    &entries
+                        .iter()
+                        .map(|(sender, _)| key(sender))
+                        .collect::<Vec<_>>(),
+                    instruction::BatchRefund {
+                        Ids: entries.iter().map(|(_, id)| id.bytes()).collect(),
+                        best_effort: *best_effort,
+                    },
+                )]
+            }
+            Action::ReserveId { sender } => vec![anchor_htlc::reserve_id(&key(sender))],
+            Action::Close { sender, id } => vec![anchor_htlc::close(
+                &key(sender),
+                instruction::Close { Id: id.bytes() },
+            )],
+            Action::Warp { .. } => vec![],
+        }
+    }
+}
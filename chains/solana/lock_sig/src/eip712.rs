@@ -0,0 +1,118 @@
+//! The EIP-712 add_lock message of the Solana programs, for add_lock_sig_evm.
+//!
+//! On EVM, addLockSig checks a signature over the typed data
+//! `addLockMsg(bytes32 Id,bytes32 hashlock,uint48 timelock)`. The Solana
+//! programs accept the same struct with a `uint48 deadline`, signed by the
+//! Ethereum address stored on the HTLC. A Solana program has no chain id nor
+//! contract address, so the domain is
+//! `EIP712Domain(string name,string version,bytes32 salt,bytes32 genesisHash)`
+//! with the name "Train", the version "2", the program id as salt and the genesis
+//! hash of the cluster: like add_lock_sig, a signature is only valid for one
+//! program on one cluster, until the deadline, and never for an EVM contract.
+
+use crate::Domain;
+use solana_program::{keccak, secp256k1_recover::secp256k1_recover};
+
+/// Type of the EIP-712 domain of the Solana programs.
+pub const DOMAIN_TYPE: &[u8] =
+    b"EIP712Domain(string name,string version,bytes32 salt,bytes32 genesisHash)";
+/// Type of the signed struct, the one of the EVM contracts with a deadline.
+pub const ADD_LOCK_MSG_TYPE: &[u8] =
+    b"addLockMsg(bytes32 Id,bytes32 hashlock,uint48 timelock,uint48 deadline)";
+/// Name of the EIP-712 domain, as in the EVM contracts.
+pub const DOMAIN_NAME: &[u8] = b"Train";
+/// Version of the EIP-712 domain. Version 1 had neither the genesis hash nor
+/// the deadline.
+pub const DOMAIN_VERSION: &[u8] = b"2";
+/// Largest timelock a uint48 holds.
+pub const MAX_TIMELOCK: u64 = (1 << 48) - 1;
+
+/// Half the order of secp256k1. Signatures with a larger `s` are malleable
+/// copies of another one and rejected, as by OpenZeppelin's ECDSA.
+const HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+fn keccak256(values: &[&[u8]]) -> [u8; 32] {
+    keccak::hashv(values).to_bytes()
+}
+
+/// Abi-encodes an unsigned integer as a 32-byte word.
+fn encode_uint(value: u64) -> [u8; 32] {
+    let mut encoded = [0; 32];
+    encoded[24..].copy_from_slice(&value.to_be_bytes());
+    encoded
+}
+
+/// The domain separator of a program on a cluster.
+pub fn domain_separator(domain: &Domain) -> [u8; 32] {
+    keccak256(&[
+        &keccak256(&[DOMAIN_TYPE]),
+        &keccak256(&[DOMAIN_NAME]),
+        &keccak256(&[DOMAIN_VERSION]),
+        domain.program_id.as_ref(),
+        &domain.genesis_hash,
+    ])
+}
+
+/// hashStruct of addLockMsg. The timelock and the deadline are abi-encoded as
+/// uint48, so they must not be above [`MAX_TIMELOCK`].
+pub fn add_lock_struct_hash(
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> [u8; 32] {
+    keccak256(&[
+        &keccak256(&[ADD_LOCK_MSG_TYPE]),
+        id,
+        hashlock,
+        &encode_uint(timelock),
+        &encode_uint(deadline),
+    ])
+}
+
+/// The digest an Ethereum wallet signs for eth_signTypedData_v4 of addLockMsg.
+pub fn add_lock_digest(
+    domain: &Domain,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> [u8; 32] {
+    keccak256(&[
+        b"\x19\x01",
+        &domain_separator(domain),
+        &add_lock_struct_hash(id, hashlock, timelock, deadline),
+    ])
+}
+
+/// The Ethereum address of an uncompressed secp256k1 public key, without its
+/// 0x04 prefix.
+pub fn evm_address(pubkey: &[u8; 64]) -> [u8; 20] {
+    let hash = keccak256(&[pubkey]);
+    let mut address = [0; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// Recovers the Ethereum address that signed `digest`, like Solidity's
+/// `ECDSA.recover(digest, v, r, s)`: `v` is 27 or 28 and `s` is in the lower
+/// half of the curve order. None if the signature is invalid.
+pub fn recover_evm_address(
+    digest: &[u8; 32],
+    r: &[u8; 32],
+    s: &[u8; 32],
+    v: u8,
+) -> Option<[u8; 20]> {
+    if *s > HALF_ORDER {
+        return None;
+    }
+    let recovery_id = v.checked_sub(27).filter(|id| *id <= 1)?;
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(r);
+    signature[32..].copy_from_slice(s);
+    let pubkey = secp256k1_recover(digest, recovery_id, &signature).ok()?;
+    Some(evm_address(&pubkey.to_bytes()))
+}
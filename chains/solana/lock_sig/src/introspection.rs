@@ -0,0 +1,130 @@
+//! Finding an Ed25519Program verification in the instructions of a transaction.
+//!
+//! The runtime verifies every Ed25519Program instruction of a transaction before
+//! running it, so a program only has to find one that covers the (public key,
+//! message, signature) it expects. An Ed25519Program instruction can sit at any
+//! index, e.g. after compute budget instructions, carries any number of
+//! signatures, and each of them may point at data in another instruction. Every
+//! offset is bounds-checked: malformed data never matches and never panics.
+
+use solana_program::{
+    account_info::AccountInfo,
+    ed25519_program,
+    program_error::ProgramError,
+    sysvar::instructions::{self, load_instruction_at_checked},
+};
+
+/// Size of the count and padding bytes in front of the offsets.
+pub const SIGNATURE_OFFSETS_START: usize = 2;
+/// Size of the offsets of one signature: seven little-endian u16.
+pub const SIGNATURE_OFFSETS_LEN: usize = 14;
+/// Instruction index meaning "the Ed25519Program instruction itself".
+pub const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+/// Offsets of one signature in Ed25519Program instruction data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignatureOffsets {
+    pub signature_offset: u16,
+    pub signature_instruction_index: u16,
+    pub public_key_offset: u16,
+    pub public_key_instruction_index: u16,
+    pub message_data_offset: u16,
+    pub message_data_size: u16,
+    pub message_instruction_index: u16,
+}
+
+/// Parses the offsets of every signature of Ed25519Program instruction data,
+/// or None if the data is too short for the count it declares.
+pub fn signature_offsets(data: &[u8]) -> Option<Vec<SignatureOffsets>> {
+    let count = *data.first()? as usize;
+    let end = SIGNATURE_OFFSETS_START + count * SIGNATURE_OFFSETS_LEN;
+    let offsets = data.get(SIGNATURE_OFFSETS_START..end)?;
+    Some(
+        offsets
+            .chunks_exact(SIGNATURE_OFFSETS_LEN)
+            .map(|chunk| {
+                let field = |i: usize| u16::from_le_bytes([chunk[2 * i], chunk[2 * i + 1]]);
+                SignatureOffsets {
+                    signature_offset: field(0),
+                    signature_instruction_index: field(1),
+                    public_key_offset: field(2),
+                    public_key_instruction_index: field(3),
+                    message_data_offset: field(4),
+                    message_data_size: field(5),
+                    message_instruction_index: field(6),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Whether Ed25519Program instruction `data` verifies `signature` of `message`
+/// by `pubkey`. `instruction_data` returns the data of the instruction at an
+/// index of the transaction, for signatures pointing outside of `data`.
+pub fn verifies(
+    data: &[u8],
+    mut instruction_data: impl FnMut(u16) -> Option<Vec<u8>>,
+    pubkey: &[u8; 32],
+    message: &[u8],
+    signature: &[u8; 64],
+) -> bool {
+    let Some(offsets) = signature_offsets(data) else {
+        return false;
+    };
+    let mut field_matches = |index: u16, offset: u16, expected: &[u8]| {
+        let start = offset as usize;
+        let end = start + expected.len();
+        if index == CURRENT_INSTRUCTION {
+            data.get(start..end) == Some(expected)
+        } else {
+            instruction_data(index).is_some_and(|other| other.get(start..end) == Some(expected))
+        }
+    };
+    offsets.iter().any(|offsets| {
+        offsets.message_data_size as usize == message.len()
+            && field_matches(
+                offsets.public_key_instruction_index,
+                offsets.public_key_offset,
+                pubkey,
+            )
+            && field_matches(
+                offsets.signature_instruction_index,
+                offsets.signature_offset,
+                signature,
+            )
+            && field_matches(
+                offsets.message_instruction_index,
+                offsets.message_data_offset,
+                message,
+            )
+    })
+}
+
+/// Whether an Ed25519Program instruction of the transaction verifies
+/// `signature` of `message` by `pubkey`, scanning every instruction through
+/// the instructions sysvar.
+pub fn find_ed25519_verification(
+    instructions_sysvar: &AccountInfo,
+    pubkey: &[u8; 32],
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<bool, ProgramError> {
+    if *instructions_sysvar.key != instructions::ID {
+        return Err(ProgramError::UnsupportedSysvar);
+    }
+    let load = |index: u16| {
+        load_instruction_at_checked(index as usize, instructions_sysvar)
+            .ok()
+            .map(|instruction| instruction.data)
+    };
+    let mut index = 0;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions_sysvar) {
+        if instruction.program_id == ed25519_program::ID
+            && verifies(&instruction.data, load, pubkey, message, signature)
+        {
+            return Ok(true);
+        }
+        index += 1;
+    }
+    Ok(false)
+}
@@ -0,0 +1,50 @@
+//! The first add_lock_sig message layout, accepted by add_lock_sig_legacy
+//! while senders migrate to the versioned message.
+//!
+//! Its body is sha256(Id || hashlock || timelock as little-endian u64): it is
+//! not bound to a program, a cluster nor a deadline, so a signature for one
+//! HTLC can be replayed against the same Id in the other program or on another
+//! cluster. Programs built without the `legacy-lock-sig` feature reject it.
+
+use sha2::{Digest, Sha256};
+#[cfg(feature = "signer")]
+use solana_program::instruction::Instruction;
+
+use crate::off_chain_message;
+
+/// sha256(Id || hashlock || timelock as little-endian u64), the body of the message.
+pub fn add_lock_hash(id: &[u8; 32], hashlock: &[u8; 32], timelock: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(id);
+    hasher.update(hashlock);
+    hasher.update(timelock.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Builds the legacy message `signer` signs to add a lock: the off-chain message
+/// header followed by [`add_lock_hash`], hex-encoded, as bytes.
+pub fn add_lock_message(
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    signer: &[u8; 32],
+) -> Vec<u8> {
+    off_chain_message(&add_lock_hash(id, hashlock, timelock), signer)
+}
+
+/// Signs the legacy add_lock_sig message for `Id`, `hashlock` and `timelock`
+/// with `signer`, the sender of the HTLC.
+///
+/// Returns the signature to pass to add_lock_sig_legacy and the Ed25519Program
+/// instruction to put anywhere in the same transaction.
+#[cfg(feature = "signer")]
+pub fn sign_add_lock(
+    signer: &dyn solana_signer::Signer,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+) -> Result<([u8; 64], Instruction), solana_signer::SignerError> {
+    let pubkey = signer.try_pubkey()?;
+    let message = add_lock_message(id, hashlock, timelock, &pubkey.to_bytes());
+    crate::sign_message(signer, pubkey, &message)
+}
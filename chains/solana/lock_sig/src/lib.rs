@@ -0,0 +1,196 @@
+//! The add_lock_sig message of the Train Solana HTLC programs.
+//!
+//! add_lock_sig lets anyone set the hashlock and timelock of a committed HTLC with
+//! a signature of its sender. The sender signs a Solana off-chain message whose
+//! body is [`add_lock_hash`], and the transaction carries an Ed25519Program
+//! instruction verifying that signature. The programs rebuild the message with
+//! [`add_lock_message`] and look for an Ed25519Program instruction covering it
+//! with [`introspection`].
+//!
+//! The message is versioned and bound to a [`Domain`], the program id and the
+//! genesis hash of the cluster, to the name of the instruction and to a
+//! deadline: a signature is only valid for one program on one cluster, until
+//! the deadline. The first layout, which covers Id, hashlock and timelock only,
+//! is in [`legacy`] and still accepted by add_lock_sig_legacy while senders
+//! migrate.
+//!
+//! With the `signer` feature, [`sign_add_lock`] signs the message with any
+//! `solana_signer::Signer`, e.g. a `Keypair`, and returns the Ed25519Program
+//! instruction to put in the same transaction as add_lock_sig.
+//!
+//! add_lock_sig_evm takes a secp256k1 signature of an EIP-712 message instead,
+//! bound to the same domain and deadline, see [`eip712`].
+
+pub mod eip712;
+pub mod introspection;
+pub mod legacy;
+
+use sha2::{Digest, Sha256};
+use solana_program::{ed25519_program, instruction::Instruction, pubkey, pubkey::Pubkey};
+
+/// Signing domain prefix of Solana off-chain messages.
+pub const SIGNING_DOMAIN: &[u8; 16] = b"\xffsolana offchain";
+/// Header version of the off-chain message.
+pub const HEADER_VERSION: u8 = 0;
+/// Application domain, "Train" padded with zeros to 32 bytes.
+pub const APPLICATION_DOMAIN: [u8; 32] =
+    *b"Train\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+/// Message format, restricted ASCII.
+pub const MESSAGE_FORMAT: u8 = 0;
+/// Number of signers of the off-chain message.
+pub const SIGNER_COUNT: u8 = 1;
+
+/// Size of the Ed25519Program instruction header for one signature.
+pub const ED25519_HEADER_LEN: usize = 16;
+/// Offset of the public key in the Ed25519Program instruction data.
+pub const ED25519_PUBKEY_OFFSET: usize = ED25519_HEADER_LEN;
+/// Offset of the signature in the Ed25519Program instruction data.
+pub const ED25519_SIGNATURE_OFFSET: usize = ED25519_PUBKEY_OFFSET + 32;
+/// Offset of the message in the Ed25519Program instruction data.
+pub const ED25519_MESSAGE_OFFSET: usize = ED25519_SIGNATURE_OFFSET + 64;
+
+/// Version of the add_lock_sig message layout, the first byte of [`add_lock_hash`]'s input.
+pub const MESSAGE_VERSION: u8 = 1;
+/// Name of the instruction the message authorizes.
+pub const ADD_LOCK_SIG: &str = "add_lock_sig";
+
+/// Genesis hash of mainnet-beta.
+pub const MAINNET_BETA_GENESIS_HASH: [u8; 32] =
+    pubkey!("5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d").to_bytes();
+/// Genesis hash of devnet.
+pub const DEVNET_GENESIS_HASH: [u8; 32] =
+    pubkey!("EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG").to_bytes();
+/// Genesis hash of testnet.
+pub const TESTNET_GENESIS_HASH: [u8; 32] =
+    pubkey!("4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY").to_bytes();
+
+/// Where a signature is valid: one program on one cluster.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Domain {
+    pub program_id: Pubkey,
+    pub genesis_hash: [u8; 32],
+}
+
+/// The body of the message: sha256 of
+/// `version || program id || genesis hash || name length as u8 || name || Id ||
+/// hashlock || timelock || deadline`, with integers as little-endian u64.
+pub fn add_lock_hash(
+    domain: &Domain,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([MESSAGE_VERSION]);
+    hasher.update(domain.program_id);
+    hasher.update(domain.genesis_hash);
+    hasher.update([ADD_LOCK_SIG.len() as u8]);
+    hasher.update(ADD_LOCK_SIG);
+    hasher.update(id);
+    hasher.update(hashlock);
+    hasher.update(timelock.to_le_bytes());
+    hasher.update(deadline.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Builds the message `signer` signs to add a lock: the off-chain message header
+/// followed by [`add_lock_hash`], hex-encoded, as bytes.
+pub fn add_lock_message(
+    domain: &Domain,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+    signer: &[u8; 32],
+) -> Vec<u8> {
+    off_chain_message(
+        &add_lock_hash(domain, id, hashlock, timelock, deadline),
+        signer,
+    )
+}
+
+/// Wraps `hash` in a Solana off-chain message signed by `signer`, hex-encoded.
+pub(crate) fn off_chain_message(hash: &[u8; 32], signer: &[u8; 32]) -> Vec<u8> {
+    let mut raw_message = Vec::with_capacity(16 + 1 + 32 + 1 + 1 + 32 + 2 + hash.len());
+    raw_message.extend_from_slice(SIGNING_DOMAIN);
+    raw_message.push(HEADER_VERSION);
+    raw_message.extend_from_slice(&APPLICATION_DOMAIN);
+    raw_message.push(MESSAGE_FORMAT);
+    raw_message.push(SIGNER_COUNT);
+    raw_message.extend_from_slice(signer);
+    raw_message.extend_from_slice(&(hash.len() as u16).to_le_bytes());
+    raw_message.extend_from_slice(hash);
+
+    hex::encode(raw_message).into_bytes()
+}
+
+/// Lays out the data of an Ed25519Program instruction verifying one signature,
+/// with the public key, signature and message inline.
+///
+/// Panics if `message` is longer than u16::MAX bytes.
+pub fn ed25519_instruction_data(
+    pubkey: &[u8; 32],
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Vec<u8> {
+    let message_size = u16::try_from(message.len()).expect("message too long");
+    let mut data = Vec::with_capacity(ED25519_MESSAGE_OFFSET + message.len());
+    data.push(1); // Number of signatures
+    data.push(0); // Padding
+    data.extend_from_slice(&(ED25519_SIGNATURE_OFFSET as u16).to_le_bytes());
+    data.extend_from_slice(&u16::MAX.to_le_bytes()); // Signature in this instruction
+    data.extend_from_slice(&(ED25519_PUBKEY_OFFSET as u16).to_le_bytes());
+    data.extend_from_slice(&u16::MAX.to_le_bytes()); // Public key in this instruction
+    data.extend_from_slice(&(ED25519_MESSAGE_OFFSET as u16).to_le_bytes());
+    data.extend_from_slice(&message_size.to_le_bytes());
+    data.extend_from_slice(&u16::MAX.to_le_bytes()); // Message in this instruction
+    data.extend_from_slice(pubkey);
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+    data
+}
+
+/// Builds the Ed25519Program instruction verifying `signature` of `message` by `pubkey`.
+pub fn new_ed25519_instruction(
+    pubkey: &Pubkey,
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Instruction {
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: vec![],
+        data: ed25519_instruction_data(&pubkey.to_bytes(), signature, message),
+    }
+}
+
+/// Signs the add_lock_sig message for `Id`, `hashlock` and `timelock` in
+/// `domain`, valid until `deadline`, with `signer`, the sender of the HTLC.
+///
+/// Returns the signature to pass to add_lock_sig and the Ed25519Program
+/// instruction to put anywhere in the same transaction.
+#[cfg(feature = "signer")]
+pub fn sign_add_lock(
+    signer: &dyn solana_signer::Signer,
+    domain: &Domain,
+    id: &[u8; 32],
+    hashlock: &[u8; 32],
+    timelock: u64,
+    deadline: u64,
+) -> Result<([u8; 64], Instruction), solana_signer::SignerError> {
+    let pubkey = signer.try_pubkey()?;
+    let message = add_lock_message(domain, id, hashlock, timelock, deadline, &pubkey.to_bytes());
+    sign_message(signer, pubkey, &message)
+}
+
+/// Signs `message` and builds the Ed25519Program instruction verifying it.
+#[cfg(feature = "signer")]
+pub(crate) fn sign_message(
+    signer: &dyn solana_signer::Signer,
+    pubkey: Pubkey,
+    message: &[u8],
+) -> Result<([u8; 64], Instruction), solana_signer::SignerError> {
+    let signature: [u8; 64] = signer.try_sign_message(message)?.into();
+    let instruction = new_ed25519_instruction(&pubkey, &signature, message);
+    Ok((signature, instruction))
+}
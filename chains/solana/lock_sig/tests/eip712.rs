@@ -0,0 +1,161 @@
+//! Golden vectors of the EIP-712 add_lock message, signed with the secp256k1
+//! key 0x01..0x20, for each program on devnet.
+
+use solana_program::pubkey::Pubkey;
+use std::str::FromStr;
+use train_lock_sig::{eip712::*, Domain, DEVNET_GENESIS_HASH, MAINNET_BETA_GENESIS_HASH};
+
+/// Ethereum address of the key 0x01..0x20.
+const ADDRESS: &str = "6370ef2f4db3611d657b90667de398a2cc2a370c";
+const ID: [u8; 32] = [0x11; 32];
+const HASHLOCK: &str = "9f72ea0cf49536e3c66c787f705186df9a4378083753ae9536d65b3ad7fcddc4";
+const TIMELOCK: u64 = 1_700_000_900;
+const DEADLINE: u64 = 1_700_000_600;
+const STRUCT_HASH: &str = "f0f8e3334a7879bcfdaca8a2f59ddb1210315704fa8c4f961b037e22d2545da1";
+
+/// (program id, domain separator, digest, r || s, v) of each program.
+const PROGRAMS: [(&str, &str, &str, &str, u8); 2] = [
+    (
+        "M4q843mQwjoTn52Ks5HsmZV8bq8ZtA2XgAWW5pHhwdM",
+        "b4d53ca53aa24dd1e51e0f8b9d6c89dadc376b560540b011c6e801726d3a65bb",
+        "44ba7a5e52de7c8bbae2395b272e844665c1452de2520879ae162c55c4608dcc",
+        "ab771aa391ac4f10fcfd1fcb720fd2b783b001f6930d1a6c505371eaa692eceb6da587df39d6771fc903bef437b46a9e77bbd4977c2493015ee43f1f4dd2df6e",
+        27,
+    ),
+    (
+        "AN8Y7CGKNQcBCLxeNmnob786v8EkWhrGBBriPD2JzjK8",
+        "4dfe8f2713877b41231d8eb45628ac1bcb615708203ecd3be836f922a6c14a29",
+        "3996c61672b1b8d88e204404bea74ddff2766ee3b98b898ef933e898246854fd",
+        "adf4aa2be667096cefb2464551cf0116a7efd8b862a4517d47bc9e16612d380c4eabc07252ecfe1aa583636fc547e423511651b39fc377d2659c2fd1faae043d",
+        28,
+    ),
+];
+
+/// secp256k1 order.
+const ORDER: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+fn bytes32(hex_str: &str) -> [u8; 32] {
+    hex::decode(hex_str).unwrap().try_into().unwrap()
+}
+
+fn devnet(program_id: &str) -> Domain {
+    Domain {
+        program_id: Pubkey::from_str(program_id).unwrap(),
+        genesis_hash: DEVNET_GENESIS_HASH,
+    }
+}
+
+fn address() -> [u8; 20] {
+    hex::decode(ADDRESS).unwrap().try_into().unwrap()
+}
+
+fn rs(hex_str: &str) -> ([u8; 32], [u8; 32]) {
+    let bytes = hex::decode(hex_str).unwrap();
+    (
+        bytes[..32].try_into().unwrap(),
+        bytes[32..].try_into().unwrap(),
+    )
+}
+
+/// n - s, the other `s` of the same signature.
+fn negate(s: &[u8; 32]) -> [u8; 32] {
+    let order = bytes32(ORDER);
+    let mut out = [0; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut byte = order[i] as i16 - s[i] as i16 - borrow;
+        borrow = (byte < 0) as i16;
+        if byte < 0 {
+            byte += 256;
+        }
+        out[i] = byte as u8;
+    }
+    out
+}
+
+#[test]
+fn hashes_the_struct_with_its_deadline() {
+    assert_eq!(
+        add_lock_struct_hash(&ID, &bytes32(HASHLOCK), TIMELOCK, DEADLINE),
+        bytes32(STRUCT_HASH)
+    );
+    assert_ne!(
+        add_lock_struct_hash(&ID, &bytes32(HASHLOCK), TIMELOCK, DEADLINE + 1),
+        bytes32(STRUCT_HASH)
+    );
+}
+
+#[test]
+fn separates_the_domain_of_each_program_and_cluster() {
+    for (program_id, domain, digest, _, _) in PROGRAMS {
+        assert_eq!(domain_separator(&devnet(program_id)), bytes32(domain));
+        assert_eq!(
+            add_lock_digest(
+                &devnet(program_id),
+                &ID,
+                &bytes32(HASHLOCK),
+                TIMELOCK,
+                DEADLINE
+            ),
+            bytes32(digest)
+        );
+        let mainnet_beta = Domain {
+            genesis_hash: MAINNET_BETA_GENESIS_HASH,
+            ..devnet(program_id)
+        };
+        assert_ne!(domain_separator(&mainnet_beta), bytes32(domain));
+    }
+}
+
+#[test]
+fn recovers_the_signer() {
+    for (_, _, digest, signature, v) in PROGRAMS {
+        let (r, s) = rs(signature);
+        assert_eq!(
+            recover_evm_address(&bytes32(digest), &r, &s, v),
+            Some(address())
+        );
+        assert_ne!(
+            recover_evm_address(&bytes32(digest), &r, &s, 55 - v),
+            Some(address())
+        );
+    }
+
+    // Signed for the native program, checked against the token program.
+    let (r, s) = rs(PROGRAMS[0].3);
+    assert_ne!(
+        recover_evm_address(&bytes32(PROGRAMS[1].2), &r, &s, 27),
+        Some(address())
+    );
+}
+
+#[test]
+fn rejects_malleable_and_malformed_signatures() {
+    let (_, _, digest, signature, v) = PROGRAMS[0];
+    let (r, s) = rs(signature);
+
+    // The same signature with the upper `s` recovers the same key, but is rejected.
+    assert_eq!(
+        recover_evm_address(&bytes32(digest), &r, &negate(&s), 55 - v),
+        None
+    );
+    for v in [0, 1, 26, 29, 255] {
+        assert_eq!(recover_evm_address(&bytes32(digest), &r, &s, v), None);
+    }
+    assert_eq!(recover_evm_address(&bytes32(digest), &[0; 32], &s, v), None);
+    assert_eq!(recover_evm_address(&bytes32(digest), &r, &[0; 32], v), None);
+}
+
+#[test]
+fn derives_the_address_of_a_public_key() {
+    // The public key of the secret key 1, the generator of secp256k1.
+    let generator = hex::decode(
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+         483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8",
+    )
+    .unwrap();
+    assert_eq!(
+        hex::encode(evm_address(&generator.try_into().unwrap())),
+        "7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+    );
+}
@@ -0,0 +1,210 @@
+//! Golden vectors of the add_lock_sig message, signed with the ed25519 key whose
+//! seed is 0x00..0x1f, for the native program on devnet, and of the legacy
+//! message.
+
+use solana_program::pubkey::Pubkey;
+use train_lock_sig::*;
+
+const PUBKEY: &str = "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8";
+const ID: [u8; 32] = [0x11; 32];
+/// sha256 of a secret of 32 0x22 bytes.
+const HASHLOCK: &str = "9f72ea0cf49536e3c66c787f705186df9a4378083753ae9536d65b3ad7fcddc4";
+const TIMELOCK: u64 = 1_700_000_900;
+const DEADLINE: u64 = 1_700_000_600;
+const PROGRAM_ID: Pubkey = solana_program::pubkey!("M4q843mQwjoTn52Ks5HsmZV8bq8ZtA2XgAWW5pHhwdM");
+const HASH: &str = "ba3f3afd9e5f36fa790dd130e36db8a5cd9727aca1a040986607398e4e4421ae";
+const MESSAGE: &str = "ff736f6c616e61206f6666636861696e00547261696e000000000000000000000000000000000000000000000000000000000103a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b82000ba3f3afd9e5f36fa790dd130e36db8a5cd9727aca1a040986607398e4e4421ae";
+const SIGNATURE: &str = "3ed4eaaeced5be4c5c921f96b56b48266fa12632a983954be8b7c423f824fead5bd0e523bdfaec12dbe681a42d94724efd2605d386dbb27d01cc089590fc0c0b";
+const LEGACY_HASH: &str = "5b92d33fbaf31c4280110d3c056e43b2b27fc3ffd38fcc6f01bdf838729fc582";
+const LEGACY_MESSAGE: &str = "ff736f6c616e61206f6666636861696e00547261696e000000000000000000000000000000000000000000000000000000000103a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b820005b92d33fbaf31c4280110d3c056e43b2b27fc3ffd38fcc6f01bdf838729fc582";
+const LEGACY_SIGNATURE: &str = "adca1261008e57730b60e3ce4cfd716d29a1c23a17f27c1a523eb99dd36a116b63e94511641fd3b7d8498513c69d43166e378b00d6e2a1263539c3028678ba00";
+/// Ed25519Program header: 1 signature, pubkey at 16, signature at 48 and a
+/// 234 byte message at 112, all in the same instruction.
+const HEADER: &str = "01003000ffff1000ffff7000ea00ffff";
+
+fn bytes32(hex_str: &str) -> [u8; 32] {
+    hex::decode(hex_str).unwrap().try_into().unwrap()
+}
+
+fn signature() -> [u8; 64] {
+    hex::decode(SIGNATURE).unwrap().try_into().unwrap()
+}
+
+fn legacy_signature() -> [u8; 64] {
+    hex::decode(LEGACY_SIGNATURE).unwrap().try_into().unwrap()
+}
+
+fn domain() -> Domain {
+    Domain {
+        program_id: PROGRAM_ID,
+        genesis_hash: DEVNET_GENESIS_HASH,
+    }
+}
+
+#[test]
+fn decodes_the_genesis_hashes() {
+    assert_eq!(
+        hex::encode(DEVNET_GENESIS_HASH),
+        "ce59db5080fc2c6d3bcf7ca90712d3c2e5e6c28f27f0dfbb9953bdb0894c03ab"
+    );
+    assert_ne!(MAINNET_BETA_GENESIS_HASH, DEVNET_GENESIS_HASH);
+    assert_ne!(MAINNET_BETA_GENESIS_HASH, TESTNET_GENESIS_HASH);
+    assert_ne!(DEVNET_GENESIS_HASH, TESTNET_GENESIS_HASH);
+}
+
+#[test]
+fn hashes_the_domain_the_name_and_the_lock() {
+    assert_eq!(
+        add_lock_hash(&domain(), &ID, &bytes32(HASHLOCK), TIMELOCK, DEADLINE),
+        bytes32(HASH)
+    );
+}
+
+#[test]
+fn binds_every_field() {
+    let hash = |domain: &Domain, id: &[u8; 32], timelock, deadline| {
+        add_lock_hash(domain, id, &bytes32(HASHLOCK), timelock, deadline)
+    };
+    let golden = bytes32(HASH);
+    let other_program = Domain {
+        program_id: Pubkey::new_unique(),
+        ..domain()
+    };
+    let other_cluster = Domain {
+        genesis_hash: MAINNET_BETA_GENESIS_HASH,
+        ..domain()
+    };
+    assert_ne!(hash(&other_program, &ID, TIMELOCK, DEADLINE), golden);
+    assert_ne!(hash(&other_cluster, &ID, TIMELOCK, DEADLINE), golden);
+    assert_ne!(hash(&domain(), &[0x12; 32], TIMELOCK, DEADLINE), golden);
+    assert_ne!(hash(&domain(), &ID, TIMELOCK + 1, DEADLINE), golden);
+    assert_ne!(hash(&domain(), &ID, TIMELOCK, DEADLINE + 1), golden);
+    assert_ne!(golden, bytes32(LEGACY_HASH));
+}
+
+#[test]
+fn builds_the_off_chain_message() {
+    let message = add_lock_message(
+        &domain(),
+        &ID,
+        &bytes32(HASHLOCK),
+        TIMELOCK,
+        DEADLINE,
+        &bytes32(PUBKEY),
+    );
+    assert_eq!(message, MESSAGE.as_bytes());
+
+    let raw = hex::decode(&message).unwrap();
+    assert_eq!(&raw[..16], SIGNING_DOMAIN);
+    assert_eq!(raw[16], HEADER_VERSION);
+    assert_eq!(&raw[17..49], &APPLICATION_DOMAIN);
+    assert_eq!(raw[49], MESSAGE_FORMAT);
+    assert_eq!(raw[50], SIGNER_COUNT);
+    assert_eq!(&raw[51..83], &bytes32(PUBKEY));
+    assert_eq!(&raw[83..85], &32u16.to_le_bytes());
+    assert_eq!(&raw[85..], &bytes32(HASH));
+}
+
+#[test]
+fn builds_the_legacy_message() {
+    assert_eq!(
+        legacy::add_lock_hash(&ID, &bytes32(HASHLOCK), TIMELOCK),
+        bytes32(LEGACY_HASH)
+    );
+    assert_eq!(
+        legacy::add_lock_message(&ID, &bytes32(HASHLOCK), TIMELOCK, &bytes32(PUBKEY)),
+        LEGACY_MESSAGE.as_bytes()
+    );
+}
+
+#[test]
+fn lays_out_the_ed25519_instruction() {
+    let data = ed25519_instruction_data(&bytes32(PUBKEY), &signature(), MESSAGE.as_bytes());
+    assert_eq!(hex::encode(&data[..ED25519_HEADER_LEN]), HEADER);
+    assert_eq!(
+        &data[ED25519_PUBKEY_OFFSET..ED25519_SIGNATURE_OFFSET],
+        &bytes32(PUBKEY)
+    );
+    assert_eq!(
+        &data[ED25519_SIGNATURE_OFFSET..ED25519_MESSAGE_OFFSET],
+        &signature()
+    );
+    assert_eq!(&data[ED25519_MESSAGE_OFFSET..], MESSAGE.as_bytes());
+}
+
+#[test]
+fn lays_out_the_legacy_ed25519_instruction() {
+    let data = ed25519_instruction_data(
+        &bytes32(PUBKEY),
+        &legacy_signature(),
+        LEGACY_MESSAGE.as_bytes(),
+    );
+    assert_eq!(hex::encode(&data[..ED25519_HEADER_LEN]), HEADER);
+    assert_eq!(
+        &data[ED25519_SIGNATURE_OFFSET..ED25519_MESSAGE_OFFSET],
+        &legacy_signature()
+    );
+    assert_eq!(&data[ED25519_MESSAGE_OFFSET..], LEGACY_MESSAGE.as_bytes());
+}
+
+#[cfg(feature = "signer")]
+mod signer {
+    use super::*;
+    use solana_program::{ed25519_program, pubkey::Pubkey};
+    use solana_signature::Signature;
+    use solana_signer::{Signer, SignerError};
+
+    /// Signer returning the golden signatures, for the golden messages only.
+    struct GoldenSigner;
+
+    impl Signer for GoldenSigner {
+        fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+            Ok(Pubkey::new_from_array(bytes32(PUBKEY)))
+        }
+
+        fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+            let signature = if message == MESSAGE.as_bytes() {
+                super::signature()
+            } else {
+                assert_eq!(message, LEGACY_MESSAGE.as_bytes());
+                legacy_signature()
+            };
+            Ok(Signature::from(signature))
+        }
+
+        fn is_interactive(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn signs_add_lock() {
+        let (signature, instruction) = sign_add_lock(
+            &GoldenSigner,
+            &domain(),
+            &ID,
+            &bytes32(HASHLOCK),
+            TIMELOCK,
+            DEADLINE,
+        )
+        .unwrap();
+        assert_eq!(signature, super::signature());
+        assert_eq!(instruction.program_id, ed25519_program::ID);
+        assert!(instruction.accounts.is_empty());
+        assert_eq!(
+            instruction.data,
+            ed25519_instruction_data(&bytes32(PUBKEY), &signature, MESSAGE.as_bytes())
+        );
+    }
+
+    #[test]
+    fn signs_the_legacy_message() {
+        let (signature, instruction) =
+            legacy::sign_add_lock(&GoldenSigner, &ID, &bytes32(HASHLOCK), TIMELOCK).unwrap();
+        assert_eq!(signature, legacy_signature());
+        assert_eq!(
+            instruction.data,
+            ed25519_instruction_data(&bytes32(PUBKEY), &signature, LEGACY_MESSAGE.as_bytes())
+        );
+    }
+}
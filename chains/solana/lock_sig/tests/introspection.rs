@@ -0,0 +1,312 @@
+//! Finding the Ed25519Program verification of the add_lock_sig message in the
+//! instructions of a transaction, with the golden vector of golden.rs.
+
+use solana_program::{
+    account_info::AccountInfo,
+    ed25519_program,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::instructions::{self, construct_instructions_data, BorrowedInstruction},
+};
+use train_lock_sig::introspection::*;
+use train_lock_sig::*;
+
+const PUBKEY: &str = "03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8";
+const MESSAGE: &str = "ff736f6c616e61206f6666636861696e00547261696e000000000000000000000000000000000000000000000000000000000103a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b820005b92d33fbaf31c4280110d3c056e43b2b27fc3ffd38fcc6f01bdf838729fc582";
+const SIGNATURE: &str = "adca1261008e57730b60e3ce4cfd716d29a1c23a17f27c1a523eb99dd36a116b63e94511641fd3b7d8498513c69d43166e378b00d6e2a1263539c3028678ba00";
+
+fn pubkey() -> [u8; 32] {
+    hex::decode(PUBKEY).unwrap().try_into().unwrap()
+}
+
+fn signature() -> [u8; 64] {
+    hex::decode(SIGNATURE).unwrap().try_into().unwrap()
+}
+
+fn golden_data() -> Vec<u8> {
+    ed25519_instruction_data(&pubkey(), &signature(), MESSAGE.as_bytes())
+}
+
+fn offsets_bytes(offsets: &SignatureOffsets) -> Vec<u8> {
+    [
+        offsets.signature_offset,
+        offsets.signature_instruction_index,
+        offsets.public_key_offset,
+        offsets.public_key_instruction_index,
+        offsets.message_data_offset,
+        offsets.message_data_size,
+        offsets.message_instruction_index,
+    ]
+    .iter()
+    .flat_map(|field| field.to_le_bytes())
+    .collect()
+}
+
+/// Ed25519Program data with the given offsets followed by `payload`.
+fn data_with(offsets: &[SignatureOffsets], payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![offsets.len() as u8, 0];
+    for offsets in offsets {
+        data.extend(offsets_bytes(offsets));
+    }
+    data.extend_from_slice(payload);
+    data
+}
+
+fn verifies_inline(data: &[u8]) -> bool {
+    verifies(data, |_| None, &pubkey(), MESSAGE.as_bytes(), &signature())
+}
+
+#[test]
+fn parses_the_offsets_of_the_golden_instruction() {
+    assert_eq!(
+        signature_offsets(&golden_data()).unwrap(),
+        vec![SignatureOffsets {
+            signature_offset: ED25519_SIGNATURE_OFFSET as u16,
+            signature_instruction_index: CURRENT_INSTRUCTION,
+            public_key_offset: ED25519_PUBKEY_OFFSET as u16,
+            public_key_instruction_index: CURRENT_INSTRUCTION,
+            message_data_offset: ED25519_MESSAGE_OFFSET as u16,
+            message_data_size: MESSAGE.len() as u16,
+            message_instruction_index: CURRENT_INSTRUCTION,
+        }]
+    );
+    assert!(verifies_inline(&golden_data()));
+}
+
+#[test]
+fn rejects_another_message_key_or_signature() {
+    let data = golden_data();
+    let mut other_message = MESSAGE.as_bytes().to_vec();
+    other_message[0] ^= 1;
+    assert!(!verifies(
+        &data,
+        |_| None,
+        &pubkey(),
+        &other_message,
+        &signature()
+    ));
+    assert!(!verifies(
+        &data,
+        |_| None,
+        &[0; 32],
+        MESSAGE.as_bytes(),
+        &signature()
+    ));
+    assert!(!verifies(
+        &data,
+        |_| None,
+        &pubkey(),
+        MESSAGE.as_bytes(),
+        &[0; 64]
+    ));
+    assert!(!verifies(
+        &data,
+        |_| None,
+        &pubkey(),
+        &MESSAGE.as_bytes()[..MESSAGE.len() - 1],
+        &signature()
+    ));
+}
+
+#[test]
+fn finds_the_signature_among_several() {
+    let header_len = SIGNATURE_OFFSETS_START + 2 * SIGNATURE_OFFSETS_LEN;
+    let other = |offset: usize| SignatureOffsets {
+        public_key_offset: offset as u16,
+        public_key_instruction_index: CURRENT_INSTRUCTION,
+        signature_offset: (offset + 32) as u16,
+        signature_instruction_index: CURRENT_INSTRUCTION,
+        message_data_offset: (offset + 96) as u16,
+        message_data_size: 4,
+        message_instruction_index: CURRENT_INSTRUCTION,
+    };
+    let golden = SignatureOffsets {
+        public_key_offset: (header_len + 100) as u16,
+        public_key_instruction_index: CURRENT_INSTRUCTION,
+        signature_offset: (header_len + 132) as u16,
+        signature_instruction_index: CURRENT_INSTRUCTION,
+        message_data_offset: (header_len + 196) as u16,
+        message_data_size: MESSAGE.len() as u16,
+        message_instruction_index: CURRENT_INSTRUCTION,
+    };
+    let mut payload = vec![0x42; 100];
+    payload.extend(pubkey());
+    payload.extend(signature());
+    payload.extend(MESSAGE.as_bytes());
+
+    assert!(verifies_inline(&data_with(
+        &[other(header_len), golden],
+        &payload
+    )));
+    assert!(verifies_inline(&data_with(
+        &[golden, other(header_len)],
+        &payload
+    )));
+    assert!(!verifies_inline(&data_with(
+        &[other(header_len), other(header_len)],
+        &payload
+    )));
+}
+
+#[test]
+fn follows_offsets_into_other_instructions() {
+    let mut other_instruction = vec![0x42; 7];
+    other_instruction.extend(pubkey());
+    other_instruction.extend(MESSAGE.as_bytes());
+    let offsets = SignatureOffsets {
+        public_key_offset: 7,
+        public_key_instruction_index: 3,
+        signature_offset: (SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_LEN) as u16,
+        signature_instruction_index: CURRENT_INSTRUCTION,
+        message_data_offset: 39,
+        message_data_size: MESSAGE.len() as u16,
+        message_instruction_index: 3,
+    };
+    let data = data_with(&[offsets], &signature());
+
+    let instruction_data = |index: u16| (index == 3).then(|| other_instruction.clone());
+    assert!(verifies(
+        &data,
+        instruction_data,
+        &pubkey(),
+        MESSAGE.as_bytes(),
+        &signature()
+    ));
+    assert!(!verifies_inline(&data));
+    assert!(!verifies(
+        &data,
+        |_| Some(other_instruction[..other_instruction.len() - 1].to_vec()),
+        &pubkey(),
+        MESSAGE.as_bytes(),
+        &signature()
+    ));
+}
+
+#[test]
+fn malformed_data_never_verifies() {
+    let data = golden_data();
+    for len in 0..data.len() {
+        assert!(!verifies_inline(&data[..len]), "truncated to {len}");
+    }
+
+    // Declares a second signature without its offsets.
+    let mut data = golden_data();
+    data[0] = 2;
+    assert!(signature_offsets(&data[..SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_LEN]).is_none());
+
+    // Offsets past the end of the data, an unknown instruction index and
+    // another message size.
+    for (field, value) in [
+        (0, u16::MAX),
+        (1, 3),
+        (2, u16::MAX),
+        (3, 3),
+        (4, u16::MAX),
+        (5, 1),
+        (6, 3),
+    ] {
+        let mut data = golden_data();
+        let at = SIGNATURE_OFFSETS_START + 2 * field;
+        data[at..at + 2].copy_from_slice(&value.to_le_bytes());
+        assert!(!verifies_inline(&data), "field {field}");
+    }
+
+    // No signatures at all.
+    assert!(!verifies_inline(&[0, 0]));
+}
+
+fn instructions_data(instructions: &[(Pubkey, Vec<u8>)]) -> Vec<u8> {
+    let borrowed: Vec<BorrowedInstruction> = instructions
+        .iter()
+        .map(|(program_id, data)| BorrowedInstruction {
+            program_id,
+            accounts: vec![],
+            data,
+        })
+        .collect();
+    construct_instructions_data(&borrowed)
+}
+
+fn find(key: Pubkey, instructions: &[(Pubkey, Vec<u8>)]) -> Result<bool, ProgramError> {
+    let mut lamports = 0;
+    let mut data = instructions_data(instructions);
+    let owner = Pubkey::default();
+    let account = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    find_ed25519_verification(&account, &pubkey(), MESSAGE.as_bytes(), &signature())
+}
+
+#[test]
+fn finds_the_verification_at_any_index() {
+    let compute_budget = Pubkey::new_unique();
+    let htlc = Pubkey::new_unique();
+    let ed25519 = (ed25519_program::ID, golden_data());
+    assert!(find(instructions::ID, &[ed25519.clone(), (htlc, vec![1])]).unwrap());
+    assert!(find(
+        instructions::ID,
+        &[
+            (compute_budget, vec![2, 0, 0, 0]),
+            (compute_budget, vec![3, 0, 0, 0]),
+            ed25519.clone(),
+            (htlc, vec![1]),
+        ]
+    )
+    .unwrap());
+    assert!(find(instructions::ID, &[(htlc, vec![1]), ed25519.clone()]).unwrap());
+}
+
+#[test]
+fn finds_a_verification_pointing_at_another_instruction() {
+    let htlc = Pubkey::new_unique();
+    let offsets = SignatureOffsets {
+        public_key_offset: 0,
+        public_key_instruction_index: 1,
+        signature_offset: 32,
+        signature_instruction_index: 1,
+        message_data_offset: 96,
+        message_data_size: MESSAGE.len() as u16,
+        message_instruction_index: 1,
+    };
+    let mut memo = pubkey().to_vec();
+    memo.extend(signature());
+    memo.extend(MESSAGE.as_bytes());
+    assert!(find(
+        instructions::ID,
+        &[
+            (ed25519_program::ID, data_with(&[offsets], &[])),
+            (Pubkey::new_unique(), memo),
+            (htlc, vec![1]),
+        ]
+    )
+    .unwrap());
+}
+
+#[test]
+fn ignores_the_data_of_other_programs() {
+    let htlc = Pubkey::new_unique();
+    assert!(!find(
+        instructions::ID,
+        &[(Pubkey::new_unique(), golden_data()), (htlc, vec![1])]
+    )
+    .unwrap());
+    assert!(!find(instructions::ID, &[(htlc, vec![1])]).unwrap());
+}
+
+#[test]
+fn rejects_another_sysvar() {
+    assert_eq!(
+        find(
+            Pubkey::new_unique(),
+            &[(ed25519_program::ID, golden_data())]
+        ),
+        Err(ProgramError::UnsupportedSysvar)
+    );
+}
@@ -1,4 +1,4 @@
-// import assert from "assert";
+import assert from "assert";
 import * as anchor from "@coral-xyz/anchor";
 import { randomBytes, createHash } from "crypto";
 import * as ed from '@noble/ed25519';
@@ -31,9 +31,10 @@ describe("HTLC", () => {
 
     const getPdaParams = async (
         Id: Buffer,
+        sender: anchor.web3.PublicKey = wallet.publicKey,
     ): Promise<PDAParameters> => {
         let [htlc, _] = await anchor.web3.PublicKey.findProgramAddressSync(
-            [Id],
+            [Buffer.from("htlc"), sender.toBuffer(), Id],
             program.programId
         );
 
@@ -42,6 +43,15 @@ describe("HTLC", () => {
         };
     };
 
+    const getSecretRecord = (secret: Buffer): anchor.web3.PublicKey => {
+        const hashlock = createHash("sha256").update(secret).digest();
+        const [secretRecord] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("secret_record"), hashlock],
+            program.programId
+        );
+        return secretRecord;
+    };
+
 
     const createUser = async (): Promise<anchor.web3.Keypair> => {
         const user = new anchor.web3.Keypair();
@@ -62,7 +72,7 @@ describe("HTLC", () => {
     const createPHTLC = async (Id: Buffer, amount: anchor.BN, timelock: anchor.BN) => {
         const pda = await getPdaParams(Id);
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, amount)
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, amount, null)
             .accountsPartial({
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
@@ -84,6 +94,7 @@ describe("HTLC", () => {
         const rewardTx = await program.methods
             .lockReward(Array.from(Id), rewardTimelock, new anchor.BN(REWARD))
             .accountsPartial({
+                authority: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: htlc_pda.htlc,
             }).transaction();
@@ -132,6 +143,42 @@ describe("HTLC", () => {
         return [finalMessage, signature]
     }
 
+    // CI builds the programs with the devnet feature, so add_lock_sig checks the devnet genesis hash.
+    const GENESIS_HASH = new anchor.web3.PublicKey("EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG").toBuffer();
+
+    /// Signs the add_lock_sig message, bound to this program, the cluster and the deadline.
+    const signAddLock = async (Id: Buffer, hashlock: Buffer, timelock: anchor.BN, deadline: anchor.BN): Promise<[Uint8Array, Uint8Array]> => {
+        const name = Buffer.from("add_lock_sig");
+        const MSG = createHash("sha256")
+            .update(Buffer.from([1]))
+            .update(program.programId.toBuffer())
+            .update(GENESIS_HASH)
+            .update(Buffer.from([name.length]))
+            .update(name)
+            .update(Id)
+            .update(hashlock)
+            .update(timelock.toArrayLike(Buffer, "le", 8))
+            .update(deadline.toArrayLike(Buffer, "le", 8))
+            .digest();
+
+        const messageLength = Buffer.alloc(2);
+        messageLength.writeUInt16LE(MSG.length, 0);
+        const applicationDomain = Buffer.alloc(32);
+        applicationDomain.write("Train");
+        const rawMessage = Buffer.concat([
+            Buffer.from("\xffsolana offchain", "ascii"),
+            Buffer.from([0]),
+            applicationDomain,
+            Buffer.from([0]),
+            Buffer.from([1]),
+            wallet.publicKey.toBytes(),
+            messageLength,
+            MSG,
+        ]);
+        const finalMessage = new TextEncoder().encode(rawMessage.toString('hex'));
+        return [finalMessage, await ed.sign(finalMessage, wallet.payer.secretKey.slice(0, 32))];
+    }
+
     function wait(ms: number): Promise<void> {
         return new Promise((resolve) => setTimeout(resolve, ms));
     }
@@ -151,6 +198,7 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
             })
             .signers([wallet.payer])
             .rpc().catch(e => console.error(e));
@@ -179,6 +227,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(hashlock), timelock).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -207,9 +256,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -255,6 +305,7 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
             })
             .signers([wallet.payer])
             .rpc();
@@ -278,6 +329,7 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(wrong_secret),
             })
             .signers([wallet.payer])
             .rpc().catch(e => console.error(e));
@@ -308,6 +360,7 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
             })
             .signers([wallet.payer])
             .rpc();
@@ -465,9 +518,9 @@ describe("HTLC", () => {
         const Id = randomBytes(32);
         const time = (new Date().getTime() + 1000000) / 1000;
 
-        const pda = await getPdaParams(Id);
+        const pda = await getPdaParams(Id, bob.publicKey);
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT))
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT), null)
             .accountsPartial({
                 sender: bob.publicKey,
                 htlc: pda.htlc
@@ -484,7 +537,7 @@ describe("HTLC", () => {
         const time = (new Date().getTime() + 1000000) / 1000;
         const rtime = (new Date().getTime() + 900000) / 1000;
 
-        const htlc_pda = await getPdaParams(Id);
+        const htlc_pda = await getPdaParams(Id, bob.publicKey);
 
         const lockTx = await program.methods
             .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), new anchor.BN(AMOUNT), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey)
@@ -495,6 +548,7 @@ describe("HTLC", () => {
         const rewardTx = await program.methods
             .lockReward(Array.from(Id), new anchor.BN(rtime), new anchor.BN(REWARD))
             .accountsPartial({
+                authority: bob.publicKey,
                 sender: bob.publicKey,
                 htlc: htlc_pda.htlc,
             }).transaction();
@@ -586,6 +640,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -603,6 +658,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -621,6 +677,7 @@ describe("HTLC", () => {
 
         const AddLockTx1 = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -629,6 +686,7 @@ describe("HTLC", () => {
 
         const AddLockTx2 = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -647,6 +705,7 @@ describe("HTLC", () => {
         const wrong_time = (new Date().getTime() - 1000000) / 1000;
         const AddLockTx = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(wrong_time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -691,9 +750,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -723,6 +783,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -743,9 +804,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -789,9 +851,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), wrong_timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), wrong_timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -834,9 +897,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -879,9 +943,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -925,9 +990,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -953,10 +1019,10 @@ describe("HTLC", () => {
         const hashlock = createHash("sha256").update(secret).digest();
         const amount = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
 
-        const pda = await getPdaParams(Id);
+        const pda = await getPdaParams(Id, bob.publicKey);
 
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, new anchor.BN(amount))
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, new anchor.BN(amount), null)
             .accountsPartial({
                 sender: bob.publicKey,
                 htlc: pda.htlc,
@@ -979,9 +1045,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: bob.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1011,4 +1078,307 @@ describe("HTLC", () => {
         await T12_7()
     });
 
-});
\ No newline at end of file
+    /// Lifecycle events are emitted for lock, lockReward and redeem.
+    it("T13", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+
+        const events = [];
+        const listeners = ["tokenLocked", "tokenRewardLocked", "tokenRedeemed"].map((name) =>
+            program.addEventListener(name as any, (event) => events.push([name, event]))
+        );
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        await program.methods.redeem(Array.from(Id), Array.from(secret)).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        await wait(2000);
+        for (const listener of listeners) {
+            await program.removeEventListener(listener);
+        }
+
+        const names = events.map(([name, _]) => name);
+        assert.deepEqual(names, ["tokenLocked", "tokenRewardLocked", "tokenRedeemed"]);
+        const [, locked] = events[0];
+        assert.deepEqual(locked.id, Array.from(Id));
+        assert.deepEqual(locked.hashlock, Array.from(hashlock));
+        assert.equal(locked.srcReceiver.toBase58(), bob.publicKey.toBase58());
+        const [, redeemed] = events[2];
+        assert.deepEqual(redeemed.secret, Array.from(secret));
+        assert.equal(redeemed.redeemAddress.toBase58(), wallet.publicKey.toBase58());
+    });
+
+    /// The HTLC status follows Committed -> Locked -> Redeemed and rejects a second redeem.
+    it("T14", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const pda = await getPdaParams(Id);
+
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        assert.deepEqual((await program.account.htlc.fetch(pda.htlc)).status, { committed: {} });
+
+        await program.methods.addLock(Array.from(Id), Array.from(hashlock), new anchor.BN(time)).
+            accountsPartial({
+                locker: wallet.publicKey,
+                sender: wallet.publicKey,
+                htlc: pda.htlc,
+            })
+            .signers([wallet.payer])
+            .rpc();
+        assert.deepEqual((await program.account.htlc.fetch(pda.htlc)).status, { locked: {} });
+
+        const redeem = () => program.methods.redeem(Array.from(Id), Array.from(secret)).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+            })
+            .signers([wallet.payer])
+            .rpc();
+        await redeem();
+        assert.deepEqual((await program.account.htlc.fetch(pda.htlc)).status, { redeemed: {} });
+
+        await assert.rejects(redeem(), (e: anchor.AnchorError) => e.error.errorCode.code === "AlreadyRedeemed");
+    });
+
+    /// Max-length destination addresses of every supported chain family fit in the HTLC account,
+    /// longer strings are rejected up front.
+    it("T15", async () => {
+        const MAX_ADDRESSES = {
+            EVM: "0x" + "ab".repeat(20),
+            STARKNET: "0x" + "ab".repeat(32),
+            FUEL: "0x" + "ab".repeat(32),
+            SOLANA: "1".repeat(44),
+            BITCOIN: "bc1" + "q".repeat(87),
+        };
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const LOCK = async (Id: Buffer, hashlock: number[], timelock: anchor.BN, dstChain: string, dstAddress: string, dstAsset: string) => {
+            const pda = await getPdaParams(Id);
+            await program.methods
+                .lock(Array.from(Id), hashlock, timelock, new anchor.BN(AMOUNT), dstChain, dstAddress, dstAsset, SRCASSET, bob.publicKey)
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                })
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        for (const [chain, address] of Object.entries(MAX_ADDRESSES)) {
+            const Id = randomBytes(32);
+            const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+            const pda = await getPdaParams(Id);
+            await LOCK(Id, Array.from(hashlock), new anchor.BN(time), chain, address, "A".repeat(66));
+            const htlc = await program.account.htlc.fetch(pda.htlc);
+            assert.equal(htlc.dstAddress, address);
+        }
+
+        const Id = randomBytes(32);
+        const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+        await assert.rejects(
+            LOCK(Id, Array.from(hashlock), new anchor.BN(time), DSTCHAIN, "x".repeat(91), DSTASSET),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "StringTooLong"
+        );
+    });
+
+    /// The hop route is stored on commit and returned by getDetails, mismatched routes are rejected.
+    it("T16", async () => {
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const hopChains = ["ARBITRUM_SEPOLIA", DSTCHAIN];
+        const hopAssets = ["USDC", DSTASSET];
+        const hopAddresses = ["0x" + "ab".repeat(20), DSTADDRESS];
+        const COMMIT = async (Id: Buffer, chains: string[], assets: string[], addresses: string[]) => {
+            const pda = await getPdaParams(Id);
+            await program.methods
+                .commit(Array.from(Id), chains, assets, addresses, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT), null)
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                })
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        const Id = randomBytes(32);
+        const pda = await getPdaParams(Id);
+        await COMMIT(Id, hopChains, hopAssets, hopAddresses);
+        const details = await program.methods.getDetails(Array.from(Id)).accountsPartial({ sender: wallet.publicKey, htlc: pda.htlc }).view();
+        assert.deepEqual(details.hopChains, hopChains);
+        assert.deepEqual(details.hopAssets, hopAssets);
+        assert.deepEqual(details.hopAddresses, hopAddresses);
+
+        await assert.rejects(
+            COMMIT(randomBytes(32), hopChains, hopAssets, [DSTADDRESS]),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "HopLengthMismatch"
+        );
+    });
+
+    /// A settled HTLC can be closed by the sender to get the rent back, a pending one can not.
+    it("T17", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+        const close = () => program.methods.close(Array.from(Id)).
+            accountsPartial({
+                sender: wallet.publicKey,
+                htlc: pda.htlc,
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        await assert.rejects(close(), (e: anchor.AnchorError) => e.error.errorCode.code === "NotSettled");
+
+        await program.methods.redeem(Array.from(Id), Array.from(secret)).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+            })
+            .signers([wallet.payer])
+            .rpc();
+        const rent = await provider.connection.getBalance(pda.htlc);
+        assert.ok(rent > 0);
+        await close();
+        assert.equal(await provider.connection.getAccountInfo(pda.htlc), null);
+    });
+
+    /// Another sender using the same Id gets a different HTLC, so Ids cannot be squatted.
+    it("T18", async () => {
+        const Id = randomBytes(32);
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const squatter = await getPdaParams(Id, bob.publicKey);
+        await program.methods
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT / 10), null)
+            .accountsPartial({
+                sender: bob.publicKey,
+                htlc: squatter.htlc,
+            })
+            .signers([bob])
+            .rpc();
+
+        const pda = await getPdaParams(Id);
+        assert.notEqual(pda.htlc.toBase58(), squatter.htlc.toBase58());
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        const htlc = await program.account.htlc.fetch(pda.htlc);
+        assert.equal(htlc.sender.toBase58(), wallet.publicKey.toBase58());
+    });
+
+    /// reserve_id hands out sha256(sender || nonce) and increments the sender nonce.
+    it("T19", async () => {
+        const [senderState] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("sender_state"), wallet.publicKey.toBuffer()],
+            program.programId
+        );
+        const state = await program.account.senderState.fetchNullable(senderState);
+        const nonce = state ? state.nonce : new anchor.BN(0);
+        const Id = createHash("sha256").update(wallet.publicKey.toBuffer()).update(nonce.toArrayLike(Buffer, "le", 8)).digest();
+
+        const reserved = await program.methods.reserveId()
+            .accountsPartial({ sender: wallet.publicKey })
+            .view();
+        assert.deepEqual(reserved, Array.from(Id));
+
+        await program.methods.reserveId()
+            .accountsPartial({ sender: wallet.publicKey })
+            .signers([wallet.payer])
+            .rpc();
+        assert.equal((await program.account.senderState.fetch(senderState)).nonce.toString(), nonce.addn(1).toString());
+
+        const time = (new Date().getTime() + 10000000) / 1000;
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        assert.equal((await program.account.htlc.fetch((await getPdaParams(Id)).htlc)).sender.toBase58(), wallet.publicKey.toBase58());
+    });
+
+    /// The first redeem records the secret of the hashlock, so it can be read without the HTLC.
+    it("T20", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+        const secretRecord = getSecretRecord(secret);
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        assert.equal(await provider.connection.getAccountInfo(secretRecord), null);
+        await program.methods.redeem(Array.from(Id), Array.from(secret)).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                htlc: pda.htlc,
+                secretRecord,
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        const record = await program.account.secretRecord.fetch(secretRecord);
+        assert.deepEqual(record.hashlock, Array.from(hashlock));
+        assert.deepEqual(record.secret, Array.from(secret));
+        const recorded = await program.methods.getSecret(Array.from(hashlock))
+            .accountsPartial({ secretRecord })
+            .view();
+        assert.deepEqual(recorded.secret, Array.from(secret));
+    });
+
+    /// Can add lock with a signature bound to the program, the cluster and a deadline, until the deadline.
+    it("T21", async () => {
+        const now = Math.floor(new Date().getTime() / 1000);
+        const timelock = new anchor.BN(now + 1000);
+        const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+        const addLockSig = async (Id: Buffer, deadline: anchor.BN) => {
+            const pda = await getPdaParams(Id);
+            const [message, signature] = await signAddLock(Id, hashlock, timelock, deadline);
+            return program.methods.addLockSig(Array.from(Id), Array.from(hashlock), timelock, deadline, Array.from(signature))
+                .accountsPartial({
+                    payer: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+                })
+                .preInstructions([anchor.web3.Ed25519Program.createInstructionWithPublicKey({
+                    publicKey: wallet.publicKey.toBytes(),
+                    message,
+                    signature,
+                })])
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        const expired = randomBytes(32);
+        await createPHTLC(expired, new anchor.BN(AMOUNT), timelock);
+        await assert.rejects(
+            addLockSig(expired, new anchor.BN(now - 1)),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "SignatureExpired"
+        );
+
+        const Id = randomBytes(32);
+        await createPHTLC(Id, new anchor.BN(AMOUNT), timelock);
+        await addLockSig(Id, new anchor.BN(now + 600));
+        const htlc = await program.account.htlc.fetch((await getPdaParams(Id)).htlc);
+        assert.deepEqual(htlc.hashlock, Array.from(hashlock));
+        assert.equal(htlc.timelock.toString(), timelock.toString());
+    });
+});
@@ -50,7 +50,7 @@ describe("HTLC", () => {
     id: Buffer,
   ): Promise<HTLCParameters> => {
     let [htlc, _] = await anchor.web3.PublicKey.findProgramAddressSync(
-      [id],
+      [Buffer.from("htlc"), wallet.publicKey.toBuffer(), id],
       program.programId
     );
     console.log(`[${htlc}] derived htlc`);
@@ -59,6 +59,15 @@ describe("HTLC", () => {
     };
   };
 
+  const getSecretRecord = (secret: Buffer): anchor.web3.PublicKey => {
+    const hashlock = createHash("sha256").update(secret).digest();
+    const [secretRecord] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("secret_record"), hashlock],
+      program.programId
+    );
+    return secretRecord;
+  };
+
   const createUser = async (): Promise<anchor.web3.Keypair> => {
     const user = new anchor.web3.Keypair();
     // Fund user with some SOL
@@ -93,7 +102,7 @@ describe("HTLC", () => {
     console.log("lamport balance of wallet before commit",
       await anchor.getProvider().connection.getBalance(wallet.publicKey));
     const commitTx = await program.methods
-      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, wallet.publicKey, TIMELOCK, new anchor.BN(AMOUNT))
+      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, wallet.publicKey, TIMELOCK, new anchor.BN(AMOUNT), null)
       .accountsPartial({
         sender: user.publicKey,
         htlc: pda.htlc,
@@ -154,9 +163,10 @@ describe("HTLC", () => {
       .add(
         // Our instruction
         await program.methods.
-          addLockSig(IDArray, HASHLOCKArray, TIMELOCK, Array.from(signature)).
+          addLockSigLegacy(IDArray, HASHLOCKArray, TIMELOCK, Array.from(signature)).
           accountsPartial({
             payer: wallet.publicKey,
+            sender: wallet.publicKey,
             htlc: pda.htlc,
             ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
           })
@@ -177,6 +187,7 @@ describe("HTLC", () => {
       accountsPartial({
         userSigning: user.publicKey,
         htlc: pda.htlc,
+        secretRecord: getSecretRecord(SECRET),
         srcReceiver: wallet.publicKey,
       })
       .signers([user.payer])
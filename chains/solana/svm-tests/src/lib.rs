@@ -0,0 +1,4 @@
+//! In-process SVM tests of the native_htlc and anchor_htlc programs, see `tests/`.
+//!
+//! The tests run both programs natively as builtins of LiteSVM, through the
+//! syscall stubs of solana-program-test, and warp the clock instead of sleeping.
@@ -0,0 +1,1182 @@
+//! anchor_htlc in an in-process SVM, each test run with an SPL Token and a
+//! Token-2022 mint.
+//!
+//! The status, string and hop checks are shared with native_htlc and covered
+//! there; these tests cover the token movements and the token account checks.
+
+// The error of a LiteSVM transaction carries its metadata.
+#![allow(clippy::result_large_err)]
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{program_option::COption, program_pack::Pack};
+use anchor_lang::{error::ErrorCode, AnchorDeserialize, AnchorSerialize, Discriminator};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig},
+        BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions, StateWithExtensionsMut,
+    },
+    state::{Account as TokenAccount, AccountState, Mint},
+};
+use common::*;
+use litesvm::types::{TransactionMetadata, TransactionResult};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_signer::Signer;
+use spl_associated_token_account_client::instruction::create_associated_token_account;
+use train_solana_client::anchor_htlc::{
+    self, instruction, FeePayer, HTLCError, HtlcStatus, LockEntry, SecretRecord, SenderState, HTLC,
+    SESSION_ADD_LOCK, SESSION_LOCK_REWARD,
+};
+
+const TOKEN_PROGRAMS: [Pubkey; 2] = [spl_token::ID, spl_token_2022::ID];
+const AMOUNT: u64 = 1_000_000;
+const REWARD: u64 = 10_000;
+const MINTED: u64 = 100 * AMOUNT;
+
+/// An Env with a mint of `token_program` whose authority is the solver, and
+/// MINTED tokens in the associated token account of the sender.
+struct TokenEnv {
+    env: Env,
+    mint: Pubkey,
+    token_program: Pubkey,
+}
+
+impl TokenEnv {
+    fn new(token_program: Pubkey) -> Self {
+        let mut env = Env::new();
+        let mint = create_mint(&mut env, &token_program);
+        Self::with_mint(env, mint, token_program)
+    }
+
+    /// A TokenEnv whose Token-2022 mint withholds `basis_points` of each
+    /// transfer, up to `maximum_fee`.
+    fn with_fee_mint(basis_points: u16, maximum_fee: u64) -> Self {
+        let mut env = Env::new();
+        let mint = create_fee_mint(&mut env, basis_points, maximum_fee);
+        Self::with_mint(env, mint, spl_token_2022::ID)
+    }
+
+    fn with_mint(env: Env, mint: Pubkey, token_program: Pubkey) -> Self {
+        let mut env = Self {
+            env,
+            mint,
+            token_program,
+        };
+        let sender = env.env.sender.insecure_clone();
+        let solver = env.env.solver.insecure_clone();
+        env.create_token_account(&sender.pubkey());
+        let mint_to = spl_token_2022::instruction::mint_to(
+            &token_program,
+            &mint,
+            &env.ata(&sender.pubkey()),
+            &solver.pubkey(),
+            &[],
+            MINTED,
+        )
+        .unwrap();
+        assert_ok(env.env.send(&[mint_to], &[&solver]));
+        env
+    }
+
+    fn ata(&self, owner: &Pubkey) -> Pubkey {
+        anchor_htlc::associated_token_address(owner, &self.mint, &self.token_program)
+    }
+
+    fn create_token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let solver = self.env.solver.insecure_clone();
+        let ix = create_associated_token_account(
+            &solver.pubkey(),
+            owner,
+            &self.mint,
+            &self.token_program,
+        );
+        assert_ok(self.env.send(&[ix], &[&solver]));
+        self.ata(owner)
+    }
+
+    /// Token balance of `owner`, 0 without a token account.
+    fn balance(&self, owner: &Pubkey) -> u64 {
+        self.token_balance(&self.ata(owner))
+    }
+
+    fn token_balance(&self, address: &Pubkey) -> u64 {
+        match self.env.svm.get_account(address) {
+            Some(account) if !account.data.is_empty() => {
+                StateWithExtensions::<TokenAccount>::unpack(&account.data)
+                    .unwrap()
+                    .base
+                    .amount
+            }
+            _ => 0,
+        }
+    }
+
+    fn htlc_address(&self, id: &[u8; 32]) -> Pubkey {
+        anchor_htlc::find_htlc_address(&self.env.sender.pubkey(), id).0
+    }
+
+    fn htlc_token_account(&self, id: &[u8; 32]) -> Pubkey {
+        anchor_htlc::find_htlc_token_account_address(&self.env.sender.pubkey(), id).0
+    }
+
+    fn htlc(&self, id: &[u8; 32]) -> HTLC {
+        anchor_htlc::decode_htlc(&self.env.data(&self.htlc_address(id))).unwrap()
+    }
+
+    fn commit_ix(&self, id: [u8; 32], timelock: u64) -> train_solana_client::Instruction {
+        anchor_htlc::commit(
+            &self.env.sender.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::Commit {
+                Id: id,
+                hopChains: vec![DST_CHAIN.to_string()],
+                hopAssets: vec![DST_ASSET.to_string()],
+                hopAddress: vec![DST_ADDRESS.to_string()],
+                dst_chain: DST_CHAIN.to_string(),
+                dst_asset: DST_ASSET.to_string(),
+                dst_address: DST_ADDRESS.to_string(),
+                src_asset: SRC_ASSET.to_string(),
+                src_receiver: self.env.receiver.pubkey(),
+                timelock,
+                amount: AMOUNT,
+                fee_payer: FeePayer::Receiver,
+                authorized_locker: None,
+            },
+        )
+    }
+
+    fn lock_ix(&self, id: [u8; 32], timelock: u64) -> train_solana_client::Instruction {
+        anchor_htlc::lock(
+            &self.env.sender.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::Lock {
+                Id: id,
+                hashlock: hashlock(&secret(id[0])),
+                timelock,
+                dst_chain: DST_CHAIN.to_string(),
+                dst_address: DST_ADDRESS.to_string(),
+                dst_asset: DST_ASSET.to_string(),
+                src_asset: SRC_ASSET.to_string(),
+                src_receiver: self.env.receiver.pubkey(),
+                amount: AMOUNT,
+                fee_payer: FeePayer::Receiver,
+            },
+        )
+    }
+
+    fn send_as_sender(&mut self, ix: train_solana_client::Instruction) -> TransactionResult {
+        let sender = self.env.sender.insecure_clone();
+        self.env.send(&[ix], &[&sender])
+    }
+
+    /// Locks AMOUNT for the receiver until now + 1800 and returns the secret.
+    fn lock(&mut self, id: [u8; 32]) -> [u8; 32] {
+        let ix = self.lock_ix(id, self.env.now() + 1800);
+        assert_ok(self.send_as_sender(ix));
+        secret(id[0])
+    }
+
+    fn lock_reward(&mut self, id: [u8; 32], reward_timelock: u64) -> TransactionResult {
+        let ix = anchor_htlc::lock_reward(
+            &self.env.sender.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::LockReward {
+                Id: id,
+                reward_timelock,
+                reward: REWARD,
+            },
+        );
+        self.send_as_sender(ix)
+    }
+
+    fn redeem_ix(
+        &self,
+        id: [u8; 32],
+        secret: [u8; 32],
+        by: &Pubkey,
+    ) -> train_solana_client::Instruction {
+        anchor_htlc::redeem(
+            by,
+            &self.env.sender.pubkey(),
+            &self.env.receiver.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::Redeem {
+                Id: id,
+                secret,
+                htlc_bump: anchor_htlc::find_htlc_address(&self.env.sender.pubkey(), &id).1,
+            },
+        )
+    }
+
+    fn redeem(&mut self, id: [u8; 32], secret: [u8; 32], by: &Keypair) -> TransactionResult {
+        let ix = self.redeem_ix(id, secret, &by.pubkey());
+        self.env.send(&[ix], &[by])
+    }
+
+    fn refund(&mut self, id: [u8; 32], by: &Keypair) -> TransactionResult {
+        let ix = anchor_htlc::refund(
+            &by.pubkey(),
+            &self.env.sender.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::Refund {
+                Id: id,
+                htlc_bump: anchor_htlc::find_htlc_address(&self.env.sender.pubkey(), &id).1,
+            },
+        );
+        self.env.send(&[ix], &[by])
+    }
+
+    fn lock_entry(&self, id: [u8; 32]) -> LockEntry {
+        LockEntry {
+            Id: id,
+            hashlock: hashlock(&secret(id[0])),
+            timelock: self.env.now() + 1800,
+            dst_chain: DST_CHAIN.to_string(),
+            dst_address: DST_ADDRESS.to_string(),
+            dst_asset: DST_ASSET.to_string(),
+            src_asset: SRC_ASSET.to_string(),
+            src_receiver: self.env.receiver.pubkey(),
+            amount: AMOUNT,
+            fee_payer: FeePayer::Receiver,
+        }
+    }
+
+    fn batch_lock(&mut self, ids: &[[u8; 32]]) -> TransactionResult {
+        let ix = anchor_htlc::batch_lock(
+            &self.env.sender.pubkey(),
+            &self.mint,
+            &self.token_program,
+            instruction::BatchLock {
+                entries: ids.iter().map(|id| self.lock_entry(*id)).collect(),
+                best_effort: false,
+            },
+        );
+        let sender = self.env.sender.insecure_clone();
+        self.env
+            .send(&[set_compute_unit_limit(1_400_000), ix], &[&sender])
+    }
+
+    fn batch_redeem(
+        &mut self,
+        entries: Vec<([u8; 32], [u8; 32])>,
+        by: &Keypair,
+        best_effort: bool,
+    ) -> TransactionResult {
+        let htlcs = vec![(self.env.sender.pubkey(), self.env.receiver.pubkey()); entries.len()];
+        let ix = anchor_htlc::batch_redeem(
+            &by.pubkey(),
+            &self.mint,
+            &self.token_program,
+            &htlcs,
+            instruction::BatchRedeem {
+                entries,
+                best_effort,
+            },
+        );
+        self.env
+            .send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+    }
+
+    fn redeem_by_hashlock(
+        &mut self,
+        hashlock: [u8; 32],
+        secret: Option<[u8; 32]>,
+        proof: Option<[u8; 32]>,
+        ids: Vec<[u8; 32]>,
+        by: &Keypair,
+    ) -> TransactionResult {
+        let htlcs = vec![(self.env.sender.pubkey(), self.env.receiver.pubkey()); ids.len()];
+        let ix = anchor_htlc::redeem_by_hashlock(
+            &by.pubkey(),
+            &self.mint,
+            &self.token_program,
+            proof.map(|proof| anchor_htlc::find_htlc_address(&self.env.sender.pubkey(), &proof).0),
+            &htlcs,
+            instruction::RedeemByHashlock {
+                hashlock,
+                secret,
+                Ids: ids,
+                best_effort: false,
+            },
+        );
+        self.env
+            .send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+    }
+
+    fn batch_refund(&mut self, ids: Vec<[u8; 32]>, by: &Keypair) -> TransactionResult {
+        let senders = vec![self.env.sender.pubkey(); ids.len()];
+        let ix = anchor_htlc::batch_refund(
+            &by.pubkey(),
+            &self.mint,
+            &self.token_program,
+            &senders,
+            instruction::BatchRefund {
+                Ids: ids,
+                best_effort: false,
+            },
+        );
+        self.env
+            .send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+    }
+}
+
+/// The entries a batch reports as settled.
+fn settled(meta: TransactionMetadata) -> Vec<bool> {
+    Vec::<bool>::deserialize(&mut &meta.return_data.data[..]).unwrap()
+}
+
+/// Writes a mint of `token_program` without extensions, 6 decimals, whose
+/// authority is the solver.
+fn create_mint(env: &mut Env, token_program: &Pubkey) -> Pubkey {
+    let mint = Pubkey::new_unique();
+    let mut data = vec![0; Mint::LEN];
+    Mint {
+        mint_authority: COption::Some(env.solver.pubkey()),
+        supply: 0,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+    set_account(env, mint, data, *token_program);
+    mint
+}
+
+/// Writes a Token-2022 mint like create_mint, with a TransferFeeConfig
+/// withholding `basis_points` of each transfer, up to `maximum_fee`.
+fn create_fee_mint(env: &mut Env, basis_points: u16, maximum_fee: u64) -> Pubkey {
+    let mint = Pubkey::new_unique();
+    let space =
+        ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])
+            .unwrap();
+    let mut data = vec![0; space];
+    let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+    let fee = TransferFee {
+        epoch: 0.into(),
+        maximum_fee: maximum_fee.into(),
+        transfer_fee_basis_points: basis_points.into(),
+    };
+    let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+    config.older_transfer_fee = fee;
+    config.newer_transfer_fee = fee;
+    state.base = Mint {
+        mint_authority: COption::Some(env.solver.pubkey()),
+        supply: 0,
+        decimals: 6,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    state.pack_base();
+    state.init_account_type().unwrap();
+    set_account(env, mint, data, spl_token_2022::ID);
+    mint
+}
+
+fn set_account(env: &mut Env, address: Pubkey, data: Vec<u8>, owner: Pubkey) {
+    let lamports = env.svm.minimum_balance_for_rent_exemption(data.len());
+    env.svm
+        .set_account(
+            address,
+            Account {
+                lamports,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn commit_takes_the_tokens() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let sender = t.env.sender.pubkey();
+        let ix = t.commit_ix(id(1), START + 899);
+        assert_error(t.send_as_sender(ix), HTLCError::InvalidTimeLock);
+
+        let ix = t.commit_ix(id(1), START + 900);
+        assert_ok(t.send_as_sender(ix));
+        assert_eq!(t.balance(&sender), MINTED - AMOUNT);
+        assert_eq!(t.token_balance(&t.htlc_token_account(&id(1))), AMOUNT);
+
+        let htlc = t.htlc(&id(1));
+        assert_eq!(htlc.status, HtlcStatus::Committed);
+        assert_eq!(htlc.amount, AMOUNT);
+        assert_eq!(htlc.token_contract, t.mint);
+        assert_eq!(htlc.token_program, token_program);
+        assert_eq!(htlc.token_wallet, t.htlc_token_account(&id(1)));
+    }
+}
+
+#[test]
+fn lock_timelock_must_be_at_least_1800_seconds_away() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let ix = t.lock_ix(id(1), START + 1799);
+        assert_error(t.send_as_sender(ix), HTLCError::InvalidTimeLock);
+
+        let ix = t.lock_ix(id(1), START + 1800);
+        assert_ok(t.send_as_sender(ix));
+        assert_eq!(t.htlc(&id(1)).status, HtlcStatus::Locked);
+        assert_eq!(t.token_balance(&t.htlc_token_account(&id(1))), AMOUNT);
+    }
+}
+
+#[test]
+fn add_lock_sig_locks_a_token_htlc() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let ix = t.commit_ix(id(1), START + 900);
+        assert_ok(t.send_as_sender(ix));
+
+        let solver = t.env.solver.insecure_clone();
+        let hashlock = hashlock(&secret(1));
+        let instructions = anchor_htlc::signed_add_lock_sig(
+            &solver.pubkey(),
+            &t.env.sender,
+            GENESIS_HASH,
+            id(1),
+            hashlock,
+            START + 900,
+            DEADLINE,
+        )
+        .unwrap();
+        assert_ok(t.env.send(&instructions, &[&solver]));
+        assert_eq!(t.htlc(&id(1)).hashlock, hashlock);
+
+        // The Ed25519Program instruction after a compute budget instruction.
+        let ix = t.commit_ix(id(2), START + 900);
+        assert_ok(t.send_as_sender(ix));
+        let [ed25519_ix, ix] = anchor_htlc::signed_add_lock_sig(
+            &solver.pubkey(),
+            &t.env.sender,
+            GENESIS_HASH,
+            id(2),
+            hashlock,
+            START + 900,
+            DEADLINE,
+        )
+        .unwrap();
+        assert_ok(t.env.send(
+            &[set_compute_unit_limit(200_000), ed25519_ix, ix],
+            &[&solver],
+        ));
+        assert_eq!(t.htlc(&id(2)).hashlock, hashlock);
+
+        // The legacy message, through add_lock_sig_legacy, only with `legacy-lock-sig`.
+        let ix = t.commit_ix(id(3), START + 900);
+        assert_ok(t.send_as_sender(ix));
+        let instructions = anchor_htlc::signed_add_lock_sig_legacy(
+            &solver.pubkey(),
+            &t.env.sender,
+            id(3),
+            hashlock,
+            START + 900,
+        )
+        .unwrap();
+        let result = t.env.send(&instructions, &[&solver]);
+        if cfg!(feature = "legacy-lock-sig") {
+            assert_ok(result);
+            assert_eq!(t.htlc(&id(3)).status, HtlcStatus::Locked);
+        } else {
+            assert_error(result, HTLCError::LegacyLockSigDisabled);
+            assert_eq!(t.htlc(&id(3)).status, HtlcStatus::Committed);
+        }
+
+        // Signed by the solver, add_lock_sig targets the solver's HTLC under that Id.
+        let instructions = anchor_htlc::signed_add_lock_sig(
+            &solver.pubkey(),
+            &solver,
+            GENESIS_HASH,
+            id(1),
+            hashlock,
+            START + 900,
+            DEADLINE,
+        )
+        .unwrap();
+        assert_error(
+            t.env.send(&instructions, &[&solver]),
+            anchor_lang::error::ErrorCode::AccountNotInitialized,
+        );
+    }
+}
+
+#[test]
+fn authorized_locker_locks_a_token_htlc() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let ix = t.commit_ix(id(1), START + 900);
+        assert_ok(t.send_as_sender(ix));
+        let solver = t.env.solver.insecure_clone();
+        let sender = t.env.sender.pubkey();
+        let add_lock = anchor_htlc::add_lock_as(
+            &solver.pubkey(),
+            &sender,
+            instruction::AddLock {
+                Id: id(1),
+                hashlock: hashlock(&secret(1)),
+                timelock: START + 900,
+            },
+        );
+        assert_error(
+            t.env.send(std::slice::from_ref(&add_lock), &[&solver]),
+            HTLCError::UnauthorizedAccess,
+        );
+
+        let ix = anchor_htlc::set_authorized_locker(
+            &sender,
+            instruction::SetAuthorizedLocker {
+                Id: id(1),
+                authorized_locker: Some(solver.pubkey()),
+            },
+        );
+        assert_ok(t.send_as_sender(ix));
+        assert_ok(t.env.send(&[add_lock], &[&solver]));
+        let htlc = t.htlc(&id(1));
+        assert_eq!(htlc.status, HtlcStatus::Locked);
+        assert_eq!(htlc.authorized_locker, Some(solver.pubkey()));
+    }
+}
+
+#[test]
+fn add_lock_sig_evm_locks_a_token_htlc() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let ix = t.commit_ix(id(1), START + 900);
+        assert_ok(t.send_as_sender(ix));
+        let key = evm_key(1);
+        let ix = anchor_htlc::set_evm_authorizer(
+            &t.env.sender.pubkey(),
+            instruction::SetEvmAuthorizer {
+                Id: id(1),
+                evm_authorizer: evm_address(&key),
+            },
+        );
+        assert_ok(t.send_as_sender(ix));
+
+        let solver = t.env.solver.insecure_clone();
+        let sender = t.env.sender.pubkey();
+        let hashlock = hashlock(&secret(1));
+        let add_lock_sig_evm = |digest: [u8; 32]| {
+            let (r, s, v) = evm_sign(&key, &digest);
+            anchor_htlc::add_lock_sig_evm(
+                &solver.pubkey(),
+                &sender,
+                instruction::AddLockSigEvm {
+                    Id: id(1),
+                    hashlock,
+                    timelock: START + 900,
+                    deadline: DEADLINE,
+                    r,
+                    s,
+                    v,
+                },
+            )
+        };
+
+        // Signed for the native program.
+        let ix = add_lock_sig_evm(train_solana_client::native_htlc::add_lock_evm_digest(
+            GENESIS_HASH,
+            &id(1),
+            &hashlock,
+            START + 900,
+            DEADLINE,
+        ));
+        assert_error(
+            t.env.send(&[ix], &[&solver]),
+            HTLCError::SigVerificationFailed,
+        );
+
+        let ix = add_lock_sig_evm(anchor_htlc::add_lock_evm_digest(
+            GENESIS_HASH,
+            &id(1),
+            &hashlock,
+            START + 900,
+            DEADLINE,
+        ));
+        assert_ok(t.env.send(&[ix], &[&solver]));
+        assert_eq!(t.htlc(&id(1)).status, HtlcStatus::Locked);
+        assert_eq!(t.htlc(&id(1)).hashlock, hashlock);
+    }
+}
+
+#[test]
+fn session_delegate_pays_the_reward_from_the_sender_token_account() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        t.lock(id(1));
+        t.lock(id(2));
+        let solver = t.env.solver.insecure_clone();
+        let sender = t.env.sender.pubkey();
+        let session = anchor_htlc::find_session_address(&sender, &solver.pubkey()).0;
+        let ix = anchor_htlc::create_session(
+            &sender,
+            &t.mint,
+            &t.token_program,
+            instruction::CreateSession {
+                delegate: solver.pubkey(),
+                expiry: DEADLINE,
+                max_amount: REWARD,
+                permissions: SESSION_ADD_LOCK | SESSION_LOCK_REWARD,
+            },
+        );
+        assert_ok(t.send_as_sender(ix));
+        let delegation = |t: &TokenEnv| {
+            let account = StateWithExtensions::<TokenAccount>::unpack(&t.env.data(&t.ata(&sender)))
+                .unwrap()
+                .base;
+            (account.delegate, account.delegated_amount)
+        };
+        assert_eq!(delegation(&t), (COption::Some(session), REWARD));
+
+        // The token account has a single delegate, so a second session waits for the first.
+        let other = t.env.receiver.pubkey();
+        let create_other = anchor_htlc::create_session(
+            &sender,
+            &t.mint,
+            &t.token_program,
+            instruction::CreateSession {
+                delegate: other,
+                expiry: DEADLINE,
+                max_amount: REWARD,
+                permissions: SESSION_ADD_LOCK,
+            },
+        );
+        assert_error(
+            t.send_as_sender(create_other.clone()),
+            HTLCError::TokenAccountDelegated,
+        );
+
+        let lock_reward = |id| {
+            anchor_htlc::lock_reward_by_session(
+                &solver.pubkey(),
+                &sender,
+                &t.mint,
+                &t.token_program,
+                instruction::LockReward {
+                    Id: id,
+                    reward_timelock: START + 1000,
+                    reward: REWARD,
+                },
+            )
+        };
+        let (first, second) = (lock_reward(id(1)), lock_reward(id(2)));
+        assert_ok(t.env.send(&[first], &[&solver]));
+        assert_eq!(
+            t.token_balance(&t.htlc_token_account(&id(1))),
+            AMOUNT + REWARD
+        );
+        let spent = anchor_htlc::decode_session(&t.env.data(&session)).unwrap();
+        assert_eq!((spent.spent, spent.uses), (REWARD, 1));
+        assert_error(
+            t.env.send(&[second], &[&solver]),
+            HTLCError::SessionBudgetExceeded,
+        );
+
+        let ix = anchor_htlc::revoke_session(
+            &sender,
+            &t.mint,
+            &t.token_program,
+            instruction::RevokeSession {
+                delegate: solver.pubkey(),
+            },
+        );
+        assert_ok(t.send_as_sender(ix));
+        assert!(!t.env.exists(&session));
+        assert_eq!(delegation(&t), (COption::None, 0));
+
+        assert_ok(t.send_as_sender(create_other));
+        let other_session = anchor_htlc::find_session_address(&sender, &other).0;
+        assert_eq!(delegation(&t), (COption::Some(other_session), REWARD));
+    }
+}
+
+#[test]
+fn lock_reward_takes_the_reward() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        t.lock(id(1));
+        let timelock = START + 1800;
+        assert_error(
+            t.lock_reward(id(1), START),
+            HTLCError::InvalidRewardTimeLock,
+        );
+        assert_error(
+            t.lock_reward(id(1), timelock),
+            HTLCError::InvalidRewardTimeLock,
+        );
+
+        assert_ok(t.lock_reward(id(1), timelock - 1));
+        assert_eq!(
+            t.token_balance(&t.htlc_token_account(&id(1))),
+            AMOUNT + REWARD
+        );
+        assert_eq!(t.htlc(&id(1)).reward, REWARD);
+        assert_error(
+            t.lock_reward(id(1), timelock - 1),
+            HTLCError::RewardAlreadyExists,
+        );
+    }
+}
+
+#[test]
+fn redeem_before_the_reward_timelock_returns_the_reward_to_the_sender() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let secret = t.lock(id(1));
+        assert_ok(t.lock_reward(id(1), START + 1000));
+        let solver = t.env.solver.insecure_clone();
+        let sender_before = t.balance(&t.env.sender.pubkey());
+
+        t.env.warp(START + 999);
+        assert_error(
+            t.redeem(id(1), [0; 32], &solver),
+            HTLCError::HashlockNoMatch,
+        );
+        assert_ok(t.redeem(id(1), secret, &solver));
+
+        assert_eq!(t.balance(&t.env.receiver.pubkey()), AMOUNT);
+        assert_eq!(t.balance(&t.env.sender.pubkey()), sender_before + REWARD);
+        assert_eq!(t.balance(&solver.pubkey()), 0);
+        let htlc = t.htlc(&id(1));
+        assert_eq!(htlc.status, HtlcStatus::Redeemed);
+        assert_eq!(htlc.secret, secret);
+        // The emptied HTLC token account is closed, so a second redeem fails first on it.
+        assert_error(
+            t.redeem(id(1), secret, &solver),
+            ErrorCode::AccountNotInitialized,
+        );
+    }
+}
+
+#[test]
+fn redeem_by_a_third_party_after_the_reward_timelock_splits_the_reward() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let secret = t.lock(id(1));
+        assert_ok(t.lock_reward(id(1), START + 1000));
+        let solver = t.env.solver.insecure_clone();
+        let sender_before = t.balance(&t.env.sender.pubkey());
+
+        t.env.warp(START + 1000);
+        assert_ok(t.redeem(id(1), secret, &solver));
+
+        assert_eq!(t.balance(&t.env.receiver.pubkey()), AMOUNT);
+        assert_eq!(t.balance(&solver.pubkey()), REWARD);
+        assert_eq!(t.balance(&t.env.sender.pubkey()), sender_before);
+    }
+}
+
+#[test]
+fn redeem_by_the_receiver_after_the_reward_timelock_pays_both_to_the_receiver() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let secret = t.lock(id(1));
+        assert_ok(t.lock_reward(id(1), START + 1000));
+        let receiver = t.env.receiver.insecure_clone();
+
+        t.env.warp(START + 1500);
+        assert_ok(t.redeem(id(1), secret, &receiver));
+        assert_eq!(t.balance(&receiver.pubkey()), AMOUNT + REWARD);
+    }
+}
+
+#[test]
+fn refund_only_after_the_timelock() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        t.lock(id(1));
+        assert_ok(t.lock_reward(id(1), START + 1000));
+        let solver = t.env.solver.insecure_clone();
+
+        t.env.warp(START + 1800);
+        assert_error(t.refund(id(1), &solver), HTLCError::NotPastTimeLock);
+
+        t.env.warp(START + 1801);
+        assert_ok(t.refund(id(1), &solver));
+        assert_eq!(t.balance(&t.env.sender.pubkey()), MINTED);
+        assert_eq!(t.htlc(&id(1)).status, HtlcStatus::Refunded);
+        // The emptied HTLC token account is closed.
+        assert!(!t.env.exists(&t.htlc_token_account(&id(1))));
+
+        // Later refunds and redeems fail first on the closed token account.
+        assert_error(t.refund(id(1), &solver), ErrorCode::AccountNotInitialized);
+        assert_error(
+            t.redeem(id(1), secret(1), &solver),
+            ErrorCode::AccountNotInitialized,
+        );
+
+        let sender = t.env.sender.insecure_clone();
+        let close = anchor_htlc::close(&sender.pubkey(), instruction::Close { Id: id(1) });
+        assert_ok(t.env.send(&[close], &[&sender]));
+        assert!(!t.env.exists(&t.htlc_address(&id(1))));
+    }
+}
+
+#[test]
+fn batches_move_the_tokens_of_every_entry() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let meta = assert_ok(t.batch_lock(&[id(1), id(2), id(3)]));
+        assert_eq!(settled(meta), vec![true, true, true]);
+        assert_eq!(t.balance(&t.env.sender.pubkey()), MINTED - 3 * AMOUNT);
+        for seed in 1..=3 {
+            let htlc = t.htlc(&id(seed));
+            assert_eq!(htlc.status, HtlcStatus::Locked);
+            assert_eq!(htlc.amount, AMOUNT);
+            assert_eq!(htlc.token_wallet, t.htlc_token_account(&id(seed)));
+            assert_eq!(t.token_balance(&t.htlc_token_account(&id(seed))), AMOUNT);
+        }
+        assert_error(t.batch_lock(&[id(1)]), HTLCError::HtlcAlreadyExists);
+
+        // batch_redeem does not create the token accounts of the src_receivers
+        let solver = t.env.solver.insecure_clone();
+        assert_error(
+            t.batch_redeem(vec![(id(1), secret(1))], &solver, false),
+            ErrorCode::AccountNotInitialized,
+        );
+        t.create_token_account(&t.env.receiver.pubkey());
+        let meta =
+            assert_ok(t.batch_redeem(vec![(id(1), secret(1)), (id(2), [0; 32])], &solver, true));
+        assert_eq!(settled(meta), vec![true, false]);
+        assert_eq!(t.balance(&t.env.receiver.pubkey()), AMOUNT);
+        assert_eq!(t.htlc(&id(1)).status, HtlcStatus::Redeemed);
+        assert!(!t.env.exists(&t.htlc_token_account(&id(1))));
+        assert_eq!(t.htlc(&id(2)).status, HtlcStatus::Locked);
+
+        // an entry whose record cannot be written is skipped before its tokens move
+        let mut ix = anchor_htlc::batch_redeem(
+            &solver.pubkey(),
+            &t.mint,
+            &t.token_program,
+            &[(t.env.sender.pubkey(), t.env.receiver.pubkey())],
+            instruction::BatchRedeem {
+                entries: vec![(id(2), secret(2))],
+                best_effort: true,
+            },
+        );
+        let record = ix.accounts.len() - 1;
+        ix.accounts[record].pubkey =
+            anchor_htlc::find_secret_record_address(&hashlock(&secret(1))).0;
+        let meta = assert_ok(
+            t.env
+                .send(&[set_compute_unit_limit(1_400_000), ix], &[&solver]),
+        );
+        assert_eq!(settled(meta), vec![false]);
+        assert_eq!(t.balance(&t.env.receiver.pubkey()), AMOUNT);
+        assert_eq!(t.htlc(&id(2)).status, HtlcStatus::Locked);
+
+        t.env.warp(START + 1801);
+        let meta = assert_ok(t.batch_refund(vec![id(2), id(3)], &solver));
+        assert_eq!(settled(meta), vec![true, true]);
+        assert_eq!(t.balance(&t.env.sender.pubkey()), MINTED - AMOUNT);
+        assert_eq!(t.htlc(&id(3)).status, HtlcStatus::Refunded);
+    }
+}
+
+/// A best-effort batch skips an entry before creating its accounts, so its Id
+/// stays free.
+#[test]
+fn batch_lock_skips_an_entry_before_creating_its_accounts() {
+    let mut t = TokenEnv::with_fee_mint(100, u64::MAX);
+    // With the sender paying the fees, the deposit of u64::MAX overflows.
+    let ix = anchor_htlc::batch_lock(
+        &t.env.sender.pubkey(),
+        &t.mint,
+        &t.token_program,
+        instruction::BatchLock {
+            entries: vec![
+                t.lock_entry(id(1)),
+                LockEntry {
+                    amount: u64::MAX,
+                    fee_payer: FeePayer::Sender,
+                    ..t.lock_entry(id(2))
+                },
+            ],
+            best_effort: true,
+        },
+    );
+    let sender = t.env.sender.insecure_clone();
+    let meta = assert_ok(
+        t.env
+            .send(&[set_compute_unit_limit(1_400_000), ix], &[&sender]),
+    );
+    assert_eq!(settled(meta), vec![true, false]);
+    assert_eq!(t.htlc(&id(1)).amount, AMOUNT - AMOUNT / 100);
+    assert!(!t.env.exists(&t.htlc_address(&id(2))));
+    assert!(!t.env.exists(&t.htlc_token_account(&id(2))));
+
+    let meta = assert_ok(t.batch_lock(&[id(2)]));
+    assert_eq!(settled(meta), vec![true]);
+}
+
+#[test]
+fn redeem_by_hashlock_reuses_the_secret_of_a_redeemed_htlc() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let shared = hashlock(&secret(1));
+        let ix = anchor_htlc::batch_lock(
+            &t.env.sender.pubkey(),
+            &t.mint,
+            &t.token_program,
+            instruction::BatchLock {
+                entries: [id(1), id(2), id(3)]
+                    .map(|id| LockEntry {
+                        hashlock: shared,
+                        ..t.lock_entry(id)
+                    })
+                    .to_vec(),
+                best_effort: false,
+            },
+        );
+        assert_ok(t.send_as_sender(ix));
+        let solver = t.env.solver.insecure_clone();
+
+        assert_error(
+            t.redeem_by_hashlock(shared, None, None, vec![id(2)], &solver),
+            HTLCError::SecretNotProvided,
+        );
+        assert_ok(t.redeem(id(1), secret(1), &solver));
+        let record = anchor_htlc::find_secret_record_address(&shared).0;
+        let recorded = anchor_htlc::decode_secret_record(&t.env.data(&record)).unwrap();
+        assert_eq!(recorded.hashlock, shared);
+        assert_eq!(recorded.secret, secret(1));
+        let ix = anchor_htlc::get_secret(instruction::GetSecret { hashlock: shared });
+        let meta = assert_ok(t.env.send(&[ix], &[&solver]));
+        let recorded = SecretRecord::deserialize(&mut &meta.return_data.data[..]).unwrap();
+        assert_eq!(recorded.revealed_at, START);
+
+        let meta =
+            assert_ok(t.redeem_by_hashlock(shared, None, Some(id(1)), vec![id(2), id(3)], &solver));
+        assert_eq!(settled(meta), vec![true, true]);
+        assert_eq!(t.balance(&t.env.receiver.pubkey()), 3 * AMOUNT);
+        for seed in 2..=3 {
+            let htlc = t.htlc(&id(seed));
+            assert_eq!(htlc.status, HtlcStatus::Redeemed);
+            assert_eq!(htlc.secret, secret(1));
+            assert!(!t.env.exists(&t.htlc_token_account(&id(seed))));
+        }
+    }
+}
+
+#[test]
+fn sender_token_account_must_be_the_senders_of_the_mint() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+
+        // The receiver's token account in place of the sender's.
+        let receiver_account = t.create_token_account(&t.env.receiver.pubkey());
+        let mut ix = t.lock_ix(id(1), START + 1800);
+        ix.accounts[4].pubkey = receiver_account;
+        assert_error(t.send_as_sender(ix), HTLCError::NotSender);
+
+        // A token account of another mint.
+        let mint = create_mint(&mut t.env, &token_program);
+        let solver = t.env.solver.insecure_clone();
+        let create = create_associated_token_account(
+            &solver.pubkey(),
+            &t.env.sender.pubkey(),
+            &mint,
+            &token_program,
+        );
+        assert_ok(t.env.send(&[create], &[&solver]));
+        let other_account =
+            anchor_htlc::associated_token_address(&t.env.sender.pubkey(), &mint, &token_program);
+        let mut ix = t.lock_ix(id(1), START + 1800);
+        ix.accounts[4].pubkey = other_account;
+        assert_error(t.send_as_sender(ix), HTLCError::NoToken);
+    }
+}
+
+/// Redeem creates the token accounts of the receiver and the caller first, so
+/// the mint and token program checks of the HTLC are exercised through refund.
+#[test]
+fn refund_needs_the_mint_and_token_program_of_the_htlc() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    t.lock(id(1));
+    let solver = t.env.solver.insecure_clone();
+    t.env.warp(START + 1801);
+    let refund = anchor_htlc::refund(
+        &solver.pubkey(),
+        &t.env.sender.pubkey(),
+        &t.mint,
+        &spl_token::ID,
+        instruction::Refund {
+            Id: id(1),
+            htlc_bump: anchor_htlc::find_htlc_address(&t.env.sender.pubkey(), &id(1)).1,
+        },
+    );
+
+    let mut ix = refund.clone();
+    ix.accounts[4].pubkey = create_mint(&mut t.env, &spl_token::ID);
+    assert_error(t.env.send(&[ix], &[&solver]), HTLCError::NoToken);
+
+    let mut ix = refund.clone();
+    for meta in &mut ix.accounts {
+        if meta.pubkey == spl_token::ID {
+            meta.pubkey = spl_token_2022::ID;
+        }
+    }
+    assert_error(t.env.send(&[ix], &[&solver]), HTLCError::WrongTokenProgram);
+
+    assert_ok(t.env.send(&[refund], &[&solver]));
+}
+
+/// Writes a legacy HTLC at the [Id] PDA holding AMOUNT of `token_contract` in
+/// its [HTLC_TOKEN_ACCOUNT_SEED, Id] token account.
+fn legacy_htlc(t: &mut TokenEnv, id: [u8; 32], token_contract: Pubkey) {
+    let address = anchor_htlc::find_legacy_htlc_address(&id).0;
+    let token_wallet = anchor_htlc::find_legacy_htlc_token_account_address(&id).0;
+    let legacy = anchor_htlc::LegacyHTLC {
+        dst_address: DST_ADDRESS.to_string(),
+        dst_chain: DST_CHAIN.to_string(),
+        dst_asset: DST_ASSET.to_string(),
+        src_asset: SRC_ASSET.to_string(),
+        sender: t.env.sender.pubkey(),
+        src_receiver: t.env.receiver.pubkey(),
+        hashlock: hashlock(&secret(id[0])),
+        secret: [0; 32],
+        amount: AMOUNT,
+        timelock: START + 1800,
+        reward: 0,
+        reward_timelock: 0,
+        token_contract,
+        token_wallet,
+        claimed: 1,
+    };
+    let mut data = HTLC::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    set_account(&mut t.env, address, data, anchor_htlc::ID);
+
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount {
+        mint: t.mint,
+        owner: address,
+        amount: AMOUNT,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+    set_account(&mut t.env, token_wallet, data, t.token_program);
+}
+
+#[test]
+fn migrate_moves_a_legacy_token_htlc() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    let sender = t.env.sender.insecure_clone();
+    let mint = t.mint;
+    legacy_htlc(&mut t, id(1), mint);
+    let migrate = anchor_htlc::migrate(
+        &sender.pubkey(),
+        &mint,
+        &spl_token::ID,
+        instruction::Migrate { Id: id(1) },
+    );
+    assert_ok(t.env.send(&[migrate], &[&sender]));
+
+    assert!(!t
+        .env
+        .exists(&anchor_htlc::find_legacy_htlc_address(&id(1)).0));
+    assert_eq!(t.token_balance(&t.htlc_token_account(&id(1))), AMOUNT);
+    assert_eq!(t.htlc(&id(1)).status, HtlcStatus::Locked);
+
+    let solver = t.env.solver.insecure_clone();
+    assert_ok(t.redeem(id(1), secret(1), &solver));
+    assert_eq!(t.balance(&t.env.receiver.pubkey()), AMOUNT);
+}
+
+#[test]
+fn migrate_needs_the_mint_of_the_legacy_htlc() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    let sender = t.env.sender.insecure_clone();
+    let other_mint = create_mint(&mut t.env, &spl_token::ID);
+    legacy_htlc(&mut t, id(1), other_mint);
+    let migrate = anchor_htlc::migrate(
+        &sender.pubkey(),
+        &t.mint,
+        &spl_token::ID,
+        instruction::Migrate { Id: id(1) },
+    );
+    assert_error(t.env.send(&[migrate], &[&sender]), HTLCError::NoToken);
+}
+
+#[test]
+fn a_timelock_past_i64_max_never_expires() {
+    for token_program in TOKEN_PROGRAMS {
+        let mut t = TokenEnv::new(token_program);
+        let ix = t.commit_ix(id(1), u64::MAX);
+        assert_ok(t.send_as_sender(ix));
+
+        let sender = t.env.sender.insecure_clone();
+        t.env.set_unix_timestamp(i64::MAX);
+        assert_error(t.refund(id(1), &sender), HTLCError::NotPastTimeLock);
+    }
+}
+
+#[test]
+fn a_negative_clock_is_unavailable() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    t.lock(id(1));
+    let sender = t.env.sender.insecure_clone();
+    t.env.set_unix_timestamp(-1);
+
+    let ix = t.commit_ix(id(2), START);
+    assert_error(t.send_as_sender(ix), HTLCError::ClockUnavailable);
+    let ix = t.lock_ix(id(2), START);
+    assert_error(t.send_as_sender(ix), HTLCError::ClockUnavailable);
+    assert_error(t.lock_reward(id(1), START), HTLCError::ClockUnavailable);
+    assert_error(t.refund(id(1), &sender), HTLCError::ClockUnavailable);
+}
+
+/// The token account of an HTLC cannot hold more than u64::MAX, so the HTLC is
+/// written directly.
+#[test]
+fn settling_funds_that_overflow_fails_cleanly() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    let secret = t.lock(id(1));
+    assert_ok(t.lock_reward(id(1), START + 1000));
+    let mut state = t.htlc(&id(1));
+    state.amount = u64::MAX;
+    let mut data = HTLC::DISCRIMINATOR.to_vec();
+    state.serialize(&mut data).unwrap();
+    let address = t.htlc_address(&id(1));
+    t.env.write_data(&address, &data);
+
+    let receiver = t.env.receiver.insecure_clone();
+    t.env.warp(START + 1000);
+    assert_error(
+        t.redeem(id(1), secret, &receiver),
+        HTLCError::ArithmeticOverflow,
+    );
+    t.env.warp(START + 1801);
+    assert_error(t.refund(id(1), &receiver), HTLCError::ArithmeticOverflow);
+}
+
+#[test]
+fn reserve_id_fails_when_the_nonce_overflows() {
+    let mut t = TokenEnv::new(spl_token::ID);
+    let reserve_id = anchor_htlc::reserve_id(&t.env.sender.pubkey());
+    assert_ok(t.send_as_sender(reserve_id.clone()));
+
+    let mut data = SenderState::DISCRIMINATOR.to_vec();
+    SenderState { nonce: u64::MAX }
+        .serialize(&mut data)
+        .unwrap();
+    let state = anchor_htlc::find_sender_state_address(&t.env.sender.pubkey()).0;
+    t.env.write_data(&state, &data);
+    assert_error(t.send_as_sender(reserve_id), HTLCError::ArithmeticOverflow);
+}
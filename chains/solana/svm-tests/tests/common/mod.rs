@@ -0,0 +1,263 @@
+//! Harness shared by the SVM tests: loads both programs, funds the users and
+//! warps the clock.
+
+#![allow(dead_code)]
+
+use anchor_lang::prelude::{AccountInfo, Clock};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use litesvm::{
+    types::{FailedTransactionMetadata, TransactionMetadata, TransactionResult},
+    LiteSVM,
+};
+use sha2::{Digest, Sha256};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_program_runtime::invoke_context::BuiltinFunctionWithContext;
+use solana_program_test::{processor, tokio, ProgramTest};
+use solana_sdk_ids::native_loader;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use solana_transaction_error::TransactionError;
+use std::sync::Once;
+use train_solana_client::{anchor_htlc, native_htlc, Instruction, InstructionError, Pubkey};
+
+/// Unix time the clock starts at.
+pub const START: u64 = 1_700_000_000;
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+/// Genesis hash the programs bind add_lock_sig to when built natively
+/// without a cluster feature, as for these tests.
+pub const GENESIS_HASH: [u8; 32] = sol::GENESIS_HASH;
+/// Deadline of the add_lock_sig signatures.
+pub const DEADLINE: u64 = START + 600;
+
+pub const DST_CHAIN: &str = "ETHEREUM_SEPOLIA";
+pub const DST_ASSET: &str = "ETH";
+pub const DST_ADDRESS: &str = "0x021b6a2ff227f1c71cc6536e7b9e8ecd0d5599b3";
+pub const SRC_ASSET: &str = "SOL";
+
+pub struct Env {
+    pub svm: LiteSVM,
+    pub sender: Keypair,
+    pub receiver: Keypair,
+    /// Third party redeeming or refunding on behalf of others.
+    pub solver: Keypair,
+}
+
+impl Env {
+    /// Adds both programs and funds the sender, the receiver and the solver with 100 SOL.
+    pub fn new() -> Self {
+        let mut svm = LiteSVM::new();
+        add_programs(&mut svm);
+
+        let mut env = Self {
+            svm,
+            sender: Keypair::new(),
+            receiver: Keypair::new(),
+            solver: Keypair::new(),
+        };
+        env.warp(START);
+        for user in [
+            env.sender.pubkey(),
+            env.receiver.pubkey(),
+            env.solver.pubkey(),
+        ] {
+            env.svm.airdrop(&user, 100 * LAMPORTS_PER_SOL).unwrap();
+        }
+        env
+    }
+
+    pub fn now(&self) -> u64 {
+        self.svm.get_sysvar::<Clock>().unix_timestamp as u64
+    }
+
+    /// Moves the clock to `unix_timestamp`, and the slot forward so the next
+    /// transactions are not deduplicated.
+    pub fn warp(&mut self, unix_timestamp: u64) {
+        self.set_unix_timestamp(unix_timestamp as i64);
+    }
+
+    /// Sets the raw Clock timestamp, which may be negative.
+    pub fn set_unix_timestamp(&mut self, unix_timestamp: i64) {
+        let mut clock = self.svm.get_sysvar::<Clock>();
+        clock.unix_timestamp = unix_timestamp;
+        clock.slot += 1;
+        self.svm.set_sysvar::<Clock>(&clock);
+        self.svm.expire_blockhash();
+    }
+
+    /// Sends `instructions` in one transaction paid by the first signer.
+    pub fn send(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> TransactionResult {
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&signers[0].pubkey()),
+            signers,
+            self.svm.latest_blockhash(),
+        );
+        let result = self.svm.send_transaction(transaction);
+        self.svm.expire_blockhash();
+        result
+    }
+
+    /// Overwrites the data of a program account, keeping its size and lamports.
+    pub fn write_data(&mut self, address: &Pubkey, data: &[u8]) {
+        let mut account = self.svm.get_account(address).expect("account exists");
+        account.data[..data.len()].copy_from_slice(data);
+        account.data[data.len()..].fill(0);
+        self.svm.set_account(*address, account).unwrap();
+    }
+
+    pub fn lamports(&self, address: &Pubkey) -> u64 {
+        self.svm.get_balance(address).unwrap_or(0)
+    }
+
+    pub fn data(&self, address: &Pubkey) -> Vec<u8> {
+        self.svm
+            .get_account(address)
+            .unwrap_or_else(|| panic!("no account at {address}"))
+            .data
+    }
+
+    /// Whether `address` holds an account. Closed accounts have no lamports left.
+    pub fn exists(&self, address: &Pubkey) -> bool {
+        self.svm
+            .get_account(address)
+            .is_some_and(|account| account.lamports > 0)
+    }
+}
+
+/// Adds both programs as builtins running their entrypoints natively. Their
+/// syscalls, CPIs included, go through the stubs of solana-program-test, which
+/// keep the runtime checks of the accounts passed to a CPI. Program logs are
+/// printed to stdout rather than recorded in the transaction logs.
+fn add_programs(svm: &mut LiteSVM) {
+    static SYSCALL_STUBS: Once = Once::new();
+    SYSCALL_STUBS.call_once(|| {
+        // The stubs are installed when a ProgramTest bank is set up, which
+        // also turns on its debug logs.
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(ProgramTest::default().start());
+        solana_logger::setup_with_default("error");
+    });
+    let native_htlc: Option<BuiltinFunctionWithContext> = processor!(native_htlc_entry);
+    let anchor_htlc: Option<BuiltinFunctionWithContext> = processor!(anchor_htlc_entry);
+    for (program_id, entrypoint) in [
+        (native_htlc::ID, native_htlc),
+        (anchor_htlc::ID, anchor_htlc),
+    ] {
+        svm.add_builtin(program_id, entrypoint.unwrap());
+        // Owned by the native loader, the runtime calls the builtin itself.
+        svm.set_account(
+            program_id,
+            Account {
+                lamports: 1,
+                data: vec![],
+                owner: native_loader::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    }
+}
+
+// The Anchor entrypoints take accounts that live as long as their AccountInfos.
+fn native_htlc_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    sol::entry(
+        program_id,
+        Box::leak(accounts.to_vec().into_boxed_slice()),
+        data,
+    )
+}
+
+fn anchor_htlc_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    native::entry(
+        program_id,
+        Box::leak(accounts.to_vec().into_boxed_slice()),
+        data,
+    )
+}
+
+pub fn id(seed: u8) -> [u8; 32] {
+    [seed; 32]
+}
+
+pub fn secret(seed: u8) -> [u8; 32] {
+    [seed.wrapping_add(0x80); 32]
+}
+
+pub fn hashlock(secret: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(secret).into()
+}
+
+/// secp256k1 key of an Ethereum wallet.
+pub fn evm_key(seed: u8) -> libsecp256k1::SecretKey {
+    libsecp256k1::SecretKey::parse(&[seed; 32]).unwrap()
+}
+
+pub fn evm_address(key: &libsecp256k1::SecretKey) -> [u8; 20] {
+    let pubkey = libsecp256k1::PublicKey::from_secret_key(key).serialize();
+    train_lock_sig::eip712::evm_address(pubkey[1..].try_into().unwrap())
+}
+
+/// Signs `digest` like eth_signTypedData_v4, returning (r, s, v).
+pub fn evm_sign(key: &libsecp256k1::SecretKey, digest: &[u8; 32]) -> ([u8; 32], [u8; 32], u8) {
+    let (signature, recovery_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(digest), key);
+    let signature = signature.serialize();
+    (
+        signature[..32].try_into().unwrap(),
+        signature[32..].try_into().unwrap(),
+        recovery_id.serialize() + 27,
+    )
+}
+
+/// ComputeBudget SetComputeUnitLimit, the usual first instruction of a
+/// transaction sent by a wallet.
+pub fn set_compute_unit_limit(units: u32) -> Instruction {
+    let mut data = vec![2];
+    data.extend(units.to_le_bytes());
+    Instruction::new_with_bytes(
+        anchor_lang::solana_program::pubkey!("ComputeBudget111111111111111111111111111111"),
+        &data,
+        vec![],
+    )
+}
+
+/// Asserts the transaction succeeded, printing its logs otherwise.
+#[track_caller]
+pub fn assert_ok(result: TransactionResult) -> TransactionMetadata {
+    match result {
+        Ok(meta) => meta,
+        Err(FailedTransactionMetadata { err, meta }) => {
+            panic!("transaction failed: {err:?}\n{}", meta.logs.join("\n"))
+        }
+    }
+}
+
+/// Asserts the transaction failed with the custom error `code`, an HTLCError or
+/// an Anchor ErrorCode.
+#[track_caller]
+pub fn assert_error(result: TransactionResult, code: impl Into<u32>) {
+    let code = code.into();
+    match result {
+        Ok(meta) => panic!(
+            "expected error {code}, transaction succeeded\n{}",
+            meta.logs.join("\n")
+        ),
+        Err(FailedTransactionMetadata { err, meta }) => match err {
+            TransactionError::InstructionError(_, InstructionError::Custom(actual)) => {
+                assert_eq!(actual, code, "unexpected error\n{}", meta.logs.join("\n"))
+            }
+            err => panic!(
+                "expected error {code}, got {err:?}\n{}",
+                meta.logs.join("\n")
+            ),
+        },
+    }
+}
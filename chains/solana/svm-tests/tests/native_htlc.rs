@@ -0,0 +1,1797 @@
+//! native_htlc in an in-process SVM.
+//!
+//! Every HTLCError the program can return has a test. NotOwner,
+//! InvalidStatusTransition, and NotSender outside of migrate and close cannot
+//! be reached: the HTLC PDA is derived from the sender, so another signer fails
+//! the seeds constraint first, which `htlc_seeds_bind_the_sender` checks.
+//! add_lock_sig_legacy is tested with the `legacy-lock-sig` feature, and
+//! LegacyLockSigDisabled without it.
+
+// The error of a LiteSVM transaction carries its metadata.
+#![allow(clippy::result_large_err)]
+
+mod common;
+
+use anchor_lang::{error::ErrorCode, AnchorDeserialize, AnchorSerialize, Discriminator};
+use common::*;
+use litesvm::types::{TransactionMetadata, TransactionResult};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_signer::Signer;
+use train_solana_client::anchor_htlc;
+use train_solana_client::lock_sig;
+use train_solana_client::native_htlc::{
+    self, instruction, HTLCError, HtlcStatus, LockEntry, SecretRecord, SenderState, Session, HTLC,
+    SESSION_ADD_LOCK, SESSION_LOCK_REWARD,
+};
+use train_solana_client::Pubkey;
+
+const AMOUNT: u64 = LAMPORTS_PER_SOL / 100;
+const REWARD: u64 = LAMPORTS_PER_SOL / 1000;
+const FEE: u64 = 5_000;
+
+fn htlc_address(env: &Env, id: &[u8; 32]) -> Pubkey {
+    native_htlc::find_htlc_address(&env.sender.pubkey(), id).0
+}
+
+fn secret_record_address(hashlock: &[u8; 32]) -> Pubkey {
+    native_htlc::find_secret_record_address(hashlock).0
+}
+
+fn htlc(env: &Env, id: &[u8; 32]) -> HTLC {
+    native_htlc::decode_htlc(&env.data(&htlc_address(env, id))).unwrap()
+}
+
+fn commit_args(env: &Env, id: [u8; 32], timelock: u64) -> instruction::Commit {
+    instruction::Commit {
+        Id: id,
+        hopChains: vec![DST_CHAIN.to_string()],
+        hopAssets: vec![DST_ASSET.to_string()],
+        hopAddresses: vec![DST_ADDRESS.to_string()],
+        dst_chain: DST_CHAIN.to_string(),
+        dst_asset: DST_ASSET.to_string(),
+        dst_address: DST_ADDRESS.to_string(),
+        src_asset: SRC_ASSET.to_string(),
+        src_receiver: env.receiver.pubkey(),
+        timelock,
+        amount: AMOUNT,
+        authorized_locker: None,
+    }
+}
+
+fn lock_args(env: &Env, id: [u8; 32], hashlock: [u8; 32], timelock: u64) -> instruction::Lock {
+    instruction::Lock {
+        Id: id,
+        hashlock,
+        timelock,
+        amount: AMOUNT,
+        dst_chain: DST_CHAIN.to_string(),
+        dst_address: DST_ADDRESS.to_string(),
+        dst_asset: DST_ASSET.to_string(),
+        src_asset: SRC_ASSET.to_string(),
+        src_receiver: env.receiver.pubkey(),
+    }
+}
+
+fn commit(env: &mut Env, id: [u8; 32]) {
+    let ix = native_htlc::commit(&env.sender.pubkey(), commit_args(env, id, env.now() + 900));
+    let sender = env.sender.insecure_clone();
+    assert_ok(env.send(&[ix], &[&sender]));
+}
+
+/// Locks AMOUNT for the receiver until now + 1800 and returns the secret.
+fn lock(env: &mut Env, id: [u8; 32]) -> [u8; 32] {
+    let secret = secret(id[0]);
+    let args = lock_args(env, id, hashlock(&secret), env.now() + 1800);
+    let ix = native_htlc::lock(&env.sender.pubkey(), args);
+    let sender = env.sender.insecure_clone();
+    assert_ok(env.send(&[ix], &[&sender]));
+    secret
+}
+
+fn lock_reward(env: &mut Env, id: [u8; 32], reward_timelock: u64) -> TransactionResult {
+    let ix = native_htlc::lock_reward(
+        &env.sender.pubkey(),
+        instruction::LockReward {
+            Id: id,
+            reward_timelock,
+            reward: REWARD,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+fn add_lock(env: &mut Env, id: [u8; 32], timelock: u64) -> TransactionResult {
+    let ix = native_htlc::add_lock(
+        &env.sender.pubkey(),
+        instruction::AddLock {
+            Id: id,
+            hashlock: hashlock(&secret(id[0])),
+            timelock,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+/// add_lock of the sender's HTLC, signed by `locker`.
+fn add_lock_as(env: &mut Env, id: [u8; 32], locker: &Keypair) -> TransactionResult {
+    let ix = native_htlc::add_lock_as(
+        &locker.pubkey(),
+        &env.sender.pubkey(),
+        instruction::AddLock {
+            Id: id,
+            hashlock: hashlock(&secret(id[0])),
+            timelock: env.now() + 900,
+        },
+    );
+    env.send(&[ix], &[locker])
+}
+
+fn set_authorized_locker(
+    env: &mut Env,
+    id: [u8; 32],
+    authorized_locker: Option<Pubkey>,
+) -> TransactionResult {
+    let ix = native_htlc::set_authorized_locker(
+        &env.sender.pubkey(),
+        instruction::SetAuthorizedLocker {
+            Id: id,
+            authorized_locker,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+fn set_evm_authorizer(env: &mut Env, id: [u8; 32], evm_authorizer: [u8; 20]) -> TransactionResult {
+    let ix = native_htlc::set_evm_authorizer(
+        &env.sender.pubkey(),
+        instruction::SetEvmAuthorizer {
+            Id: id,
+            evm_authorizer,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+/// add_lock_sig_evm sent by the solver with the signature of `key`.
+fn add_lock_sig_evm(
+    env: &mut Env,
+    id: [u8; 32],
+    timelock: u64,
+    key: &libsecp256k1::SecretKey,
+) -> TransactionResult {
+    let hashlock = hashlock(&secret(id[0]));
+    let (r, s, v) = evm_sign(
+        key,
+        &native_htlc::add_lock_evm_digest(GENESIS_HASH, &id, &hashlock, timelock, DEADLINE),
+    );
+    let ix = native_htlc::add_lock_sig_evm(
+        &env.solver.pubkey(),
+        &env.sender.pubkey(),
+        instruction::AddLockSigEvm {
+            Id: id,
+            hashlock,
+            timelock,
+            deadline: DEADLINE,
+            r,
+            s,
+            v,
+        },
+    );
+    let solver = env.solver.insecure_clone();
+    env.send(&[ix], &[&solver])
+}
+
+fn redeem(env: &mut Env, id: [u8; 32], secret: [u8; 32], by: &Keypair) -> TransactionResult {
+    let ix = native_htlc::redeem(
+        &by.pubkey(),
+        &env.sender.pubkey(),
+        &env.receiver.pubkey(),
+        instruction::Redeem { Id: id, secret },
+    );
+    env.send(&[ix], &[by])
+}
+
+fn refund(env: &mut Env, id: [u8; 32], by: &Keypair) -> TransactionResult {
+    let ix = native_htlc::refund(
+        &by.pubkey(),
+        &env.sender.pubkey(),
+        instruction::Refund { Id: id },
+    );
+    env.send(&[ix], &[by])
+}
+
+fn lock_entry(env: &Env, id: [u8; 32]) -> LockEntry {
+    let args = lock_args(env, id, hashlock(&secret(id[0])), env.now() + 1800);
+    LockEntry {
+        Id: args.Id,
+        hashlock: args.hashlock,
+        timelock: args.timelock,
+        amount: args.amount,
+        dst_chain: args.dst_chain,
+        dst_address: args.dst_address,
+        dst_asset: args.dst_asset,
+        src_asset: args.src_asset,
+        src_receiver: args.src_receiver,
+    }
+}
+
+fn batch_lock(env: &mut Env, entries: Vec<LockEntry>, best_effort: bool) -> TransactionResult {
+    let ix = native_htlc::batch_lock(
+        &env.sender.pubkey(),
+        instruction::BatchLock {
+            entries,
+            best_effort,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[set_compute_unit_limit(1_400_000), ix], &[&sender])
+}
+
+fn batch_redeem(
+    env: &mut Env,
+    entries: Vec<([u8; 32], [u8; 32])>,
+    by: &Keypair,
+    best_effort: bool,
+) -> TransactionResult {
+    let htlcs = vec![(env.sender.pubkey(), env.receiver.pubkey()); entries.len()];
+    let ix = native_htlc::batch_redeem(
+        &by.pubkey(),
+        &htlcs,
+        instruction::BatchRedeem {
+            entries,
+            best_effort,
+        },
+    );
+    env.send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+}
+
+fn batch_refund(
+    env: &mut Env,
+    ids: Vec<[u8; 32]>,
+    by: &Keypair,
+    best_effort: bool,
+) -> TransactionResult {
+    let senders = vec![env.sender.pubkey(); ids.len()];
+    let ix = native_htlc::batch_refund(
+        &by.pubkey(),
+        &senders,
+        instruction::BatchRefund {
+            Ids: ids,
+            best_effort,
+        },
+    );
+    env.send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+}
+
+fn redeem_by_hashlock(
+    env: &mut Env,
+    hashlock: [u8; 32],
+    secret: Option<[u8; 32]>,
+    proof: Option<[u8; 32]>,
+    ids: Vec<[u8; 32]>,
+    by: &Keypair,
+) -> TransactionResult {
+    let htlcs = vec![(env.sender.pubkey(), env.receiver.pubkey()); ids.len()];
+    let ix = native_htlc::redeem_by_hashlock(
+        &by.pubkey(),
+        proof.map(|proof| htlc_address(env, &proof)),
+        &htlcs,
+        instruction::RedeemByHashlock {
+            hashlock,
+            secret,
+            Ids: ids,
+            best_effort: true,
+        },
+    );
+    env.send(&[set_compute_unit_limit(1_400_000), ix], &[by])
+}
+
+/// The entries a batch reports as settled.
+fn settled(meta: TransactionMetadata) -> Vec<bool> {
+    Vec::<bool>::deserialize(&mut &meta.return_data.data[..]).unwrap()
+}
+
+fn session_address(env: &Env) -> Pubkey {
+    native_htlc::find_session_address(&env.sender.pubkey(), &env.solver.pubkey()).0
+}
+
+fn session(env: &Env) -> Session {
+    native_htlc::decode_session(&env.data(&session_address(env))).unwrap()
+}
+
+/// Lets the solver act for the sender until DEADLINE.
+fn create_session(env: &mut Env, max_amount: u64, permissions: u8) -> TransactionResult {
+    let ix = native_htlc::create_session(
+        &env.sender.pubkey(),
+        instruction::CreateSession {
+            delegate: env.solver.pubkey(),
+            expiry: DEADLINE,
+            max_amount,
+            permissions,
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+fn revoke_session(env: &mut Env) -> TransactionResult {
+    let ix = native_htlc::revoke_session(
+        &env.sender.pubkey(),
+        instruction::RevokeSession {
+            delegate: env.solver.pubkey(),
+        },
+    );
+    let sender = env.sender.insecure_clone();
+    env.send(&[ix], &[&sender])
+}
+
+/// add_lock of the sender's HTLC, signed by the solver through its session.
+fn add_lock_by_session(env: &mut Env, id: [u8; 32]) -> TransactionResult {
+    let ix = native_htlc::add_lock_by_session(
+        &env.solver.pubkey(),
+        &env.sender.pubkey(),
+        instruction::AddLock {
+            Id: id,
+            hashlock: hashlock(&secret(id[0])),
+            timelock: env.now() + 900,
+        },
+    );
+    let solver = env.solver.insecure_clone();
+    env.send(&[ix], &[&solver])
+}
+
+fn lock_reward_by_session(env: &mut Env, id: [u8; 32]) -> TransactionResult {
+    let ix = native_htlc::lock_reward_by_session(
+        &env.solver.pubkey(),
+        &env.sender.pubkey(),
+        instruction::LockReward {
+            Id: id,
+            reward_timelock: env.now() + 1000,
+            reward: REWARD,
+        },
+    );
+    let solver = env.solver.insecure_clone();
+    env.send(&[ix], &[&solver])
+}
+
+#[test]
+fn commit_records_the_htlc_and_takes_the_funds() {
+    let mut env = Env::new();
+    let id = id(1);
+    let before = env.lamports(&env.sender.pubkey());
+    commit(&mut env, id);
+
+    let htlc = htlc(&env, &id);
+    assert_eq!(htlc.status, HtlcStatus::Committed);
+    assert_eq!(htlc.sender, env.sender.pubkey());
+    assert_eq!(htlc.src_receiver, env.receiver.pubkey());
+    assert_eq!(htlc.amount, AMOUNT);
+    assert_eq!(htlc.timelock, START + 900);
+    assert_eq!(htlc.hashlock, [0; 32]);
+    assert_eq!(htlc.hop_chains, vec![DST_CHAIN.to_string()]);
+
+    let address = htlc_address(&env, &id);
+    let rent = env.lamports(&address) - AMOUNT;
+    assert_eq!(
+        env.lamports(&env.sender.pubkey()),
+        before - AMOUNT - rent - FEE
+    );
+}
+
+#[test]
+fn commit_timelock_must_be_at_least_900_seconds_away() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let ix = native_htlc::commit(&sender.pubkey(), commit_args(&env, id(1), START + 899));
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::InvalidTimeLock);
+
+    let ix = native_htlc::commit(&sender.pubkey(), commit_args(&env, id(1), START + 900));
+    assert_ok(env.send(&[ix], &[&sender]));
+}
+
+#[test]
+fn lock_timelock_must_be_at_least_1800_seconds_away() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+    let ix = native_htlc::lock(
+        &sender.pubkey(),
+        lock_args(&env, id(1), hashlock, START + 1799),
+    );
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::InvalidTimeLock);
+
+    let ix = native_htlc::lock(
+        &sender.pubkey(),
+        lock_args(&env, id(1), hashlock, START + 1800),
+    );
+    assert_ok(env.send(&[ix], &[&sender]));
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Locked);
+}
+
+#[test]
+fn commit_and_lock_need_funds() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let mut args = commit_args(&env, id(1), START + 900);
+    args.amount = 0;
+    let ix = native_htlc::commit(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::FundsNotSent);
+
+    let mut args = lock_args(&env, id(2), hashlock(&secret(2)), START + 1800);
+    args.amount = 0;
+    let ix = native_htlc::lock(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::FundsNotSent);
+}
+
+#[test]
+fn strings_and_hops_are_bounded() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let too_long = "x".repeat(native_htlc::MAX_CHAIN_LEN + 1);
+
+    let mut args = commit_args(&env, id(1), START + 900);
+    args.dst_chain = too_long.clone();
+    let ix = native_htlc::commit(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::StringTooLong);
+
+    let mut args = lock_args(&env, id(1), hashlock(&secret(1)), START + 1800);
+    args.dst_chain = too_long.clone();
+    let ix = native_htlc::lock(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::StringTooLong);
+
+    let mut args = commit_args(&env, id(1), START + 900);
+    args.hopChains[0] = too_long;
+    let ix = native_htlc::commit(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::StringTooLong);
+
+    let mut args = commit_args(&env, id(1), START + 900);
+    args.hopAssets.clear();
+    let ix = native_htlc::commit(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::HopLengthMismatch);
+
+    let mut args = commit_args(&env, id(1), START + 900);
+    let hops = native_htlc::MAX_HOPS + 1;
+    args.hopChains = vec![DST_CHAIN.to_string(); hops];
+    args.hopAssets = vec![DST_ASSET.to_string(); hops];
+    args.hopAddresses = vec![DST_ADDRESS.to_string(); hops];
+    let ix = native_htlc::commit(&sender.pubkey(), args);
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::TooManyHops);
+}
+
+#[test]
+fn add_lock_timelock_must_be_at_least_900_seconds_away() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    assert_error(
+        add_lock(&mut env, id(1), START + 899),
+        HTLCError::InvalidTimeLock,
+    );
+    assert_ok(add_lock(&mut env, id(1), START + 900));
+
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.hashlock, hashlock(&secret(1)));
+    assert_eq!(htlc.timelock, START + 900);
+
+    assert_error(
+        add_lock(&mut env, id(1), START + 900),
+        HTLCError::HashlockAlreadySet,
+    );
+}
+
+#[test]
+fn add_lock_sig_sets_the_hashlock_signed_by_the_sender() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+
+    let [ed25519_ix, ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        START + 899,
+        DEADLINE,
+    )
+    .unwrap();
+    assert_error(
+        env.send(&[ed25519_ix, ix], &[&solver]),
+        HTLCError::InvalidTimeLock,
+    );
+
+    let [ed25519_ix, ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        START + 900,
+        DEADLINE,
+    )
+    .unwrap();
+    assert_ok(env.send(&[ed25519_ix, ix], &[&solver]));
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.hashlock, hashlock);
+}
+
+#[test]
+fn add_lock_sig_finds_the_signature_anywhere_in_the_transaction() {
+    let mut env = Env::new();
+    let solver = env.solver.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+
+    // After a compute budget instruction.
+    commit(&mut env, id(1));
+    let [ed25519_ix, ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        START + 900,
+        DEADLINE,
+    )
+    .unwrap();
+    assert_ok(env.send(
+        &[set_compute_unit_limit(200_000), ed25519_ix, ix],
+        &[&solver],
+    ));
+    assert_eq!(htlc(&env, &id(1)).hashlock, hashlock);
+
+    // After add_lock_sig itself.
+    commit(&mut env, id(2));
+    let [ed25519_ix, ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(2),
+        hashlock,
+        START + 900,
+        DEADLINE,
+    )
+    .unwrap();
+    assert_ok(env.send(
+        &[set_compute_unit_limit(200_000), ix, ed25519_ix],
+        &[&solver],
+    ));
+    assert_eq!(htlc(&env, &id(2)).hashlock, hashlock);
+}
+
+#[test]
+fn add_lock_sig_rejects_other_signatures() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+    let timelock = START + 900;
+
+    // A valid signature, but not by the sender.
+    let domain = native_htlc::add_lock_sig_domain(GENESIS_HASH);
+    let (signature, ed25519_ix) =
+        lock_sig::sign_add_lock(&solver, &domain, &id(1), &hashlock, timelock, DEADLINE).unwrap();
+    let args = instruction::AddLockSig {
+        Id: id(1),
+        hashlock,
+        timelock,
+        deadline: DEADLINE,
+        signature,
+    };
+    let ix = native_htlc::add_lock_sig(&solver.pubkey(), &env.sender.pubkey(), args);
+    assert_error(
+        env.send(&[ed25519_ix, ix], &[&solver]),
+        HTLCError::SigVerificationFailed,
+    );
+
+    // No Ed25519Program instruction in the transaction.
+    let [_, ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        timelock,
+        DEADLINE,
+    )
+    .unwrap();
+    assert_error(
+        env.send(&[ix], &[&solver]),
+        HTLCError::SigVerificationFailed,
+    );
+
+    // The signature covers another timelock.
+    let [ed25519_ix, mut ix] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        timelock + 1,
+        DEADLINE,
+    )
+    .unwrap();
+    ix.data = anchor_lang::InstructionData::data(&instruction::AddLockSig {
+        Id: id(1),
+        hashlock,
+        timelock,
+        deadline: DEADLINE,
+        signature: [0; 64],
+    });
+    assert_error(
+        env.send(&[ed25519_ix, ix], &[&solver]),
+        HTLCError::SigVerificationFailed,
+    );
+}
+
+#[test]
+fn add_lock_sig_binds_the_program_the_cluster_and_the_deadline() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+    let timelock = START + 900;
+
+    // Signed for another cluster, or for the token program.
+    let send_signed_for = |env: &mut Env, domain: lock_sig::Domain| {
+        let (signature, ed25519_ix) =
+            lock_sig::sign_add_lock(&env.sender, &domain, &id(1), &hashlock, timelock, DEADLINE)
+                .unwrap();
+        let args = instruction::AddLockSig {
+            Id: id(1),
+            hashlock,
+            timelock,
+            deadline: DEADLINE,
+            signature,
+        };
+        let ix = native_htlc::add_lock_sig(&solver.pubkey(), &env.sender.pubkey(), args);
+        env.send(&[ed25519_ix, ix], &[&solver])
+    };
+    assert_error(
+        send_signed_for(
+            &mut env,
+            native_htlc::add_lock_sig_domain(lock_sig::DEVNET_GENESIS_HASH),
+        ),
+        HTLCError::SigVerificationFailed,
+    );
+    assert_error(
+        send_signed_for(&mut env, anchor_htlc::add_lock_sig_domain(GENESIS_HASH)),
+        HTLCError::SigVerificationFailed,
+    );
+
+    // The signature is accepted until its deadline, not after.
+    let lock = |id: [u8; 32]| {
+        native_htlc::signed_add_lock_sig(
+            &solver.pubkey(),
+            &env.sender,
+            GENESIS_HASH,
+            id,
+            hashlock,
+            DEADLINE + 900,
+            DEADLINE,
+        )
+        .unwrap()
+    };
+    let expired = lock(id(1));
+    let on_time = lock(id(2));
+    commit(&mut env, id(2));
+    env.warp(DEADLINE);
+    assert_ok(env.send(&on_time, &[&solver]));
+    env.warp(DEADLINE + 1);
+    assert_error(env.send(&expired, &[&solver]), HTLCError::SignatureExpired);
+}
+
+#[test]
+#[cfg(not(feature = "legacy-lock-sig"))]
+fn add_lock_sig_legacy_is_disabled_by_default() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let instructions = native_htlc::signed_add_lock_sig_legacy(
+        &solver.pubkey(),
+        &env.sender,
+        id(1),
+        hashlock(&secret(1)),
+        START + 900,
+    )
+    .unwrap();
+    assert_error(
+        env.send(&instructions, &[&solver]),
+        HTLCError::LegacyLockSigDisabled,
+    );
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Committed);
+}
+
+#[test]
+#[cfg(feature = "legacy-lock-sig")]
+fn add_lock_sig_legacy_accepts_the_legacy_message() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let hashlock = hashlock(&secret(1));
+
+    // The new message is not accepted by the legacy instruction, nor the
+    // legacy message by add_lock_sig.
+    let [ed25519_ix, _] = native_htlc::signed_add_lock_sig(
+        &solver.pubkey(),
+        &env.sender,
+        GENESIS_HASH,
+        id(1),
+        hashlock,
+        START + 900,
+        START + 900,
+    )
+    .unwrap();
+    let [legacy_ed25519_ix, legacy_ix] = native_htlc::signed_add_lock_sig_legacy(
+        &solver.pubkey(),
+        &env.sender,
+        id(1),
+        hashlock,
+        START + 900,
+    )
+    .unwrap();
+    assert_error(
+        env.send(&[ed25519_ix, legacy_ix.clone()], &[&solver]),
+        HTLCError::SigVerificationFailed,
+    );
+    let ix = native_htlc::add_lock_sig(
+        &solver.pubkey(),
+        &env.sender.pubkey(),
+        instruction::AddLockSig {
+            Id: id(1),
+            hashlock,
+            timelock: START + 900,
+            deadline: START + 900,
+            signature: legacy_ed25519_ix.data[48..112].try_into().unwrap(),
+        },
+    );
+    assert_error(
+        env.send(&[legacy_ed25519_ix.clone(), ix], &[&solver]),
+        HTLCError::SigVerificationFailed,
+    );
+
+    assert_ok(env.send(&[legacy_ed25519_ix, legacy_ix], &[&solver]));
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.hashlock, hashlock);
+}
+
+#[test]
+fn add_lock_sig_evm_sets_the_hashlock_signed_by_the_evm_authorizer() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let key = evm_key(1);
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), START + 900, &key),
+        HTLCError::NoEvmAuthorizer,
+    );
+
+    assert_ok(set_evm_authorizer(&mut env, id(1), evm_address(&key)));
+    assert_eq!(htlc(&env, &id(1)).evm_authorizer, evm_address(&key));
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), START + 899, &key),
+        HTLCError::InvalidTimeLock,
+    );
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), lock_sig::eip712::MAX_TIMELOCK + 1, &key),
+        HTLCError::InvalidTimeLock,
+    );
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), START + 900, &evm_key(2)),
+        HTLCError::SigVerificationFailed,
+    );
+
+    assert_ok(add_lock_sig_evm(&mut env, id(1), START + 900, &key));
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.hashlock, hashlock(&secret(1)));
+    assert_eq!(htlc.timelock, START + 900);
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), START + 900, &key),
+        HTLCError::HashlockAlreadySet,
+    );
+}
+
+#[test]
+fn add_lock_sig_evm_binds_the_program_the_cluster_and_the_deadline() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let key = evm_key(1);
+    assert_ok(set_evm_authorizer(&mut env, id(1), evm_address(&key)));
+    let hashlock = hashlock(&secret(1));
+    let timelock = DEADLINE + 900;
+
+    let send = |env: &mut Env, digest: [u8; 32]| {
+        let (r, s, v) = evm_sign(&key, &digest);
+        let ix = native_htlc::add_lock_sig_evm(
+            &env.solver.pubkey(),
+            &env.sender.pubkey(),
+            instruction::AddLockSigEvm {
+                Id: id(1),
+                hashlock,
+                timelock,
+                deadline: DEADLINE,
+                r,
+                s,
+                v,
+            },
+        );
+        let solver = env.solver.insecure_clone();
+        env.send(&[ix], &[&solver])
+    };
+    let native_digest = |genesis_hash, timelock, deadline| {
+        native_htlc::add_lock_evm_digest(genesis_hash, &id(1), &hashlock, timelock, deadline)
+    };
+
+    // Signed for the token program.
+    let digest = train_solana_client::anchor_htlc::add_lock_evm_digest(
+        GENESIS_HASH,
+        &id(1),
+        &hashlock,
+        timelock,
+        DEADLINE,
+    );
+    assert_error(send(&mut env, digest), HTLCError::SigVerificationFailed);
+    // Signed for another cluster, timelock or deadline.
+    for digest in [
+        native_digest(lock_sig::DEVNET_GENESIS_HASH, timelock, DEADLINE),
+        native_digest(GENESIS_HASH, timelock + 1, DEADLINE),
+        native_digest(GENESIS_HASH, timelock, DEADLINE + 1),
+    ] {
+        assert_error(send(&mut env, digest), HTLCError::SigVerificationFailed);
+    }
+
+    // The signature is accepted until its deadline, not after.
+    let digest = native_digest(GENESIS_HASH, timelock, DEADLINE);
+    env.warp(DEADLINE + 1);
+    assert_error(send(&mut env, digest), HTLCError::SignatureExpired);
+    env.warp(DEADLINE);
+    assert_ok(send(&mut env, digest));
+}
+
+#[test]
+fn authorized_locker_adds_the_lock_without_the_sender() {
+    let mut env = Env::new();
+    let solver = env.solver.insecure_clone();
+    let receiver = env.receiver.insecure_clone();
+    let mut args = commit_args(&env, id(1), START + 900);
+    args.authorized_locker = Some(solver.pubkey());
+    let ix = native_htlc::commit(&env.sender.pubkey(), args);
+    let sender = env.sender.insecure_clone();
+    assert_ok(env.send(&[ix], &[&sender]));
+    assert_eq!(htlc(&env, &id(1)).authorized_locker, Some(solver.pubkey()));
+
+    assert_error(
+        add_lock_as(&mut env, id(1), &receiver),
+        HTLCError::UnauthorizedAccess,
+    );
+    assert_ok(add_lock_as(&mut env, id(1), &solver));
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.hashlock, hashlock(&secret(1)));
+}
+
+#[test]
+fn set_authorized_locker_rotates_and_revokes() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let receiver = env.receiver.insecure_clone();
+    assert_eq!(htlc(&env, &id(1)).authorized_locker, None);
+    assert_error(
+        add_lock_as(&mut env, id(1), &solver),
+        HTLCError::UnauthorizedAccess,
+    );
+
+    // The solver can only address its own HTLCs.
+    let ix = native_htlc::set_authorized_locker(
+        &solver.pubkey(),
+        instruction::SetAuthorizedLocker {
+            Id: id(1),
+            authorized_locker: Some(solver.pubkey()),
+        },
+    );
+    assert_error(
+        env.send(&[ix], &[&solver]),
+        ErrorCode::AccountNotInitialized,
+    );
+
+    assert_ok(set_authorized_locker(
+        &mut env,
+        id(1),
+        Some(solver.pubkey()),
+    ));
+    assert_ok(set_authorized_locker(
+        &mut env,
+        id(1),
+        Some(receiver.pubkey()),
+    ));
+    assert_error(
+        add_lock_as(&mut env, id(1), &solver),
+        HTLCError::UnauthorizedAccess,
+    );
+    assert_ok(set_authorized_locker(&mut env, id(1), None));
+    assert_error(
+        add_lock_as(&mut env, id(1), &receiver),
+        HTLCError::UnauthorizedAccess,
+    );
+
+    // The sender can always add the lock, and nobody can be authorized after.
+    assert_ok(add_lock(&mut env, id(1), START + 900));
+    assert_error(
+        set_authorized_locker(&mut env, id(1), Some(solver.pubkey())),
+        HTLCError::HashlockAlreadySet,
+    );
+}
+
+#[test]
+fn session_delegate_adds_the_lock_and_the_reward_within_its_budget() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    lock(&mut env, id(2));
+    lock(&mut env, id(3));
+    assert_error(
+        add_lock_by_session(&mut env, id(1)),
+        ErrorCode::AccountNotInitialized,
+    );
+
+    let before = env.lamports(&env.sender.pubkey());
+    assert_ok(create_session(
+        &mut env,
+        REWARD,
+        SESSION_ADD_LOCK | SESSION_LOCK_REWARD,
+    ));
+    let rent = env.lamports(&session_address(&env)) - REWARD;
+    assert_eq!(
+        env.lamports(&env.sender.pubkey()),
+        before - REWARD - rent - FEE
+    );
+
+    assert_ok(add_lock_by_session(&mut env, id(1)));
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Locked);
+
+    let before = env.lamports(&htlc_address(&env, &id(2)));
+    assert_ok(lock_reward_by_session(&mut env, id(2)));
+    assert_eq!(env.lamports(&htlc_address(&env, &id(2))), before + REWARD);
+    assert_eq!(env.lamports(&session_address(&env)), rent);
+    let session = session(&env);
+    assert_eq!((session.spent, session.uses), (REWARD, 2));
+
+    assert_error(
+        lock_reward_by_session(&mut env, id(3)),
+        HTLCError::SessionBudgetExceeded,
+    );
+}
+
+#[test]
+fn session_is_bound_to_its_permissions_delegate_and_expiry() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    commit(&mut env, id(2));
+    assert_ok(create_session(&mut env, REWARD, SESSION_ADD_LOCK));
+    assert_error(
+        lock_reward_by_session(&mut env, id(1)),
+        HTLCError::SessionNotPermitted,
+    );
+
+    // The receiver passes the session of the solver.
+    let receiver = env.receiver.insecure_clone();
+    let mut ix = native_htlc::add_lock_by_session(
+        &receiver.pubkey(),
+        &env.sender.pubkey(),
+        instruction::AddLock {
+            Id: id(2),
+            hashlock: hashlock(&secret(2)),
+            timelock: env.now() + 900,
+        },
+    );
+    ix.accounts[3].pubkey = session_address(&env);
+    assert_error(env.send(&[ix], &[&receiver]), ErrorCode::ConstraintSeeds);
+
+    env.warp(DEADLINE + 1);
+    assert_error(
+        add_lock_by_session(&mut env, id(2)),
+        HTLCError::SessionExpired,
+    );
+
+    // Revoking closes the session and returns its budget and rent at once.
+    let before = env.lamports(&env.sender.pubkey());
+    let held = env.lamports(&session_address(&env));
+    assert_ok(revoke_session(&mut env));
+    assert!(!env.exists(&session_address(&env)));
+    assert_eq!(env.lamports(&env.sender.pubkey()), before + held - FEE);
+    assert_error(
+        add_lock_by_session(&mut env, id(2)),
+        ErrorCode::AccountNotInitialized,
+    );
+    assert_error(
+        create_session(&mut env, REWARD, SESSION_ADD_LOCK),
+        HTLCError::SessionExpired,
+    );
+}
+
+#[test]
+fn set_evm_authorizer_only_before_the_hashlock() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let key = evm_key(1);
+
+    // The solver can only address its own HTLCs.
+    let solver = env.solver.insecure_clone();
+    let ix = native_htlc::set_evm_authorizer(
+        &solver.pubkey(),
+        instruction::SetEvmAuthorizer {
+            Id: id(1),
+            evm_authorizer: evm_address(&key),
+        },
+    );
+    assert_error(
+        env.send(&[ix], &[&solver]),
+        ErrorCode::AccountNotInitialized,
+    );
+
+    // The zero address removes the authorizer.
+    assert_ok(set_evm_authorizer(&mut env, id(1), evm_address(&key)));
+    assert_ok(set_evm_authorizer(&mut env, id(1), [0; 20]));
+    assert_error(
+        add_lock_sig_evm(&mut env, id(1), START + 900, &key),
+        HTLCError::NoEvmAuthorizer,
+    );
+
+    assert_ok(add_lock(&mut env, id(1), START + 900));
+    assert_error(
+        set_evm_authorizer(&mut env, id(1), evm_address(&key)),
+        HTLCError::HashlockAlreadySet,
+    );
+    env.warp(START + 901);
+    let sender = env.sender.insecure_clone();
+    assert_ok(refund(&mut env, id(1), &sender));
+    assert_error(
+        set_evm_authorizer(&mut env, id(1), evm_address(&key)),
+        HTLCError::AlreadyRefunded,
+    );
+}
+
+#[test]
+fn htlc_seeds_bind_the_sender() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+
+    // The solver passes its own key as sender with the sender's HTLC.
+    let mut ix = native_htlc::add_lock(
+        &solver.pubkey(),
+        instruction::AddLock {
+            Id: id(1),
+            hashlock: [1; 32],
+            timelock: START + 900,
+        },
+    );
+    ix.accounts[2].pubkey = htlc_address(&env, &id(1));
+    assert_error(env.send(&[ix], &[&solver]), ErrorCode::ConstraintSeeds);
+}
+
+#[test]
+fn lock_reward_window_is_between_now_and_the_timelock() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    let timelock = START + 1800;
+
+    assert_error(
+        lock_reward(&mut env, id(1), START),
+        HTLCError::InvalidRewardTimeLock,
+    );
+    assert_error(
+        lock_reward(&mut env, id(1), timelock),
+        HTLCError::InvalidRewardTimeLock,
+    );
+
+    let before = env.lamports(&htlc_address(&env, &id(1)));
+    assert_ok(lock_reward(&mut env, id(1), timelock - 1));
+    assert_eq!(env.lamports(&htlc_address(&env, &id(1))), before + REWARD);
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.reward, REWARD);
+    assert_eq!(htlc.reward_timelock, timelock - 1);
+
+    assert_error(
+        lock_reward(&mut env, id(1), timelock - 1),
+        HTLCError::RewardAlreadyExists,
+    );
+}
+
+#[test]
+fn redeem_pays_the_receiver() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+
+    assert_error(
+        redeem(&mut env, id(1), [0; 32], &solver),
+        HTLCError::HashlockNoMatch,
+    );
+    assert_ok(redeem(&mut env, id(1), secret, &solver));
+
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + AMOUNT
+    );
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Redeemed);
+    assert_eq!(htlc.secret, secret);
+
+    assert_error(
+        redeem(&mut env, id(1), secret, &solver),
+        HTLCError::AlreadyRedeemed,
+    );
+    assert_error(
+        lock_reward(&mut env, id(1), START + 1000),
+        HTLCError::AlreadyRedeemed,
+    );
+    env.warp(START + 1801);
+    assert_error(refund(&mut env, id(1), &solver), HTLCError::AlreadyRedeemed);
+}
+
+#[test]
+fn redeem_needs_a_hashlock_and_the_receiver() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    assert_error(
+        redeem(&mut env, id(1), secret(1), &solver),
+        HTLCError::HashlockNotSet,
+    );
+
+    assert_ok(add_lock(&mut env, id(1), START + 900));
+    let ix = native_htlc::redeem(
+        &solver.pubkey(),
+        &env.sender.pubkey(),
+        &solver.pubkey(),
+        instruction::Redeem {
+            Id: id(1),
+            secret: secret(1),
+        },
+    );
+    assert_error(env.send(&[ix], &[&solver]), HTLCError::NotReciever);
+}
+
+#[test]
+fn redeem_before_the_reward_timelock_returns_the_reward_to_the_sender() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    assert_ok(lock_reward(&mut env, id(1), START + 1000));
+    let solver = env.solver.insecure_clone();
+    let sender_before = env.lamports(&env.sender.pubkey());
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+    let solver_before = env.lamports(&solver.pubkey());
+
+    env.warp(START + 999);
+    assert_ok(redeem(&mut env, id(1), secret, &solver));
+    let record_rent = env.lamports(&secret_record_address(&hashlock(&secret)));
+
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + AMOUNT
+    );
+    assert_eq!(env.lamports(&env.sender.pubkey()), sender_before + REWARD);
+    assert_eq!(
+        env.lamports(&solver.pubkey()),
+        solver_before - record_rent - FEE
+    );
+}
+
+#[test]
+fn redeem_by_a_third_party_after_the_reward_timelock_splits_the_reward() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    assert_ok(lock_reward(&mut env, id(1), START + 1000));
+    let solver = env.solver.insecure_clone();
+    let sender_before = env.lamports(&env.sender.pubkey());
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+    let solver_before = env.lamports(&solver.pubkey());
+
+    // The reward is due from the reward timelock itself.
+    env.warp(START + 1000);
+    assert_ok(redeem(&mut env, id(1), secret, &solver));
+    let record_rent = env.lamports(&secret_record_address(&hashlock(&secret)));
+
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + AMOUNT
+    );
+    assert_eq!(
+        env.lamports(&solver.pubkey()),
+        solver_before + REWARD - record_rent - FEE
+    );
+    assert_eq!(env.lamports(&env.sender.pubkey()), sender_before);
+}
+
+#[test]
+fn redeem_by_the_receiver_after_the_reward_timelock_pays_both_to_the_receiver() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    assert_ok(lock_reward(&mut env, id(1), START + 1000));
+    let receiver = env.receiver.insecure_clone();
+    let sender_before = env.lamports(&env.sender.pubkey());
+    let receiver_before = env.lamports(&receiver.pubkey());
+
+    env.warp(START + 1500);
+    assert_ok(redeem(&mut env, id(1), secret, &receiver));
+    let record_rent = env.lamports(&secret_record_address(&hashlock(&secret)));
+
+    assert_eq!(
+        env.lamports(&receiver.pubkey()),
+        receiver_before + AMOUNT + REWARD - record_rent - FEE
+    );
+    assert_eq!(env.lamports(&env.sender.pubkey()), sender_before);
+}
+
+#[test]
+fn refund_only_after_the_timelock() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    assert_ok(lock_reward(&mut env, id(1), START + 1000));
+    let solver = env.solver.insecure_clone();
+    let sender_before = env.lamports(&env.sender.pubkey());
+
+    assert_error(refund(&mut env, id(1), &solver), HTLCError::NotPastTimeLock);
+    env.warp(START + 1800);
+    assert_error(refund(&mut env, id(1), &solver), HTLCError::NotPastTimeLock);
+
+    env.warp(START + 1801);
+    assert_ok(refund(&mut env, id(1), &solver));
+    assert_eq!(
+        env.lamports(&env.sender.pubkey()),
+        sender_before + AMOUNT + REWARD
+    );
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Refunded);
+
+    assert_error(refund(&mut env, id(1), &solver), HTLCError::AlreadyRefunded);
+    assert_error(
+        redeem(&mut env, id(1), secret(1), &solver),
+        HTLCError::AlreadyRefunded,
+    );
+}
+
+#[test]
+fn refund_a_committed_htlc() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let sender = env.sender.insecure_clone();
+    env.warp(START + 901);
+    let before = env.lamports(&sender.pubkey());
+    assert_ok(refund(&mut env, id(1), &sender));
+    assert_eq!(env.lamports(&sender.pubkey()), before + AMOUNT - FEE);
+    assert_error(
+        add_lock(&mut env, id(1), START + 2000),
+        HTLCError::AlreadyRefunded,
+    );
+}
+
+#[test]
+fn batch_lock_redeem_and_refund_settle_every_entry() {
+    let mut env = Env::new();
+    let sender_before = env.lamports(&env.sender.pubkey());
+    let entries = vec![
+        lock_entry(&env, id(1)),
+        lock_entry(&env, id(2)),
+        lock_entry(&env, id(3)),
+    ];
+    let meta = assert_ok(batch_lock(&mut env, entries, false));
+    assert_eq!(settled(meta), vec![true, true, true]);
+    let rent = env.lamports(&htlc_address(&env, &id(1))) - AMOUNT;
+    assert_eq!(
+        env.lamports(&env.sender.pubkey()),
+        sender_before - 3 * (AMOUNT + rent) - FEE
+    );
+    for seed in 1..=3 {
+        let htlc = htlc(&env, &id(seed));
+        assert_eq!(htlc.status, HtlcStatus::Locked);
+        assert_eq!(htlc.hashlock, hashlock(&secret(seed)));
+        assert_eq!(htlc.amount, AMOUNT);
+    }
+    let again = lock_entry(&env, id(1));
+    assert_error(
+        batch_lock(&mut env, vec![again], false),
+        HTLCError::HtlcAlreadyExists,
+    );
+
+    let solver = env.solver.insecure_clone();
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+    let meta = assert_ok(batch_redeem(
+        &mut env,
+        vec![(id(1), secret(1)), (id(2), secret(2))],
+        &solver,
+        false,
+    ));
+    assert_eq!(settled(meta), vec![true, true]);
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + 2 * AMOUNT
+    );
+    assert_eq!(htlc(&env, &id(1)).secret, secret(1));
+    assert_eq!(htlc(&env, &id(2)).status, HtlcStatus::Redeemed);
+
+    assert_error(
+        batch_refund(&mut env, vec![id(3)], &solver, false),
+        HTLCError::NotPastTimeLock,
+    );
+    env.warp(START + 1801);
+    let sender_before = env.lamports(&env.sender.pubkey());
+    let meta = assert_ok(batch_refund(&mut env, vec![id(3)], &solver, false));
+    assert_eq!(settled(meta), vec![true]);
+    assert_eq!(env.lamports(&env.sender.pubkey()), sender_before + AMOUNT);
+    assert_eq!(htlc(&env, &id(3)).status, HtlcStatus::Refunded);
+}
+
+#[test]
+fn batch_is_all_or_nothing_unless_best_effort() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    lock(&mut env, id(2));
+    let solver = env.solver.insecure_clone();
+    let entries = vec![(id(1), secret(1)), (id(2), [0; 32]), (id(1), secret(1))];
+
+    assert_error(
+        batch_redeem(&mut env, entries.clone(), &solver, false),
+        HTLCError::HashlockNoMatch,
+    );
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Locked);
+
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+    let meta = assert_ok(batch_redeem(&mut env, entries, &solver, true));
+    // the wrong secret and the second redeem of id(1) are skipped
+    assert_eq!(settled(meta), vec![true, false, false]);
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + AMOUNT
+    );
+    assert_eq!(htlc(&env, &id(1)).status, HtlcStatus::Redeemed);
+    assert_eq!(htlc(&env, &id(2)).status, HtlcStatus::Locked);
+
+    // the htlc must be at the address derived from the sender passed with it
+    let mut ix = native_htlc::batch_redeem(
+        &solver.pubkey(),
+        &[(env.sender.pubkey(), env.receiver.pubkey())],
+        instruction::BatchRedeem {
+            entries: vec![(id(2), secret(2))],
+            best_effort: false,
+        },
+    );
+    let sender = ix.accounts.len() - 4;
+    ix.accounts[sender].pubkey = solver.pubkey();
+    assert_error(env.send(&[ix], &[&solver]), ErrorCode::ConstraintSeeds);
+
+    let ix = native_htlc::batch_redeem(
+        &solver.pubkey(),
+        &[],
+        instruction::BatchRedeem {
+            entries: vec![(id(2), secret(2))],
+            best_effort: true,
+        },
+    );
+    assert_error(
+        env.send(&[ix], &[&solver]),
+        HTLCError::BatchAccountsMismatch,
+    );
+}
+
+#[test]
+fn redeem_by_hashlock_settles_every_htlc_of_the_hashlock() {
+    let mut env = Env::new();
+    let shared = hashlock(&secret(1));
+    for seed in 1..=3 {
+        let args = lock_args(&env, id(seed), shared, env.now() + 1800);
+        let ix = native_htlc::lock(&env.sender.pubkey(), args);
+        let sender = env.sender.insecure_clone();
+        assert_ok(env.send(&[ix], &[&sender]));
+    }
+    lock(&mut env, id(4));
+    let solver = env.solver.insecure_clone();
+
+    assert_error(
+        redeem_by_hashlock(
+            &mut env,
+            shared,
+            Some(secret(2)),
+            None,
+            vec![id(2)],
+            &solver,
+        ),
+        HTLCError::HashlockNoMatch,
+    );
+    assert_error(
+        redeem_by_hashlock(&mut env, shared, None, None, vec![id(2)], &solver),
+        HTLCError::SecretNotProvided,
+    );
+    // a pending HTLC holds no secret yet
+    assert_error(
+        redeem_by_hashlock(&mut env, shared, None, Some(id(1)), vec![id(2)], &solver),
+        HTLCError::HashlockNoMatch,
+    );
+
+    assert_ok(redeem(&mut env, id(1), secret(1), &solver));
+    let receiver_before = env.lamports(&env.receiver.pubkey());
+    let meta = assert_ok(redeem_by_hashlock(
+        &mut env,
+        shared,
+        None,
+        Some(id(1)),
+        vec![id(2), id(3), id(4)],
+        &solver,
+    ));
+    // id(4) has another hashlock and is skipped
+    assert_eq!(settled(meta), vec![true, true, false]);
+    assert_eq!(
+        env.lamports(&env.receiver.pubkey()),
+        receiver_before + 2 * AMOUNT
+    );
+    for seed in 2..=3 {
+        let htlc = htlc(&env, &id(seed));
+        assert_eq!(htlc.status, HtlcStatus::Redeemed);
+        assert_eq!(htlc.secret, secret(1));
+    }
+    assert_eq!(htlc(&env, &id(4)).status, HtlcStatus::Locked);
+}
+
+#[test]
+fn first_redeem_records_the_secret_of_the_hashlock() {
+    let mut env = Env::new();
+    let shared = hashlock(&secret(1));
+    for seed in 1..=3 {
+        let args = lock_args(&env, id(seed), shared, env.now() + 1800);
+        let ix = native_htlc::lock(&env.sender.pubkey(), args);
+        let sender = env.sender.insecure_clone();
+        assert_ok(env.send(&[ix], &[&sender]));
+    }
+    let solver = env.solver.insecure_clone();
+    let record = secret_record_address(&shared);
+    assert!(!env.exists(&record));
+
+    // the record must be the one of the hashlock
+    let mut ix = native_htlc::redeem(
+        &solver.pubkey(),
+        &env.sender.pubkey(),
+        &env.receiver.pubkey(),
+        instruction::Redeem {
+            Id: id(1),
+            secret: secret(1),
+        },
+    );
+    ix.accounts[4].pubkey = secret_record_address(&hashlock(&secret(2)));
+    assert_error(env.send(&[ix], &[&solver]), ErrorCode::ConstraintSeeds);
+
+    assert_ok(redeem(&mut env, id(1), secret(1), &solver));
+    let recorded = native_htlc::decode_secret_record(&env.data(&record)).unwrap();
+    assert_eq!(recorded.hashlock, shared);
+    assert_eq!(recorded.secret, secret(1));
+    assert_eq!(recorded.revealed_at, START);
+
+    // later redeems keep the first record and pay no rent for it
+    env.warp(START + 100);
+    let receiver = env.receiver.insecure_clone();
+    let receiver_before = env.lamports(&receiver.pubkey());
+    assert_ok(redeem(&mut env, id(2), secret(1), &receiver));
+    assert_eq!(
+        env.lamports(&receiver.pubkey()),
+        receiver_before + AMOUNT - FEE
+    );
+    let ix = native_htlc::get_secret(instruction::GetSecret { hashlock: shared });
+    let meta = assert_ok(env.send(&[ix], &[&solver]));
+    let recorded = SecretRecord::deserialize(&mut &meta.return_data.data[..]).unwrap();
+    assert_eq!(recorded.secret, secret(1));
+    assert_eq!(recorded.revealed_at, START);
+
+    // the record is proof of the secret for redeem_by_hashlock
+    let meta = assert_ok(redeem_by_hashlock(
+        &mut env,
+        shared,
+        None,
+        None,
+        vec![id(3)],
+        &solver,
+    ));
+    assert_eq!(settled(meta), vec![true]);
+    assert_eq!(htlc(&env, &id(3)).secret, secret(1));
+}
+
+#[test]
+fn batches_record_the_secrets_before_paying_the_caller() {
+    let mut env = Env::new();
+    for seed in [1, 2, 5] {
+        lock(&mut env, id(seed));
+    }
+    let shared = hashlock(&secret(3));
+    for seed in 3..=4 {
+        let args = lock_args(&env, id(seed), shared, env.now() + 1800);
+        let ix = native_htlc::lock(&env.sender.pubkey(), args);
+        let sender = env.sender.insecure_clone();
+        assert_ok(env.send(&[ix], &[&sender]));
+    }
+    for seed in 1..=5 {
+        assert_ok(lock_reward(&mut env, id(seed), START + 1000));
+    }
+    env.warp(START + 1000);
+    let solver = env.solver.insecure_clone();
+
+    // an entry whose record cannot be written is skipped before its payout
+    let mut ix = native_htlc::batch_redeem(
+        &solver.pubkey(),
+        &[(env.sender.pubkey(), env.receiver.pubkey()); 2],
+        instruction::BatchRedeem {
+            entries: vec![(id(1), secret(1)), (id(2), secret(2))],
+            best_effort: true,
+        },
+    );
+    let record = ix.accounts.len() - 1;
+    ix.accounts[record].pubkey = secret_record_address(&shared);
+    let meta = assert_ok(env.send(&[set_compute_unit_limit(1_400_000), ix], &[&solver]));
+    assert_eq!(settled(meta), vec![true, false]);
+    assert_eq!(htlc(&env, &id(2)).status, HtlcStatus::Locked);
+
+    // the rewards paid to the caller do not break the records written after them
+    let solver_before = env.lamports(&solver.pubkey());
+    let meta = assert_ok(batch_redeem(
+        &mut env,
+        vec![(id(2), secret(2)), (id(5), secret(5))],
+        &solver,
+        false,
+    ));
+    assert_eq!(settled(meta), vec![true, true]);
+    let record_rent = env.lamports(&secret_record_address(&hashlock(&secret(2))));
+    assert_eq!(
+        env.lamports(&solver.pubkey()),
+        solver_before + 2 * REWARD - 2 * record_rent - FEE
+    );
+
+    let solver_before = env.lamports(&solver.pubkey());
+    let meta = assert_ok(redeem_by_hashlock(
+        &mut env,
+        shared,
+        Some(secret(3)),
+        None,
+        vec![id(3), id(4)],
+        &solver,
+    ));
+    assert_eq!(settled(meta), vec![true, true]);
+    assert_eq!(
+        env.lamports(&solver.pubkey()),
+        solver_before + 2 * REWARD - record_rent - FEE
+    );
+    for seed in [1, 2, 3, 5] {
+        let record = secret_record_address(&hashlock(&secret(seed)));
+        let recorded = native_htlc::decode_secret_record(&env.data(&record)).unwrap();
+        assert_eq!(recorded.secret, secret(seed));
+    }
+}
+
+#[test]
+fn reserve_id_hands_out_ids_from_the_nonce() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    for nonce in 0..2 {
+        let id = native_htlc::derive_htlc_id(&sender.pubkey(), nonce);
+        let ix = native_htlc::commit(&sender.pubkey(), commit_args(&env, id, START + 900));
+        assert_ok(env.send(&[native_htlc::reserve_id(&sender.pubkey()), ix], &[&sender]));
+        assert_eq!(htlc(&env, &id).status, HtlcStatus::Committed);
+    }
+    let state = native_htlc::find_sender_state_address(&sender.pubkey()).0;
+    assert_eq!(
+        native_htlc::decode_sender_state(&env.data(&state))
+            .unwrap()
+            .nonce,
+        2
+    );
+}
+
+#[test]
+fn close_only_after_settlement() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    let sender = env.sender.insecure_clone();
+    let close = native_htlc::close(&sender.pubkey(), instruction::Close { Id: id(1) });
+    assert_error(
+        env.send(std::slice::from_ref(&close), &[&sender]),
+        HTLCError::NotSettled,
+    );
+
+    let solver = env.solver.insecure_clone();
+    assert_ok(redeem(&mut env, id(1), secret, &solver));
+    let rent = env.lamports(&htlc_address(&env, &id(1)));
+    let before = env.lamports(&sender.pubkey());
+    assert_ok(env.send(&[close], &[&sender]));
+    assert!(!env.exists(&htlc_address(&env, &id(1))));
+    assert_eq!(env.lamports(&sender.pubkey()), before + rent - FEE);
+}
+
+#[test]
+fn get_details_returns_the_htlc() {
+    let mut env = Env::new();
+    lock(&mut env, id(1));
+    let solver = env.solver.insecure_clone();
+    let ix = native_htlc::get_details(&env.sender.pubkey(), instruction::GetDetails { Id: id(1) });
+    let meta = assert_ok(env.send(&[ix], &[&solver]));
+    let details = HTLC::deserialize(&mut &meta.return_data.data[..]).unwrap();
+    assert_eq!(details.hashlock, hashlock(&secret(1)));
+    assert_eq!(details.amount, AMOUNT);
+}
+
+/// Writes a legacy HTLC at the [Id] PDA, as created before HTLCs were
+/// namespaced by sender.
+fn legacy_htlc(env: &mut Env, id: [u8; 32], sender: Pubkey, amount: u64, claimed: u8) -> Pubkey {
+    let legacy = native_htlc::LegacyHTLC {
+        dst_address: DST_ADDRESS.to_string(),
+        dst_chain: DST_CHAIN.to_string(),
+        dst_asset: DST_ASSET.to_string(),
+        src_asset: SRC_ASSET.to_string(),
+        sender,
+        src_receiver: env.receiver.pubkey(),
+        hashlock: hashlock(&secret(id[0])),
+        secret: [0; 32],
+        amount,
+        reward: REWARD,
+        timelock: START + 1800,
+        reward_timelock: START + 1000,
+        claimed,
+    };
+    let mut data = HTLC::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+    let rent = env.svm.minimum_balance_for_rent_exemption(data.len());
+    let address = native_htlc::find_legacy_htlc_address(&id).0;
+    env.svm
+        .set_account(
+            address,
+            Account {
+                lamports: rent + AMOUNT + REWARD,
+                data,
+                owner: native_htlc::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+    address
+}
+
+#[test]
+fn migrate_moves_a_legacy_htlc_to_the_sender_namespace() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let legacy = legacy_htlc(&mut env, id(1), sender.pubkey(), AMOUNT, 1);
+    let legacy_lamports = env.lamports(&legacy);
+    let migrate = native_htlc::migrate(&sender.pubkey(), instruction::Migrate { Id: id(1) });
+    assert_ok(env.send(&[migrate], &[&sender]));
+
+    assert!(!env.exists(&legacy));
+    let htlc = htlc(&env, &id(1));
+    assert_eq!(htlc.status, HtlcStatus::Locked);
+    assert_eq!(htlc.amount, AMOUNT);
+    assert_eq!(htlc.reward, REWARD);
+    let address = htlc_address(&env, &id(1));
+    assert!(env.lamports(&address) >= AMOUNT + REWARD);
+    assert!(legacy_lamports > AMOUNT + REWARD);
+
+    let secret = secret(1);
+    let solver = env.solver.insecure_clone();
+    assert_ok(redeem(&mut env, id(1), secret, &solver));
+}
+
+#[test]
+fn migrate_rejects_claimed_and_foreign_htlcs() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    legacy_htlc(&mut env, id(1), sender.pubkey(), AMOUNT, 2);
+    let migrate = native_htlc::migrate(&sender.pubkey(), instruction::Migrate { Id: id(1) });
+    assert_error(env.send(&[migrate], &[&sender]), HTLCError::AlreadyClaimed);
+
+    let solver = env.solver.insecure_clone();
+    legacy_htlc(&mut env, id(2), sender.pubkey(), AMOUNT, 1);
+    let migrate = native_htlc::migrate(&solver.pubkey(), instruction::Migrate { Id: id(2) });
+    assert_error(env.send(&[migrate], &[&solver]), HTLCError::NotSender);
+}
+
+/// Overwrites the HTLC of `id` with `htlc`.
+fn write_htlc(env: &mut Env, id: &[u8; 32], htlc: &HTLC) {
+    let mut data = HTLC::DISCRIMINATOR.to_vec();
+    htlc.serialize(&mut data).unwrap();
+    env.write_data(&htlc_address(env, id), &data);
+}
+
+#[test]
+fn a_timelock_past_i64_max_never_expires() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let ix = native_htlc::commit(&sender.pubkey(), commit_args(&env, id(1), u64::MAX));
+    assert_ok(env.send(&[ix], &[&sender]));
+
+    env.set_unix_timestamp(i64::MAX);
+    assert_error(refund(&mut env, id(1), &sender), HTLCError::NotPastTimeLock);
+}
+
+/// A Clock sysvar that cannot be read fails the same way, but cannot be
+/// produced in the SVM.
+#[test]
+fn a_negative_clock_is_unavailable() {
+    let mut env = Env::new();
+    commit(&mut env, id(1));
+    let sender = env.sender.insecure_clone();
+    env.set_unix_timestamp(-1);
+
+    let ix = native_htlc::commit(&sender.pubkey(), commit_args(&env, id(2), START));
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::ClockUnavailable);
+    let ix = native_htlc::lock(
+        &sender.pubkey(),
+        lock_args(&env, id(2), hashlock(&secret(2)), START),
+    );
+    assert_error(env.send(&[ix], &[&sender]), HTLCError::ClockUnavailable);
+    assert_error(
+        add_lock(&mut env, id(1), START),
+        HTLCError::ClockUnavailable,
+    );
+    assert_error(
+        lock_reward(&mut env, id(1), START),
+        HTLCError::ClockUnavailable,
+    );
+    assert_error(
+        refund(&mut env, id(1), &sender),
+        HTLCError::ClockUnavailable,
+    );
+}
+
+#[test]
+fn settling_funds_that_overflow_fails_cleanly() {
+    let mut env = Env::new();
+    let secret = lock(&mut env, id(1));
+    assert_ok(lock_reward(&mut env, id(1), START + 1000));
+    let mut state = htlc(&env, &id(1));
+    state.amount = u64::MAX;
+    write_htlc(&mut env, &id(1), &state);
+    let solver = env.solver.insecure_clone();
+
+    assert_error(
+        redeem(&mut env, id(1), secret, &solver),
+        HTLCError::ArithmeticOverflow,
+    );
+    env.warp(START + 1801);
+    assert_error(
+        refund(&mut env, id(1), &solver),
+        HTLCError::ArithmeticOverflow,
+    );
+}
+
+#[test]
+fn migrating_funds_that_overflow_fails_cleanly() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    legacy_htlc(&mut env, id(1), sender.pubkey(), u64::MAX, 1);
+    let migrate = native_htlc::migrate(&sender.pubkey(), instruction::Migrate { Id: id(1) });
+    assert_error(
+        env.send(&[migrate], &[&sender]),
+        HTLCError::ArithmeticOverflow,
+    );
+}
+
+#[test]
+fn reserve_id_fails_when_the_nonce_overflows() {
+    let mut env = Env::new();
+    let sender = env.sender.insecure_clone();
+    let reserve_id = native_htlc::reserve_id(&sender.pubkey());
+    assert_ok(env.send(std::slice::from_ref(&reserve_id), &[&sender]));
+
+    let mut data = SenderState::DISCRIMINATOR.to_vec();
+    SenderState { nonce: u64::MAX }
+        .serialize(&mut data)
+        .unwrap();
+    let state = native_htlc::find_sender_state_address(&sender.pubkey()).0;
+    env.write_data(&state, &data);
+    assert_error(
+        env.send(&[reserve_id], &[&sender]),
+        HTLCError::ArithmeticOverflow,
+    );
+}
@@ -0,0 +1,116 @@
+use anchor_lang::prelude::*;
+use spl_discriminator::SplDiscriminate;
+use spl_tlv_account_resolution::{
+    account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList,
+};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+declare_id!("5yKPSFLabWSKCg5cjiEJoCwGNGJ6r2MTa86Ca3fMPJ49");
+
+/// Seeds of the extra-account-meta list PDA of a mint, as set by the transfer hook interface.
+pub const EXTRA_ACCOUNT_METAS_SEED: &[u8] = b"extra-account-metas";
+/// Seeds of the PDA recording the hook calls.
+pub const CALLS_SEED: &[u8] = b"calls";
+
+/// @title Transfer hook used by the anchor_htlc tests.
+///
+/// Token-2022 calls execute on every transfer of a mint using this hook, and the
+/// call is recorded in the Calls PDA, which is the only extra account of the hook.
+#[program]
+pub mod mock_hook {
+    use super::*;
+
+    /// @dev Writes the extra-account-meta list of the mint and creates the Calls PDA.
+    pub fn initialize_extra_account_meta_list(
+        ctx: Context<InitializeExtraAccountMetaList>,
+    ) -> Result<()> {
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?,
+            &extra_account_metas()?,
+        )?;
+        Ok(())
+    }
+
+    /// @dev Called by Token-2022 on transfer, records the transfer.
+    #[instruction(discriminator = ExecuteInstruction::SPL_DISCRIMINATOR_SLICE)]
+    pub fn execute(ctx: Context<Execute>, amount: u64) -> Result<()> {
+        let calls = &mut ctx.accounts.calls;
+        calls.count += 1;
+        calls.last_source = ctx.accounts.source.key();
+        calls.last_destination = ctx.accounts.destination.key();
+        calls.last_amount = amount;
+        Ok(())
+    }
+}
+
+fn extra_account_metas() -> Result<Vec<ExtraAccountMeta>> {
+    Ok(vec![ExtraAccountMeta::new_with_seeds(
+        &[Seed::Literal {
+            bytes: CALLS_SEED.to_vec(),
+        }],
+        false,
+        true,
+    )?])
+}
+
+#[account]
+#[derive(Default, InitSpace)]
+pub struct Calls {
+    pub count: u64,
+    pub last_source: Pubkey,
+    pub last_destination: Pubkey,
+    pub last_amount: u64,
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    payer: Signer<'info>,
+    ///CHECK: The extra-account-meta list, written by ExtraAccountMetaList::init
+    #[account(
+        init,
+        payer = payer,
+        space = ExtraAccountMetaList::size_of(1)?,
+        seeds = [
+            EXTRA_ACCOUNT_METAS_SEED,
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    ///CHECK: The mint
+    pub mint: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Calls::INIT_SPACE,
+        seeds = [CALLS_SEED],
+        bump,
+    )]
+    pub calls: Account<'info, Calls>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Execute<'info> {
+    ///CHECK: The source token account
+    pub source: UncheckedAccount<'info>,
+    ///CHECK: The mint
+    pub mint: UncheckedAccount<'info>,
+    ///CHECK: The destination token account
+    pub destination: UncheckedAccount<'info>,
+    ///CHECK: The owner or delegate of the source
+    pub owner: UncheckedAccount<'info>,
+    ///CHECK: The extra-account-meta list of the mint
+    #[account(
+        seeds = [
+            EXTRA_ACCOUNT_METAS_SEED,
+            mint.key().as_ref()
+        ],
+        bump,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(mut, seeds = [CALLS_SEED], bump)]
+    pub calls: Account<'info, Calls>,
+}
@@ -9,18 +9,124 @@
 //       @@@@@  @@@           @@@@@@@@@ @@@  @@@   @@@          @@@
 
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::ed25519_program::ID as ED25519_ID;
-use anchor_lang::solana_program::instruction::Instruction;
-use anchor_lang::solana_program::sysvar::instructions::{load_instruction_at_checked, ID as IX_ID};
+use anchor_lang::solana_program::sysvar::instructions::ID as IX_ID;
+use anchor_lang::system_program;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use anchor_spl::token_2022::spl_token_2022::{self, state::Mint as MintState};
+use anchor_spl::token_2022_extensions::transfer_fee::{
+    harvest_withheld_tokens_to_mint, HarvestWithheldTokensToMint,
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{CloseAccount, Mint, Token, TokenAccount, Transfer},
+    associated_token::{get_associated_token_address_with_program_id, AssociatedToken},
+    token_interface::{
+        self, Approve, CloseAccount, InitializeAccount3, Mint, Revoke, TokenAccount,
+        TokenInterface, TransferChecked,
+    },
 };
 use sha2::{Digest, Sha256};
-use std::convert::TryInto;
-use std::mem::size_of;
+use train_lock_sig::eip712::{add_lock_digest, recover_evm_address, MAX_TIMELOCK};
+use train_lock_sig::introspection::{find_ed25519_verification, verifies};
+use train_lock_sig::{add_lock_message, legacy, Domain};
 
 declare_id!("AN8Y7CGKNQcBCLxeNmnob786v8EkWhrGBBriPD2JzjK8");
+
+#[cfg(any(
+    all(feature = "mainnet-beta", feature = "devnet"),
+    all(feature = "mainnet-beta", feature = "testnet"),
+    all(feature = "devnet", feature = "testnet"),
+))]
+compile_error!("Enable at most one of the mainnet-beta, devnet and testnet features.");
+
+#[cfg(all(
+    target_os = "solana",
+    not(any(feature = "mainnet-beta", feature = "devnet", feature = "testnet"))
+))]
+compile_error!(
+    "Build for a cluster with the mainnet-beta, devnet or testnet feature, e.g. `anchor build -- --features devnet`."
+);
+
+/// Genesis hash of the cluster the program is built for, selected by the
+/// `mainnet-beta`, `devnet` or `testnet` feature. Zero in native builds, such
+/// as the tests, which need no cluster.
+#[cfg(feature = "mainnet-beta")]
+pub const GENESIS_HASH: [u8; 32] = train_lock_sig::MAINNET_BETA_GENESIS_HASH;
+#[cfg(feature = "devnet")]
+pub const GENESIS_HASH: [u8; 32] = train_lock_sig::DEVNET_GENESIS_HASH;
+#[cfg(feature = "testnet")]
+pub const GENESIS_HASH: [u8; 32] = train_lock_sig::TESTNET_GENESIS_HASH;
+#[cfg(not(any(feature = "mainnet-beta", feature = "devnet", feature = "testnet")))]
+pub const GENESIS_HASH: [u8; 32] = [0; 32];
+
+/// Domain the add_lock_sig message is bound to: this program on this cluster.
+pub const ADD_LOCK_SIG_DOMAIN: Domain = Domain {
+    program_id: ID,
+    genesis_hash: GENESIS_HASH,
+};
+
+/// Max length in bytes of a chain name, e.g. `STARKNET_SEPOLIA`.
+pub const MAX_CHAIN_LEN: usize = 32;
+/// Max length in bytes of an asset, long enough for a 32-byte hex token address.
+pub const MAX_ASSET_LEN: usize = 66;
+/// Max length in bytes of a destination address, the longest valid bech32 string.
+pub const MAX_ADDRESS_LEN: usize = 90;
+/// Max number of hops in a committed route.
+pub const MAX_HOPS: usize = 4;
+
+/// Prefix of the HTLC PDA seeds: [HTLC_SEED, sender, Id].
+pub const HTLC_SEED: &[u8] = b"htlc";
+/// Prefix of the HTLC token account PDA seeds: [HTLC_TOKEN_ACCOUNT_SEED, sender, Id].
+pub const HTLC_TOKEN_ACCOUNT_SEED: &[u8] = b"htlc_token_account";
+
+/// Seeds of the per-sender nonce PDA: [SENDER_STATE_SEED, sender].
+pub const SENDER_STATE_SEED: &[u8] = b"sender_state";
+
+/// Prefix of the Session PDA seeds: [SESSION_SEED, sender, delegate].
+pub const SESSION_SEED: &[u8] = b"session";
+
+/// Prefix of the SecretRecord PDA seeds: [SECRET_RECORD_SEED, hashlock].
+pub const SECRET_RECORD_SEED: &[u8] = b"secret_record";
+
+/// Session permission to call add_lock.
+pub const SESSION_ADD_LOCK: u8 = 1 << 0;
+/// Session permission to call lock_reward, paying the reward from the session budget.
+pub const SESSION_LOCK_REWARD: u8 = 1 << 1;
+
+/// Derives the HTLC PDA of `sender` for the given Id.
+pub fn find_htlc_address(sender: &Pubkey, id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[HTLC_SEED, sender.as_ref(), id], &ID)
+}
+
+/// Derives the token account PDA holding the funds of the HTLC of `sender` for the given Id.
+pub fn find_htlc_token_account_address(sender: &Pubkey, id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[HTLC_TOKEN_ACCOUNT_SEED, sender.as_ref(), id], &ID)
+}
+
+/// Derives the SenderState PDA holding the Id nonce of `sender`.
+pub fn find_sender_state_address(sender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SENDER_STATE_SEED, sender.as_ref()], &ID)
+}
+
+/// Derives the Session PDA of `sender` naming `delegate`.
+pub fn find_session_address(sender: &Pubkey, delegate: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SESSION_SEED, sender.as_ref(), delegate.as_ref()], &ID)
+}
+
+/// Derives the SecretRecord PDA holding the secret of `hashlock` once revealed.
+pub fn find_secret_record_address(hashlock: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SECRET_RECORD_SEED, hashlock], &ID)
+}
+
+/// Derives the Id that reserve_id hands out to `sender` for `nonce`:
+/// sha256(sender || nonce as little-endian u64).
+pub fn derive_htlc_id(sender: &Pubkey, nonce: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(sender.as_ref());
+    hasher.update(nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
 /// @title Pre Hashed Timelock Contracts (PHTLCs) on Solana SPL tokens.
 ///
 /// This contract provides a way to lock and keep PHTLCs for SPL tokens.
@@ -41,6 +147,95 @@ declare_id!("AN8Y7CGKNQcBCLxeNmnob786v8EkWhrGBBriPD2JzjK8");
 ///      redeem the tokens the sender / creator of the HTLC can get their tokens
 ///      back with this function.
 
+/// @dev Reads the TransferFee extension of a Token-2022 mint, if the mint has one.
+fn transfer_fee_config(
+    token_contract: &InterfaceAccount<Mint>,
+) -> Result<Option<TransferFeeConfig>> {
+    let info = token_contract.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    Ok(mint.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+/// @dev The fee the mint withholds when `amount` is transferred in the current epoch.
+fn transfer_fee(token_contract: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    match transfer_fee_config(token_contract)? {
+        Some(config) => config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(error!(HTLCError::InvalidTransferFee)),
+        None => Ok(0),
+    }
+}
+
+/// @dev The amount to transfer in the current epoch so that `net_amount` arrives after the fee.
+fn pre_fee_amount(token_contract: &InterfaceAccount<Mint>, net_amount: u64) -> Result<u64> {
+    match transfer_fee_config(token_contract)? {
+        Some(config) => config
+            .get_epoch_fee(Clock::get()?.epoch)
+            .calculate_pre_fee_amount(net_amount)
+            .ok_or(error!(HTLCError::InvalidTransferFee)),
+        None => Ok(net_amount),
+    }
+}
+
+/// @dev The amount the sender transfers in so that, with `fee_payer` paying the
+/// outbound fee, `amount` reaches the destination on payout.
+fn deposit_amount(
+    token_contract: &InterfaceAccount<Mint>,
+    amount: u64,
+    fee_payer: FeePayer,
+) -> Result<u64> {
+    match fee_payer {
+        FeePayer::Receiver => Ok(amount),
+        FeePayer::Sender => pre_fee_amount(token_contract, pre_fee_amount(token_contract, amount)?),
+    }
+}
+
+/// @dev Moves the transfer fees withheld in the htlc token account to the mint,
+/// Token-2022 does not let an account with withheld fees be closed.
+fn harvest_withheld_fees<'info>(
+    htlc_token_account: &InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
+    token_program: AccountInfo<'info>,
+) -> Result<()> {
+    if transfer_fee_config(token_contract)?.is_none() {
+        return Ok(());
+    }
+    let cpi_ctx = CpiContext::new(
+        token_program.clone(),
+        HarvestWithheldTokensToMint {
+            token_program_id: token_program,
+            mint: token_contract.to_account_info(),
+        },
+    );
+    harvest_withheld_tokens_to_mint(cpi_ctx, vec![htlc_token_account.to_account_info()])
+}
+
+/// @dev transfer_checked that also hands the remaining accounts of the instruction to
+/// the token program. Mints with a transfer hook find the accounts of their
+/// extra-account-meta list there.
+fn transfer_checked<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    spl_token_2022::onchain::invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
 /// @dev A small utility function that allows us to transfer funds out of the htlc.
 ///
 /// * `sender` - htlc creator's account
@@ -48,26 +243,38 @@ declare_id!("AN8Y7CGKNQcBCLxeNmnob786v8EkWhrGBBriPD2JzjK8");
 /// * `htlc` - the htlc public key (PDA)
 /// * `htlc_bump` - the htlc public key (PDA) bump
 /// * `htlc_token_account` - The htlc Token account
+/// * `token_contract` - the mint of the htlc Token account
 /// * `token_program` - the token program address
 /// * `destination_wallet` - The public key of the destination address (where to send funds)
+/// * `remaining_accounts` - the transfer hook accounts of the mint, if it has a hook
 /// * `amount` - the amount of token that is sent from `htlc_token_account` to `destination_wallet`
+///
+/// Returns the transfer fee withheld by the mint.
 fn transfer_htlc_out<'info>(
     sender: AccountInfo<'info>,
     Id: [u8; 32],
     htlc: AccountInfo<'info>,
     htlc_bump: u8,
-    htlc_token_account: &mut Account<'info, TokenAccount>,
+    htlc_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
     token_program: AccountInfo<'info>,
     destination_wallet: AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
     amount: u64,
-) -> Result<()> {
+) -> Result<u64> {
     let bump_vector = htlc_bump.to_le_bytes();
-    let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+    let inner = vec![
+        HTLC_SEED,
+        sender.key.as_ref(),
+        Id.as_ref(),
+        bump_vector.as_ref(),
+    ];
     let outer = vec![inner.as_slice()];
 
     // Perform the actual transfer
-    let transfer_instruction = Transfer {
+    let transfer_instruction = TransferChecked {
         from: htlc_token_account.to_account_info(),
+        mint: token_contract.to_account_info(),
         to: destination_wallet,
         authority: htlc.to_account_info(),
     };
@@ -75,8 +282,10 @@ fn transfer_htlc_out<'info>(
         token_program.to_account_info(),
         transfer_instruction,
         outer.as_slice(),
-    );
-    anchor_spl::token::transfer(cpi_ctx, amount)?;
+    )
+    .with_remaining_accounts(remaining_accounts.to_vec());
+    let fee = transfer_fee(token_contract, amount)?;
+    transfer_checked(cpi_ctx, amount, token_contract.decimals)?;
 
     // Use the `reload()` function on an account to reload it's state. Since we performed the
     // transfer, we are expecting the `amount` field to have changed.
@@ -87,6 +296,7 @@ fn transfer_htlc_out<'info>(
 
     // If token account has no more tokens, it should be wiped out since it has no other use case.
     if should_close {
+        harvest_withheld_fees(htlc_token_account, token_contract, token_program.clone())?;
         let ca = CloseAccount {
             account: htlc_token_account.to_account_info(),
             destination: sender.to_account_info(),
@@ -94,10 +304,10 @@ fn transfer_htlc_out<'info>(
         };
         let cpi_ctx =
             CpiContext::new_with_signer(token_program.to_account_info(), ca, outer.as_slice());
-        anchor_spl::token::close_account(cpi_ctx)?;
+        token_interface::close_account(cpi_ctx)?;
     }
 
-    Ok(())
+    Ok(fee)
 }
 
 fn transfer_htlc_reward_out<'info>(
@@ -105,20 +315,28 @@ fn transfer_htlc_reward_out<'info>(
     Id: [u8; 32],
     htlc: AccountInfo<'info>,
     htlc_bump: u8,
-    htlc_token_account: &mut Account<'info, TokenAccount>,
+    htlc_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
     token_program: AccountInfo<'info>,
     destination_wallet: AccountInfo<'info>,
     reward_wallet: AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
     amount: u64,
     reward: u64,
-) -> Result<()> {
+) -> Result<u64> {
     let bump_vector = htlc_bump.to_le_bytes();
-    let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+    let inner = vec![
+        HTLC_SEED,
+        sender.key.as_ref(),
+        Id.as_ref(),
+        bump_vector.as_ref(),
+    ];
     let outer = vec![inner.as_slice()];
 
     // Perform the actual transfer
-    let transfer_instruction = Transfer {
+    let transfer_instruction = TransferChecked {
         from: htlc_token_account.to_account_info(),
+        mint: token_contract.to_account_info(),
         to: destination_wallet,
         authority: htlc.to_account_info(),
     };
@@ -126,12 +344,15 @@ fn transfer_htlc_reward_out<'info>(
         token_program.to_account_info(),
         transfer_instruction,
         outer.as_slice(),
-    );
-    anchor_spl::token::transfer(cpi_ctx, amount)?;
+    )
+    .with_remaining_accounts(remaining_accounts.to_vec());
+    let fee = transfer_fee(token_contract, amount)?;
+    transfer_checked(cpi_ctx, amount, token_contract.decimals)?;
 
     // Perform the reward transfer
-    let reward_instruction = Transfer {
+    let reward_instruction = TransferChecked {
         from: htlc_token_account.to_account_info(),
+        mint: token_contract.to_account_info(),
         to: reward_wallet,
         authority: htlc.to_account_info(),
     };
@@ -139,8 +360,10 @@ fn transfer_htlc_reward_out<'info>(
         token_program.to_account_info(),
         reward_instruction,
         outer.as_slice(),
-    );
-    anchor_spl::token::transfer(reward_cpi_ctx, reward)?;
+    )
+    .with_remaining_accounts(remaining_accounts.to_vec());
+    let reward_fee = transfer_fee(token_contract, reward)?;
+    transfer_checked(reward_cpi_ctx, reward, token_contract.decimals)?;
 
     // Use the `reload()` function on an account to reload it's state. Since we performed the
     // transfer, we are expecting the `amount` field to have changed.
@@ -151,6 +374,7 @@ fn transfer_htlc_reward_out<'info>(
 
     // If token account has no more tokens, it should be wiped out since it has no other use case.
     if should_close {
+        harvest_withheld_fees(htlc_token_account, token_contract, token_program.clone())?;
         let ca = CloseAccount {
             account: htlc_token_account.to_account_info(),
             destination: sender.to_account_info(),
@@ -158,65 +382,536 @@ fn transfer_htlc_reward_out<'info>(
         };
         let cpi_ctx =
             CpiContext::new_with_signer(token_program.to_account_info(), ca, outer.as_slice());
-        anchor_spl::token::close_account(cpi_ctx)?;
+        token_interface::close_account(cpi_ctx)?;
     }
 
-    Ok(())
+    fee.checked_add(reward_fee)
+        .ok_or(error!(HTLCError::InvalidTransferFee))
 }
 
 /// Verify serialized Ed25519Program instruction data
+/// Checks that Ed25519Program instruction data verifies `sig` of `msg` by
+/// `pubkey`. Only signatures whose public key, signature and message are in
+/// `data` itself are considered; malformed data fails instead of panicking.
 pub fn check_ed25519_data(data: &[u8], pubkey: &[u8], msg: &[u8], sig: &[u8]) -> Result<()> {
-    // According to this layout used by the Ed25519Program
-    // https://github.com/solana-labs/solana-web3.js/blob/master/src/ed25519-program.ts#L33
-
-    // "Deserializing" byte slices
-
-    let num_signatures = &[data[0]]; // Byte  0
-    let padding = &[data[1]]; // Byte  1
-    let signature_offset = &data[2..=3]; // Bytes 2,3
-    let signature_instruction_index = &data[4..=5]; // Bytes 4,5
-    let public_key_offset = &data[6..=7]; // Bytes 6,7
-    let public_key_instruction_index = &data[8..=9]; // Bytes 8,9
-    let message_data_offset = &data[10..=11]; // Bytes 10,11
-    let message_data_size = &data[12..=13]; // Bytes 12,13
-    let message_instruction_index = &data[14..=15]; // Bytes 14,15
-
-    let data_pubkey = &data[16..16 + 32]; // Bytes 16..16+32
-    let data_sig = &data[48..48 + 64]; // Bytes 48..48+64
-    let data_msg = &data[112..]; // Bytes 112..end
-
-    // Expected values
-
-    let exp_public_key_offset: u16 = 16; // 2*u8 + 7*u16
-    let exp_signature_offset: u16 = exp_public_key_offset + pubkey.len() as u16;
-    let exp_message_data_offset: u16 = exp_signature_offset + sig.len() as u16;
-    let exp_num_signatures: u8 = 1;
-    let exp_message_data_size: u16 = msg.len().try_into().unwrap();
-
-    // Header and Arg Checks
-
-    // Header
-    if num_signatures != &exp_num_signatures.to_le_bytes()
-        || padding != &[0]
-        || signature_offset != &exp_signature_offset.to_le_bytes()
-        || signature_instruction_index != &u16::MAX.to_le_bytes()
-        || public_key_offset != &exp_public_key_offset.to_le_bytes()
-        || public_key_instruction_index != &u16::MAX.to_le_bytes()
-        || message_data_offset != &exp_message_data_offset.to_le_bytes()
-        || message_data_size != &exp_message_data_size.to_le_bytes()
-        || message_instruction_index != &u16::MAX.to_le_bytes()
-    {
+    let (Ok(pubkey), Ok(sig)) = (<&[u8; 32]>::try_from(pubkey), <&[u8; 64]>::try_from(sig)) else {
         return Err(HTLCError::SigVerificationFailed.into());
+    };
+    require!(
+        verifies(data, |_| None, pubkey, msg, sig),
+        HTLCError::SigVerificationFailed
+    );
+    Ok(())
+}
+
+/// Closes a legacy HTLC account, sending all of its lamports to `destination`.
+fn close_legacy<'info>(
+    legacy_htlc: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+) -> Result<()> {
+    let lamports = legacy_htlc.lamports();
+    legacy_htlc.sub_lamports(lamports)?;
+    destination.add_lamports(lamports)?;
+    legacy_htlc.assign(&System::id());
+    legacy_htlc.resize(0)?;
+    Ok(())
+}
+
+/// The unix timestamp of the Clock sysvar, as a u64 to compare with the timelocks.
+pub fn current_time() -> Result<u64> {
+    let clock = Clock::get().map_err(|_| error!(HTLCError::ClockUnavailable))?;
+    u64::try_from(clock.unix_timestamp).map_err(|_| error!(HTLCError::ClockUnavailable))
+}
+
+/// Rejects destination and source strings that do not fit in the HTLC account.
+fn check_string_lengths(
+    dst_chain: &str,
+    dst_asset: &str,
+    dst_address: &str,
+    src_asset: &str,
+) -> Result<()> {
+    require!(
+        dst_chain.len() <= MAX_CHAIN_LEN
+            && dst_asset.len() <= MAX_ASSET_LEN
+            && dst_address.len() <= MAX_ADDRESS_LEN
+            && src_asset.len() <= MAX_ASSET_LEN,
+        HTLCError::StringTooLong
+    );
+    Ok(())
+}
+
+/// Rejects hop routes with mismatched lengths, too many hops or oversized strings.
+fn check_hops(
+    hop_chains: &[String],
+    hop_assets: &[String],
+    hop_addresses: &[String],
+) -> Result<()> {
+    require!(
+        hop_chains.len() == hop_assets.len() && hop_chains.len() == hop_addresses.len(),
+        HTLCError::HopLengthMismatch
+    );
+    require!(hop_chains.len() <= MAX_HOPS, HTLCError::TooManyHops);
+    for ((chain, asset), address) in hop_chains.iter().zip(hop_assets).zip(hop_addresses) {
+        require!(
+            chain.len() <= MAX_CHAIN_LEN
+                && asset.len() <= MAX_ASSET_LEN
+                && address.len() <= MAX_ADDRESS_LEN,
+            HTLCError::StringTooLong
+        );
     }
+    Ok(())
+}
 
-    // Arguments
-    if data_pubkey != pubkey || data_msg != msg || data_sig != sig {
-        return Err(HTLCError::SigVerificationFailed.into());
+/// Rejects lock terms that the lock instructions do not accept.
+fn check_lock_terms(
+    timelock: u64,
+    amount: u64,
+    dst_chain: &str,
+    dst_asset: &str,
+    dst_address: &str,
+    src_asset: &str,
+) -> Result<()> {
+    let min_timelock = current_time()?
+        .checked_add(1800)
+        .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+    require!(timelock >= min_timelock, HTLCError::InvalidTimeLock);
+    require!(amount != 0, HTLCError::FundsNotSent);
+    check_string_lengths(dst_chain, dst_asset, dst_address, src_asset)
+}
+
+/// Returns sha256(`secret`), the hashlock it opens.
+fn hash_secret(secret: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(secret);
+    hasher.finalize().into()
+}
+
+/// Checks `secret` against the hashlock of the HTLC and pays it out. The
+/// src_receiver gets the amount; the reward goes back to the sender before the
+/// reward_timelock and to the redeemer after it. Nothing moves unless every
+/// check passes.
+///
+/// `hash` is hash_secret(`secret`), taken from the caller so that HTLCs
+/// sharing a hashlock are redeemed with a single hash.
+///
+/// Returns the transfer fee withheld by the mint.
+fn redeem_htlc<'info>(
+    htlc: &mut Account<'info, HTLC>,
+    Id: [u8; 32],
+    secret: [u8; 32],
+    hash: [u8; 32],
+    htlc_bump: u8,
+    user_signing: &AccountInfo<'info>,
+    sender: &AccountInfo<'info>,
+    htlc_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
+    token_program: &AccountInfo<'info>,
+    src_receiver_token_account: &AccountInfo<'info>,
+    sender_token_account: &AccountInfo<'info>,
+    reward_token_account: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<u64> {
+    htlc.status = htlc.status.transition(HtlcStatus::Redeemed)?;
+    require!(hash == htlc.hashlock, HTLCError::HashlockNoMatch);
+
+    htlc.secret = secret;
+    let amount = htlc.amount;
+    let reward = htlc.reward;
+    if reward != 0 {
+        // if redeem is called before the reward_timelock sender should get the reward back
+        if htlc.reward_timelock > current_time()? {
+            transfer_htlc_reward_out(
+                sender.clone(),
+                Id,
+                htlc.to_account_info(),
+                htlc_bump,
+                htlc_token_account,
+                token_contract,
+                token_program.clone(),
+                src_receiver_token_account.clone(),
+                sender_token_account.clone(),
+                remaining_accounts,
+                amount,
+                reward,
+            )
+        } else {
+            // if the caller is the receiver then they should get and the amount,
+            // and the reward
+            if user_signing.key() == htlc.src_receiver {
+                let funds = amount
+                    .checked_add(reward)
+                    .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+                transfer_htlc_out(
+                    sender.clone(),
+                    Id,
+                    htlc.to_account_info(),
+                    htlc_bump,
+                    htlc_token_account,
+                    token_contract,
+                    token_program.clone(),
+                    src_receiver_token_account.clone(),
+                    remaining_accounts,
+                    funds,
+                )
+            } else {
+                transfer_htlc_reward_out(
+                    sender.clone(),
+                    Id,
+                    htlc.to_account_info(),
+                    htlc_bump,
+                    htlc_token_account,
+                    token_contract,
+                    token_program.clone(),
+                    src_receiver_token_account.clone(),
+                    reward_token_account.clone(),
+                    remaining_accounts,
+                    amount,
+                    reward,
+                )
+            }
+        }
+    } else {
+        // send the tokens to the receiver if the reward is set to zero
+        transfer_htlc_out(
+            sender.clone(),
+            Id,
+            htlc.to_account_info(),
+            htlc_bump,
+            htlc_token_account,
+            token_contract,
+            token_program.clone(),
+            src_receiver_token_account.clone(),
+            remaining_accounts,
+            amount,
+        )
     }
+}
+
+/// Returns the amount and the reward of the HTLC to the sender.
+///
+/// Returns the transfer fee withheld by the mint.
+fn refund_htlc<'info>(
+    htlc: &mut Account<'info, HTLC>,
+    Id: [u8; 32],
+    htlc_bump: u8,
+    sender: &AccountInfo<'info>,
+    htlc_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_contract: &InterfaceAccount<'info, Mint>,
+    token_program: &AccountInfo<'info>,
+    sender_token_account: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<u64> {
+    htlc.status = htlc.status.transition(HtlcStatus::Refunded)?;
+    let funds = htlc
+        .amount
+        .checked_add(htlc.reward)
+        .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+
+    transfer_htlc_out(
+        sender.clone(),
+        Id,
+        htlc.to_account_info(),
+        htlc_bump,
+        htlc_token_account,
+        token_contract,
+        token_program.clone(),
+        sender_token_account.clone(),
+        remaining_accounts,
+        funds,
+    )
+}
+
+/// Splits the remaining accounts of a batch into one chunk of `len` accounts per
+/// entry, followed by the transfer hook accounts of the mint.
+fn batch_accounts<'a, 'info>(
+    remaining_accounts: &'a [AccountInfo<'info>],
+    entries: usize,
+    len: usize,
+) -> Result<(
+    std::slice::Chunks<'a, AccountInfo<'info>>,
+    &'a [AccountInfo<'info>],
+)> {
+    let expected = entries
+        .checked_mul(len)
+        .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+    require!(
+        remaining_accounts.len() >= expected,
+        HTLCError::BatchAccountsMismatch
+    );
+    let (accounts, hook_accounts) = remaining_accounts.split_at(expected);
+    Ok((accounts.chunks(len), hook_accounts))
+}
 
+/// Requires an account of a batch to be writable, like `mut` does for the single instructions.
+fn require_writable(info: &AccountInfo) -> Result<()> {
+    require!(info.is_writable, ErrorCode::ConstraintMut);
     Ok(())
 }
 
+/// Loads an HTLC passed to a batch, with the checks the single instructions make
+/// on their htlc account: writable, at the [HTLC_SEED, sender, Id] address and
+/// created by `sender`. Returns the HTLC with its bump.
+fn load_htlc<'info>(
+    htlc: &'info AccountInfo<'info>,
+    sender: &Pubkey,
+    Id: &[u8; 32],
+) -> Result<(Account<'info, HTLC>, u8)> {
+    require_writable(htlc)?;
+    let (address, htlc_bump) = find_htlc_address(sender, Id);
+    require_keys_eq!(htlc.key(), address, ErrorCode::ConstraintSeeds);
+    let htlc = Account::<HTLC>::try_from(htlc)?;
+    require_keys_eq!(htlc.sender, *sender, HTLCError::NotSender);
+    Ok((htlc, htlc_bump))
+}
+
+/// Loads the token account of an HTLC passed to a batch, writable and at the
+/// [HTLC_TOKEN_ACCOUNT_SEED, sender, Id] address.
+fn load_htlc_token_account<'info>(
+    htlc_token_account: &'info AccountInfo<'info>,
+    sender: &Pubkey,
+    Id: &[u8; 32],
+) -> Result<InterfaceAccount<'info, TokenAccount>> {
+    require_writable(htlc_token_account)?;
+    require_keys_eq!(
+        htlc_token_account.key(),
+        find_htlc_token_account_address(sender, Id).0,
+        ErrorCode::ConstraintSeeds
+    );
+    InterfaceAccount::try_from(htlc_token_account)
+}
+
+/// Loads a token account of `owner` for `token_contract` passed to a batch.
+fn load_owner_token_account<'info>(
+    token_account: &'info AccountInfo<'info>,
+    owner: &Pubkey,
+    token_contract: &Pubkey,
+) -> Result<InterfaceAccount<'info, TokenAccount>> {
+    require_writable(token_account)?;
+    let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account)?;
+    require_keys_eq!(token_account.owner, *owner, HTLCError::NotSender);
+    require_keys_eq!(token_account.mint, *token_contract, HTLCError::NoToken);
+    Ok(token_account)
+}
+
+/// Redeems the HTLC of a batch entry from its [sender, src_receiver, htlc,
+/// htlc_token_account, sender_token_account, src_receiver_token_account]
+/// accounts, with the checks of redeem, and persists it.
+///
+/// Returns the transfer fee withheld by the mint.
+fn redeem_entry<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    Id: [u8; 32],
+    secret: [u8; 32],
+    hash: [u8; 32],
+    user_signing: &AccountInfo<'info>,
+    token_contract: &InterfaceAccount<'info, Mint>,
+    token_program: &AccountInfo<'info>,
+    reward_token_account: &AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+) -> Result<u64> {
+    let [sender, src_receiver, htlc, htlc_token_account, sender_token_account, src_receiver_token_account] =
+        accounts
+    else {
+        return err!(HTLCError::BatchAccountsMismatch);
+    };
+    require_writable(sender)?;
+    let (mut htlc, htlc_bump) = load_htlc(htlc, sender.key, &Id)?;
+    require_keys_eq!(
+        htlc.src_receiver,
+        src_receiver.key(),
+        HTLCError::NotReciever
+    );
+    require_keys_eq!(
+        htlc.token_contract,
+        token_contract.key(),
+        HTLCError::NoToken
+    );
+    require_keys_eq!(
+        htlc.token_program,
+        token_program.key(),
+        HTLCError::WrongTokenProgram
+    );
+    let mut htlc_token_account = load_htlc_token_account(htlc_token_account, sender.key, &Id)?;
+    load_owner_token_account(sender_token_account, sender.key, &token_contract.key())?;
+    require_keys_eq!(
+        src_receiver_token_account.key(),
+        get_associated_token_address_with_program_id(
+            src_receiver.key,
+            &token_contract.key(),
+            token_program.key,
+        ),
+        ErrorCode::AccountNotAssociatedTokenAccount
+    );
+    load_owner_token_account(
+        src_receiver_token_account,
+        src_receiver.key,
+        &token_contract.key(),
+    )?;
+
+    let payout_fee = redeem_htlc(
+        &mut htlc,
+        Id,
+        secret,
+        hash,
+        htlc_bump,
+        user_signing,
+        sender,
+        &mut htlc_token_account,
+        token_contract,
+        token_program,
+        src_receiver_token_account,
+        sender_token_account,
+        reward_token_account,
+        hook_accounts,
+    )?;
+    htlc.exit(&ID)?;
+    Ok(payout_fee)
+}
+
+/// The size of a token account of `token_contract`, with the extensions its mint
+/// requires on the accounts that hold it.
+fn token_account_space(token_contract: &InterfaceAccount<Mint>) -> Result<usize> {
+    let info = token_contract.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(anchor_spl::token::TokenAccount::LEN);
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    let required =
+        ExtensionType::get_required_init_account_extensions(&mint.get_extension_types()?);
+    Ok(ExtensionType::try_calculate_account_len::<
+        spl_token_2022::state::Account,
+    >(&required)?)
+}
+
+/// Creates an account at a PDA of the program, the way `init` does for the
+/// single instructions, with `payer` paying the rent.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer = &[seeds];
+    let current = account.lamports();
+    if current == 0 {
+        let cpi_ctx = CpiContext::new_with_signer(
+            system_program.clone(),
+            system_program::CreateAccount {
+                from: payer.clone(),
+                to: account.clone(),
+            },
+            signer,
+        );
+        return system_program::create_account(cpi_ctx, rent, space as u64, owner);
+    }
+    // Someone sent lamports to the address beforehand, top it up instead.
+    if let Some(top_up) = rent.checked_sub(current).filter(|top_up| *top_up > 0) {
+        let cpi_ctx = CpiContext::new(
+            system_program.clone(),
+            system_program::Transfer {
+                from: payer.clone(),
+                to: account.clone(),
+            },
+        );
+        system_program::transfer(cpi_ctx, top_up)?;
+    }
+    let cpi_ctx = CpiContext::new_with_signer(
+        system_program.clone(),
+        system_program::Allocate {
+            account_to_allocate: account.clone(),
+        },
+        signer,
+    );
+    system_program::allocate(cpi_ctx, space as u64)?;
+    let cpi_ctx = CpiContext::new_with_signer(
+        system_program.clone(),
+        system_program::Assign {
+            account_to_assign: account.clone(),
+        },
+        signer,
+    );
+    system_program::assign(cpi_ctx, owner)
+}
+
+/// Writes the SecretRecord of `hashlock` on the first redeem that reveals its
+/// secret, with `payer` paying the rent. Later redeems leave it as it is.
+fn record_secret<'info>(
+    secret_record: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    hashlock: [u8; 32],
+    secret: [u8; 32],
+) -> Result<()> {
+    require_writable(secret_record)?;
+    let (address, bump) = find_secret_record_address(&hashlock);
+    require_keys_eq!(secret_record.key(), address, ErrorCode::ConstraintSeeds);
+    if secret_record.owner == &ID {
+        return Ok(());
+    }
+
+    let bump_vector = bump.to_le_bytes();
+    let inner = vec![SECRET_RECORD_SEED, hashlock.as_ref(), bump_vector.as_ref()];
+    create_pda_account(
+        payer,
+        secret_record,
+        system_program,
+        8 + SecretRecord::INIT_SPACE,
+        &ID,
+        inner.as_slice(),
+    )?;
+    SecretRecord {
+        hashlock,
+        secret,
+        revealed_at: current_time()?,
+    }
+    .try_serialize(&mut &mut secret_record.try_borrow_mut_data()?[..])
+}
+
+/// Reads the secret from the SecretRecord of the program at `secret_record`,
+/// if it was written.
+fn recorded_secret(secret_record: &AccountInfo) -> Result<Option<[u8; 32]>> {
+    if secret_record.owner != &ID {
+        return Ok(None);
+    }
+    let record = SecretRecord::try_deserialize(&mut &secret_record.try_borrow_data()?[..])?;
+    Ok(Some(record.secret))
+}
+
+/// Reports whether an entry of a batch settled, with what its checks returned.
+/// In best-effort mode an entry that fails its checks is logged and skipped,
+/// otherwise its error aborts the whole batch. Only the checks can be skipped:
+/// a failing transfer CPI always fails the transaction.
+fn settle_entry<T>(best_effort: bool, index: usize, result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if best_effort => {
+            msg!("Skipped batch entry {}: {}", index, err);
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Emits a lifecycle event. With the `event-cpi` feature the event is written
+/// through a self-CPI signed by the event authority, so it can be read from the
+/// inner instructions and survives log truncation. Otherwise it is logged.
+macro_rules! emit_event {
+    ($ctx:ident, $event:expr) => {{
+        let event = $event;
+        #[cfg(feature = "event-cpi")]
+        {
+            let ctx = &$ctx;
+            emit_cpi!(event);
+        }
+        #[cfg(not(feature = "event-cpi"))]
+        emit!(event);
+    }};
+}
+
 #[program]
 pub mod anchor_htlc {
     use super::*;
@@ -224,11 +919,15 @@ pub mod anchor_htlc {
     /// @dev Sender / Payer sets up a new pre-hash time lock contract depositing the
     /// funds and providing the reciever/src_receiver and terms.
     /// @param src_receiver reciever of the funds.
+    /// @param hopChains, hopAssets, hopAddresses the route to the destination chain,
+    ///                  stored on the HTLC and returned by getDetails.
     /// @param timelock UNIX epoch seconds time that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param fee_payer who pays the transfer fee of the payout for transfer-fee mints.
+    ///                  If it is the sender, enough is deposited for amount to arrive.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
-    pub fn commit(
-        ctx: Context<Commit>,
+    pub fn commit<'info>(
+        ctx: Context<'_, '_, '_, 'info, Commit<'info>>,
         Id: [u8; 32],
         hopChains: Vec<String>,
         hopAssets: Vec<String>,
@@ -240,28 +939,54 @@ pub mod anchor_htlc {
         src_receiver: Pubkey,
         timelock: u64,
         amount: u64,
+        fee_payer: FeePayer,
+        authorized_locker: Option<Pubkey>,
     ) -> Result<[u8; 32]> {
-        let clock = Clock::get().unwrap();
-        let time: u64 = clock.unix_timestamp.try_into().unwrap();
-        require!(timelock >= time + 900, HTLCError::InvalidTimeLock);
+        let time = current_time()?;
+        let min_timelock = time
+            .checked_add(900)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(timelock >= min_timelock, HTLCError::InvalidTimeLock);
         require!(amount != 0, HTLCError::FundsNotSent);
+        check_string_lengths(&dst_chain, &dst_asset, &dst_address, &src_asset)?;
+        check_hops(&hopChains, &hopAssets, &hopAddress)?;
 
         let htlc = &mut ctx.accounts.htlc;
 
         let htlc_bump = ctx.bumps.htlc;
         let bump_vector = htlc_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+        let sender_key = ctx.accounts.sender.key();
+        let inner = vec![
+            HTLC_SEED,
+            sender_key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
         let outer = vec![inner.as_slice()];
+        let deposit = deposit_amount(&ctx.accounts.token_contract, amount, fee_payer)?;
         let transfer_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: ctx.accounts.sender_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
                 to: ctx.accounts.htlc_token_account.to_account_info(),
                 authority: ctx.accounts.sender.to_account_info(),
             },
             outer.as_slice(),
-        );
-        anchor_spl::token::transfer(transfer_context, amount)?;
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        transfer_checked(
+            transfer_context,
+            deposit,
+            ctx.accounts.token_contract.decimals,
+        )?;
+
+        // Record what actually arrived, transfer-fee mints withhold part of the deposit.
+        ctx.accounts.htlc_token_account.reload()?;
+        let received = ctx.accounts.htlc_token_account.amount;
+        let lock_fee = deposit
+            .checked_sub(received)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
 
         htlc.dst_address = dst_address;
         htlc.dst_chain = dst_chain;
@@ -269,19 +994,41 @@ pub mod anchor_htlc {
         htlc.src_asset = src_asset;
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
         htlc.src_receiver = src_receiver;
+        htlc.hop_chains = hopChains;
+        htlc.hop_assets = hopAssets;
+        htlc.hop_addresses = hopAddress;
         htlc.hashlock = [0u8; 32];
         htlc.secret = [0u8; 32];
-        htlc.amount = amount;
+        htlc.amount = received;
         htlc.timelock = timelock;
         htlc.reward = 0;
         htlc.reward_timelock = 0;
         htlc.token_contract = *ctx.accounts.token_contract.to_account_info().key;
         htlc.token_wallet = *ctx.accounts.htlc_token_account.to_account_info().key;
-        htlc.claimed = 1;
+        htlc.token_program = ctx.accounts.token_program.key();
+        htlc.fee_payer = fee_payer;
+        htlc.status = HtlcStatus::Committed;
+        htlc.authorized_locker = authorized_locker;
+
+        emit_event!(
+            ctx,
+            TokenCommitted {
+                Id,
+                hop_chains: htlc.hop_chains.clone(),
+                hop_assets: htlc.hop_assets.clone(),
+                hop_addresses: htlc.hop_addresses.clone(),
+                dst_chain: htlc.dst_chain.clone(),
+                dst_address: htlc.dst_address.clone(),
+                dst_asset: htlc.dst_asset.clone(),
+                sender: htlc.sender,
+                src_receiver: htlc.src_receiver,
+                src_asset: htlc.src_asset.clone(),
+                amount: received,
+                lock_fee,
+                timelock,
+            }
+        );
 
-        // msg!("hop chains: {:?}", hopChains);
-        // msg!("hop assets: {:?}", hopAssets);
-        // msg!("hop addresses: {:?}", hopAddresses);
         Ok(Id)
     }
 
@@ -291,9 +1038,11 @@ pub mod anchor_htlc {
     /// @param hashlock A sha-256 hash hashlock.
     /// @param timelock UNIX epoch seconds time that the lock expires at.
     ///                  Refunds can be made after this time.
+    /// @param fee_payer who pays the transfer fee of the payout for transfer-fee mints.
+    ///                  If it is the sender, enough is deposited for amount to arrive.
     /// @return Id of the new HTLC. This is needed for subsequent calls.
-    pub fn lock(
-        ctx: Context<Lock>,
+    pub fn lock<'info>(
+        ctx: Context<'_, '_, '_, 'info, Lock<'info>>,
         Id: [u8; 32],
         hashlock: [u8; 32],
         timelock: u64,
@@ -303,28 +1052,53 @@ pub mod anchor_htlc {
         src_asset: String,
         src_receiver: Pubkey,
         amount: u64,
+        fee_payer: FeePayer,
     ) -> Result<[u8; 32]> {
-        let clock = Clock::get().unwrap();
-        let time: u64 = clock.unix_timestamp.try_into().unwrap();
-        require!(timelock >= time + 1800, HTLCError::InvalidTimeLock);
-        require!(amount != 0, HTLCError::FundsNotSent);
+        check_lock_terms(
+            timelock,
+            amount,
+            &dst_chain,
+            &dst_asset,
+            &dst_address,
+            &src_asset,
+        )?;
 
         let htlc = &mut ctx.accounts.htlc;
 
         let htlc_bump = ctx.bumps.htlc;
         let bump_vector = htlc_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+        let sender_key = ctx.accounts.sender.key();
+        let inner = vec![
+            HTLC_SEED,
+            sender_key.as_ref(),
+            Id.as_ref(),
+            bump_vector.as_ref(),
+        ];
         let outer = vec![inner.as_slice()];
+        let deposit = deposit_amount(&ctx.accounts.token_contract, amount, fee_payer)?;
         let transfer_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: ctx.accounts.sender_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
                 to: ctx.accounts.htlc_token_account.to_account_info(),
                 authority: ctx.accounts.sender.to_account_info(),
             },
             outer.as_slice(),
-        );
-        anchor_spl::token::transfer(transfer_context, amount)?;
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        transfer_checked(
+            transfer_context,
+            deposit,
+            ctx.accounts.token_contract.decimals,
+        )?;
+
+        // Record what actually arrived, transfer-fee mints withhold part of the deposit.
+        ctx.accounts.htlc_token_account.reload()?;
+        let received = ctx.accounts.htlc_token_account.amount;
+        let lock_fee = deposit
+            .checked_sub(received)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
 
         htlc.dst_address = dst_address;
         htlc.dst_chain = dst_chain;
@@ -332,57 +1106,129 @@ pub mod anchor_htlc {
         htlc.src_asset = src_asset;
         htlc.sender = *ctx.accounts.sender.to_account_info().key;
         htlc.src_receiver = src_receiver;
+        htlc.hop_chains = Vec::new();
+        htlc.hop_assets = Vec::new();
+        htlc.hop_addresses = Vec::new();
         htlc.hashlock = hashlock;
         htlc.secret = [0u8; 32];
-        htlc.amount = amount;
+        htlc.amount = received;
         htlc.timelock = timelock;
         htlc.reward = 0;
         htlc.reward_timelock = 0;
         htlc.token_contract = *ctx.accounts.token_contract.to_account_info().key;
         htlc.token_wallet = *ctx.accounts.htlc_token_account.to_account_info().key;
-        htlc.claimed = 1;
+        htlc.token_program = ctx.accounts.token_program.key();
+        htlc.fee_payer = fee_payer;
+        htlc.status = HtlcStatus::Locked;
+
+        emit_event!(
+            ctx,
+            TokenLocked {
+                Id,
+                hashlock,
+                dst_chain: htlc.dst_chain.clone(),
+                dst_address: htlc.dst_address.clone(),
+                dst_asset: htlc.dst_asset.clone(),
+                sender: htlc.sender,
+                src_receiver: htlc.src_receiver,
+                src_asset: htlc.src_asset.clone(),
+                amount: received,
+                lock_fee,
+                reward: 0,
+                reward_timelock: 0,
+                timelock,
+            }
+        );
 
         Ok(Id)
     }
 
-    pub fn lock_reward(
-        ctx: Context<LockReward>,
+    pub fn lock_reward<'info>(
+        ctx: Context<'_, '_, '_, 'info, LockReward<'info>>,
         Id: [u8; 32],
         reward_timelock: u64,
         reward: u64,
     ) -> Result<bool> {
-        let clock = Clock::get().unwrap();
+        let time = current_time()?;
         let htlc = &mut ctx.accounts.htlc;
 
         require!(
-            reward_timelock < htlc.timelock
-                && reward_timelock > clock.unix_timestamp.try_into().unwrap(),
+            reward_timelock < htlc.timelock && reward_timelock > time,
             HTLCError::InvalidRewardTimeLock
         );
+        htlc.status.ensure_pending()?;
         require!(htlc.reward == 0, HTLCError::RewardAlreadyExists);
 
         htlc.reward_timelock = reward_timelock;
-        htlc.reward = reward;
 
-        let htlc_bump = ctx.bumps.htlc;
-        let bump_vector = htlc_bump.to_le_bytes();
-        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
-        let outer = vec![inner.as_slice()];
+        let deposit = deposit_amount(&ctx.accounts.token_contract, reward, htlc.fee_payer)?;
+        let balance = ctx.accounts.htlc_token_account.amount;
+
+        // a session pays from the sender token account as its SPL delegate
+        let sender_key = ctx.accounts.sender.key();
+        let authority_key = ctx.accounts.authority.key();
+        let (authority, session_bump) = match &mut ctx.accounts.session {
+            Some(session) => {
+                session.use_for(SESSION_LOCK_REWARD, deposit)?;
+                (session.to_account_info(), Some(session.bump))
+            }
+            None => (ctx.accounts.authority.to_account_info(), None),
+        };
+        let bump_vector = session_bump.unwrap_or_default().to_le_bytes();
+        let inner = vec![
+            SESSION_SEED,
+            sender_key.as_ref(),
+            authority_key.as_ref(),
+            bump_vector.as_ref(),
+        ];
+        let outer = match session_bump {
+            Some(_) => vec![inner.as_slice()],
+            None => vec![],
+        };
         let transfer_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
+            TransferChecked {
                 from: ctx.accounts.sender_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
                 to: ctx.accounts.htlc_token_account.to_account_info(),
-                authority: ctx.accounts.sender.to_account_info(),
+                authority,
             },
             outer.as_slice(),
+        )
+        .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        transfer_checked(
+            transfer_context,
+            deposit,
+            ctx.accounts.token_contract.decimals,
+        )?;
+
+        ctx.accounts.htlc_token_account.reload()?;
+        let received = ctx
+            .accounts
+            .htlc_token_account
+            .amount
+            .checked_sub(balance)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        let lock_fee = deposit
+            .checked_sub(received)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        htlc.reward = received;
+
+        emit_event!(
+            ctx,
+            TokenRewardLocked {
+                Id,
+                reward: received,
+                lock_fee,
+                reward_timelock,
+            }
         );
-        anchor_spl::token::transfer(transfer_context, reward)?;
 
         Ok(true)
     }
 
-    /// @dev Called by the sender to add hashlock to the HTLC
+    /// @dev Called by the sender, its authorized_locker or the delegate of its
+    /// session to add hashlock to the HTLC
     ///
     /// @param Id of the HTLC.
     /// @param hashlock to be added.
@@ -392,191 +1238,871 @@ pub mod anchor_htlc {
         hashlock: [u8; 32],
         timelock: u64,
     ) -> Result<[u8; 32]> {
-        let clock = Clock::get().unwrap();
-        let time: u64 = clock.unix_timestamp.try_into().unwrap();
-        require!(timelock >= time + 900, HTLCError::InvalidTimeLock);
+        let time = current_time()?;
+        let min_timelock = time
+            .checked_add(900)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(timelock >= min_timelock, HTLCError::InvalidTimeLock);
+
+        if let Some(session) = &mut ctx.accounts.session {
+            session.use_for(SESSION_ADD_LOCK, 0)?;
+        }
 
         let htlc = &mut ctx.accounts.htlc;
-
-        msg!("Id: {:?}", hex::encode(Id));
-        msg!("hashlock: {:?}", hex::encode(hashlock));
-        msg!("timelock: {:?}", timelock);
-
+        htlc.status = htlc.status.transition(HtlcStatus::Locked)?;
         htlc.hashlock = hashlock;
         htlc.timelock = timelock;
 
+        emit_event!(
+            ctx,
+            TokenLockAdded {
+                Id,
+                hashlock,
+                timelock,
+            }
+        );
+
         Ok(Id)
     }
 
-    /// @dev Called by the solver to add hashlock to the HTLC
+    /// @dev Called by the solver to add hashlock to the HTLC with a signature
+    /// of the sender bound to this program, this cluster and a deadline.
     ///
     /// @param Id of the HTLC.
     /// @param hashlock to be added.
+    /// @param deadline after which the signature is no longer accepted.
     pub fn add_lock_sig(
         ctx: Context<AddLockSig>,
         Id: [u8; 32],
         hashlock: [u8; 32],
         timelock: u64,
+        deadline: u64,
         signature: [u8; 64],
     ) -> Result<[u8; 32]> {
-        let clock = Clock::get().unwrap();
-        let time: u64 = clock.unix_timestamp.try_into().unwrap();
-        require!(timelock >= time + 900, HTLCError::InvalidTimeLock);
+        let time = current_time()?;
+        require!(time <= deadline, HTLCError::SignatureExpired);
+        let min_timelock = time
+            .checked_add(900)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(timelock >= min_timelock, HTLCError::InvalidTimeLock);
         let htlc = &mut ctx.accounts.htlc;
+        htlc.status = htlc.status.transition(HtlcStatus::Locked)?;
         let signer = htlc.sender.to_bytes();
 
-        let ix: Instruction = load_instruction_at_checked(0, &ctx.accounts.ix_sysvar)?;
-
-        let mut hasher = Sha256::new();
-        hasher.update(Id.clone());
-        hasher.update(hashlock.clone());
-        hasher.update(timelock.to_le_bytes());
-        let hash = hasher.finalize();
-
-        let signing_domain: &[u8; 16] = b"\xffsolana offchain";
-        let header_version: [u8; 1] = [0u8]; // Version 0
-        let mut application_domain = [0u8; 32];
-        application_domain[..5].copy_from_slice(b"Train");
-        let message_format = [0u8]; // Assuming Restricted ASCII
-        let signer_count = [1u8]; // One signer
-        let message_length = (hash.len() as u16).to_le_bytes();
-
-        let mut raw_message = Vec::new();
-        raw_message.extend_from_slice(signing_domain);
-        raw_message.extend_from_slice(&header_version);
-        raw_message.extend_from_slice(&application_domain);
-        raw_message.extend_from_slice(&message_format);
-        raw_message.extend_from_slice(&signer_count);
-        raw_message.extend_from_slice(&signer);
-        raw_message.extend_from_slice(&message_length);
-        raw_message.extend_from_slice(&hash);
-
-        let hex_message = hex::encode(raw_message);
-        let full_message = hex_message.into_bytes();
-
-        // Check that ix is what we expect to have been sent
-        if ix.program_id!= ED25519_ID ||  // The program id we expect
-        ix.accounts.len()!= 0
-        // With no context accounts
-        || ix.data.len()!= (16 + 64 + 32 + full_message.len())
-        // And data of this size
-        {
-            return Err(HTLCError::SigVerificationFailed.into());
-        }
+        let full_message = add_lock_message(
+            &ADD_LOCK_SIG_DOMAIN,
+            &Id,
+            &hashlock,
+            timelock,
+            deadline,
+            &signer,
+        );
 
-        check_ed25519_data(&ix.data, &signer, &full_message, &signature)?;
+        // The Ed25519Program instruction may sit anywhere in the transaction
+        require!(
+            find_ed25519_verification(&ctx.accounts.ix_sysvar, &signer, &full_message, &signature)?,
+            HTLCError::SigVerificationFailed
+        );
 
         htlc.hashlock = hashlock;
         htlc.timelock = timelock;
 
-        msg!("Id: {:?}", hex::encode(Id));
-        msg!("hashlock: {:?}", hex::encode(hashlock));
-        msg!("timelock: {:?}", timelock);
+        emit_event!(
+            ctx,
+            TokenLockAdded {
+                Id,
+                hashlock,
+                timelock,
+            }
+        );
 
         Ok(Id)
     }
 
-    /// @dev Called by the src_receiver once they know the secret of the hashlock.
-    /// This will transfer the locked funds to the HTLC's src_receiver's address.
+    /// @dev add_lock_sig with the legacy message, which is bound to neither
+    /// the program, the cluster nor a deadline. Accepted while senders migrate,
+    /// only in builds with the `legacy-lock-sig` feature.
     ///
     /// @param Id of the HTLC.
-    /// @param secret sha256(secret) should equal the contract hashlock.
-    pub fn redeem(
-        ctx: Context<Redeem>,
+    /// @param hashlock to be added.
+    pub fn add_lock_sig_legacy(
+        ctx: Context<AddLockSig>,
         Id: [u8; 32],
-        secret: [u8; 32],
-        htlc_bump: u8,
-    ) -> Result<bool> {
+        hashlock: [u8; 32],
+        timelock: u64,
+        signature: [u8; 64],
+    ) -> Result<[u8; 32]> {
+        require!(
+            cfg!(feature = "legacy-lock-sig"),
+            HTLCError::LegacyLockSigDisabled
+        );
+        let time = current_time()?;
+        let min_timelock = time
+            .checked_add(900)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(timelock >= min_timelock, HTLCError::InvalidTimeLock);
         let htlc = &mut ctx.accounts.htlc;
-        let mut hasher = Sha256::new();
-        hasher.update(secret.clone());
-        let hash = hasher.finalize();
-        require!([0u8; 32] != htlc.hashlock, HTLCError::HashlockNotSet);
-        require!(hash == htlc.hashlock.into(), HTLCError::HashlockNoMatch);
-
-        htlc.claimed = 3;
-        htlc.secret = secret;
-        if htlc.reward != 0 {
-            // if redeem is called before the reward_timelock sender should get the reward back
-            if htlc.reward_timelock > Clock::get().unwrap().unix_timestamp.try_into().unwrap() {
-                transfer_htlc_reward_out(
-                    ctx.accounts.sender.to_account_info(),
-                    Id,
-                    htlc.to_account_info(),
-                    htlc_bump,
-                    &mut ctx.accounts.htlc_token_account,
-                    ctx.accounts.token_program.to_account_info(),
-                    ctx.accounts.src_receiver_token_account.to_account_info(),
-                    ctx.accounts.sender_token_account.to_account_info(),
-                    ctx.accounts.htlc.amount,
-                    ctx.accounts.htlc.reward,
-                )?;
-            } else {
-                // if the caller is the receiver then they should get and the amount,
-                // and the reward
-                if ctx.accounts.user_signing.key() == ctx.accounts.src_receiver.key() {
-                    transfer_htlc_out(
-                        ctx.accounts.sender.to_account_info(),
-                        Id,
-                        htlc.to_account_info(),
-                        htlc_bump,
-                        &mut ctx.accounts.htlc_token_account,
-                        ctx.accounts.token_program.to_account_info(),
-                        ctx.accounts.src_receiver_token_account.to_account_info(),
-                        ctx.accounts.htlc.amount + ctx.accounts.htlc.reward,
-                    )?;
-                } else {
-                    transfer_htlc_reward_out(
-                        ctx.accounts.sender.to_account_info(),
-                        Id,
-                        htlc.to_account_info(),
-                        htlc_bump,
-                        &mut ctx.accounts.htlc_token_account,
-                        ctx.accounts.token_program.to_account_info(),
-                        ctx.accounts.src_receiver_token_account.to_account_info(),
-                        ctx.accounts.reward_token_account.to_account_info(),
-                        ctx.accounts.htlc.amount,
-                        ctx.accounts.htlc.reward,
-                    )?;
-                }
-            }
-        } else {
-            // send the tokens to the receiver if the reward is set to zero
-            transfer_htlc_out(
-                ctx.accounts.sender.to_account_info(),
+        htlc.status = htlc.status.transition(HtlcStatus::Locked)?;
+        let signer = htlc.sender.to_bytes();
+
+        let full_message = legacy::add_lock_message(&Id, &hashlock, timelock, &signer);
+
+        // The Ed25519Program instruction may sit anywhere in the transaction
+        require!(
+            find_ed25519_verification(&ctx.accounts.ix_sysvar, &signer, &full_message, &signature)?,
+            HTLCError::SigVerificationFailed
+        );
+
+        htlc.hashlock = hashlock;
+        htlc.timelock = timelock;
+
+        emit_event!(
+            ctx,
+            TokenLockAdded {
                 Id,
-                htlc.to_account_info(),
-                htlc_bump,
-                &mut ctx.accounts.htlc_token_account,
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.src_receiver_token_account.to_account_info(),
-                ctx.accounts.htlc.amount,
-            )?;
-        }
+                hashlock,
+                timelock,
+            }
+        );
 
-        Ok(true)
+        Ok(Id)
     }
 
-    /// @dev Called by the sender if there was no redeem AND the time lock has
-    /// expired. This will refund the contract amount.
+    /// @dev Called by the sender to let another key add the hashlock with
+    /// add_lock, replacing the one set at commit. None revokes it.
     ///
-    /// @param Id of the HTLC to refund from.
-    pub fn refund(ctx: Context<Refund>, Id: [u8; 32], htlc_bump: u8) -> Result<bool> {
+    /// @param Id of the HTLC.
+    /// @param authorized_locker key that may call add_lock besides the sender.
+    pub fn set_authorized_locker(
+        ctx: Context<SetAuthorizedLocker>,
+        Id: [u8; 32],
+        authorized_locker: Option<Pubkey>,
+    ) -> Result<[u8; 32]> {
         let htlc = &mut ctx.accounts.htlc;
+        htlc.status.ensure_pending()?;
+        require!(
+            htlc.status == HtlcStatus::Committed,
+            HTLCError::HashlockAlreadySet
+        );
+        htlc.authorized_locker = authorized_locker;
 
-        htlc.claimed = 2;
+        Ok(Id)
+    }
 
-        transfer_htlc_out(
-            ctx.accounts.sender.to_account_info(),
+    /// @dev Called by the sender to let `delegate` act for it until `expiry`
+    /// through a Session PDA. The delegate may call the instructions in
+    /// `permissions` and spend up to `max_amount` tokens of the sender token
+    /// account, for which the session is approved as SPL delegate. A token
+    /// account has a single delegate, so it backs one session at a time and
+    /// the previous session must be revoked first.
+    ///
+    /// @param delegate key signing instead of the sender.
+    /// @param expiry UNIX epoch seconds after which the session is rejected.
+    /// @param max_amount budget of the session in base units of token_contract.
+    /// @param permissions bitmask of SESSION_ADD_LOCK and SESSION_LOCK_REWARD.
+    pub fn create_session(
+        ctx: Context<CreateSession>,
+        delegate: Pubkey,
+        expiry: u64,
+        max_amount: u64,
+        permissions: u8,
+    ) -> Result<bool> {
+        require!(expiry > current_time()?, HTLCError::SessionExpired);
+
+        let session = &mut ctx.accounts.session;
+        session.sender = ctx.accounts.sender.key();
+        session.delegate = delegate;
+        session.token_contract = ctx.accounts.token_contract.key();
+        session.expiry = expiry;
+        session.max_amount = max_amount;
+        session.permissions = permissions;
+        session.bump = ctx.bumps.session;
+
+        let approve_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Approve {
+                to: ctx.accounts.sender_token_account.to_account_info(),
+                delegate: session.to_account_info(),
+                authority: ctx.accounts.sender.to_account_info(),
+            },
+        );
+        token_interface::approve(approve_context, max_amount)?;
+
+        Ok(true)
+    }
+
+    /// @dev Called by the sender to end a session at once. The session account is
+    /// closed and, if it is still the SPL delegate of the sender token account,
+    /// its approval is revoked.
+    ///
+    /// @param delegate of the session.
+    pub fn revoke_session(ctx: Context<RevokeSession>, delegate: Pubkey) -> Result<bool> {
+        require!(
+            ctx.accounts.session.delegate == delegate,
+            HTLCError::UnauthorizedAccess
+        );
+        let sender_token_account = &ctx.accounts.sender_token_account;
+        if sender_token_account.delegate == Some(ctx.accounts.session.key()).into() {
+            let revoke_context = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Revoke {
+                    source: sender_token_account.to_account_info(),
+                    authority: ctx.accounts.sender.to_account_info(),
+                },
+            );
+            token_interface::revoke(revoke_context)?;
+        }
+        Ok(true)
+    }
+
+    /// @dev Called by the sender to let an Ethereum address add the hashlock
+    /// with add_lock_sig_evm. The zero address removes it.
+    ///
+    /// @param Id of the HTLC.
+    /// @param evm_authorizer Ethereum address signing the addLockMsg.
+    pub fn set_evm_authorizer(
+        ctx: Context<SetEvmAuthorizer>,
+        Id: [u8; 32],
+        evm_authorizer: [u8; 20],
+    ) -> Result<[u8; 32]> {
+        let htlc = &mut ctx.accounts.htlc;
+        htlc.status.ensure_pending()?;
+        require!(
+            htlc.status == HtlcStatus::Committed,
+            HTLCError::HashlockAlreadySet
+        );
+        htlc.evm_authorizer = evm_authorizer;
+
+        Ok(Id)
+    }
+
+    /// @dev Called by the solver to add hashlock to the HTLC with an EIP-712
+    /// addLockMsg signature of its evm_authorizer, as addLockSig on EVM, bound
+    /// to this program, this cluster and a deadline.
+    ///
+    /// @param Id of the HTLC.
+    /// @param hashlock to be added.
+    /// @param deadline after which the signature is no longer accepted.
+    /// @param r, s, v the ECDSA signature of the addLockMsg.
+    pub fn add_lock_sig_evm(
+        ctx: Context<AddLockSigEvm>,
+        Id: [u8; 32],
+        hashlock: [u8; 32],
+        timelock: u64,
+        deadline: u64,
+        r: [u8; 32],
+        s: [u8; 32],
+        v: u8,
+    ) -> Result<[u8; 32]> {
+        let time = current_time()?;
+        require!(time <= deadline, HTLCError::SignatureExpired);
+        let min_timelock = time
+            .checked_add(900)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(
+            timelock >= min_timelock && timelock <= MAX_TIMELOCK,
+            HTLCError::InvalidTimeLock
+        );
+        let htlc = &mut ctx.accounts.htlc;
+        htlc.status = htlc.status.transition(HtlcStatus::Locked)?;
+        require!(htlc.evm_authorizer != [0; 20], HTLCError::NoEvmAuthorizer);
+
+        let digest = add_lock_digest(&ADD_LOCK_SIG_DOMAIN, &Id, &hashlock, timelock, deadline);
+        require!(
+            recover_evm_address(&digest, &r, &s, v) == Some(htlc.evm_authorizer),
+            HTLCError::SigVerificationFailed
+        );
+
+        htlc.hashlock = hashlock;
+        htlc.timelock = timelock;
+
+        emit_event!(
+            ctx,
+            TokenLockAdded {
+                Id,
+                hashlock,
+                timelock,
+            }
+        );
+
+        Ok(Id)
+    }
+
+    /// @dev Called by the src_receiver once they know the secret of the hashlock.
+    /// This will transfer the locked funds to the HTLC's src_receiver's address.
+    /// The first redeem of a hashlock also writes its SecretRecord, paid by the
+    /// caller.
+    ///
+    /// @param Id of the HTLC.
+    /// @param secret sha256(secret) should equal the contract hashlock.
+    pub fn redeem<'info>(
+        ctx: Context<'_, '_, '_, 'info, Redeem<'info>>,
+        Id: [u8; 32],
+        secret: [u8; 32],
+        htlc_bump: u8,
+    ) -> Result<bool> {
+        let hashlock = hash_secret(&secret);
+        record_secret(
+            &ctx.accounts.secret_record,
+            &ctx.accounts.user_signing,
+            &ctx.accounts.system_program,
+            hashlock,
+            secret,
+        )?;
+        let payout_fee = redeem_htlc(
+            &mut ctx.accounts.htlc,
             Id,
-            htlc.to_account_info(),
+            secret,
+            hashlock,
             htlc_bump,
+            &ctx.accounts.user_signing,
+            &ctx.accounts.sender,
             &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
+            &ctx.accounts.token_program,
+            &ctx.accounts.src_receiver_token_account.to_account_info(),
+            &ctx.accounts.sender_token_account.to_account_info(),
+            &ctx.accounts.reward_token_account.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
+        emit_event!(
+            ctx,
+            TokenRedeemed {
+                Id,
+                redeem_address: ctx.accounts.user_signing.key(),
+                secret,
+                hashlock: ctx.accounts.htlc.hashlock,
+                payout_fee,
+            }
+        );
+
+        Ok(true)
+    }
+
+    /// @dev Called by the sender if there was no redeem AND the time lock has
+    /// expired. This will refund the contract amount.
+    ///
+    /// @param Id of the HTLC to refund from.
+    pub fn refund<'info>(
+        ctx: Context<'_, '_, '_, 'info, Refund<'info>>,
+        Id: [u8; 32],
+        htlc_bump: u8,
+    ) -> Result<bool> {
+        let payout_fee = refund_htlc(
+            &mut ctx.accounts.htlc,
+            Id,
+            htlc_bump,
+            &ctx.accounts.sender,
+            &mut ctx.accounts.htlc_token_account,
+            &ctx.accounts.token_contract,
+            &ctx.accounts.token_program,
+            &ctx.accounts.sender_token_account.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
+        emit_event!(ctx, TokenRefunded { Id, payout_fee });
+
+        Ok(true)
+    }
+
+    /// @dev Sender / Payer locks several new HTLCs of one token in one
+    /// instruction. The htlc and htlc_token_account of each entry are passed in
+    /// the remaining accounts, in order, followed by the transfer hook accounts
+    /// of the mint if it has a hook.
+    /// @param entries the terms of each HTLC, as taken by lock.
+    /// @param best_effort skip the entries that fail their checks instead of
+    ///                  failing the whole batch.
+    /// @return for each entry whether its HTLC was locked.
+    pub fn batch_lock<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchLock<'info>>,
+        entries: Vec<LockEntry>,
+        best_effort: bool,
+    ) -> Result<Vec<bool>> {
+        let sender = ctx.accounts.sender.to_account_info();
+        let token_contract = &ctx.accounts.token_contract;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let token_account_space = token_account_space(token_contract)?;
+        let (accounts, hook_accounts) = batch_accounts(ctx.remaining_accounts, entries.len(), 2)?;
+        let mut settled = Vec::with_capacity(entries.len());
+        for (index, (entry, accounts)) in entries.into_iter().zip(accounts).enumerate() {
+            let [htlc, htlc_token_account] = accounts else {
+                return err!(HTLCError::BatchAccountsMismatch);
+            };
+            // Everything that can skip the entry runs before its accounts are
+            // created, so a skipped entry leaves nothing behind.
+            let checked = (|| {
+                check_lock_terms(
+                    entry.timelock,
+                    entry.amount,
+                    &entry.dst_chain,
+                    &entry.dst_asset,
+                    &entry.dst_address,
+                    &entry.src_asset,
+                )?;
+                require_writable(htlc)?;
+                require_writable(htlc_token_account)?;
+                let (address, htlc_bump) = find_htlc_address(sender.key, &entry.Id);
+                require_keys_eq!(htlc.key(), address, ErrorCode::ConstraintSeeds);
+                let (address, token_account_bump) =
+                    find_htlc_token_account_address(sender.key, &entry.Id);
+                require_keys_eq!(
+                    htlc_token_account.key(),
+                    address,
+                    ErrorCode::ConstraintSeeds
+                );
+                require!(
+                    htlc.owner == &System::id()
+                        && htlc.data_is_empty()
+                        && htlc_token_account.owner == &System::id()
+                        && htlc_token_account.data_is_empty(),
+                    HTLCError::HtlcAlreadyExists
+                );
+                let deposit = deposit_amount(token_contract, entry.amount, entry.fee_payer)?;
+                Ok((htlc_bump, token_account_bump, deposit))
+            })();
+            let Some((htlc_bump, token_account_bump, deposit)) =
+                settle_entry(best_effort, index, checked)?
+            else {
+                settled.push(false);
+                continue;
+            };
+
+            let bump_vector = htlc_bump.to_le_bytes();
+            let inner = vec![
+                HTLC_SEED,
+                sender.key.as_ref(),
+                entry.Id.as_ref(),
+                bump_vector.as_ref(),
+            ];
+            create_pda_account(
+                &sender,
+                htlc,
+                &system_program,
+                8 + HTLC::space(0),
+                &ID,
+                inner.as_slice(),
+            )?;
+            let bump_vector = token_account_bump.to_le_bytes();
+            let inner = vec![
+                HTLC_TOKEN_ACCOUNT_SEED,
+                sender.key.as_ref(),
+                entry.Id.as_ref(),
+                bump_vector.as_ref(),
+            ];
+            create_pda_account(
+                &sender,
+                htlc_token_account,
+                &system_program,
+                token_account_space,
+                token_program.key,
+                inner.as_slice(),
+            )?;
+            token_interface::initialize_account3(CpiContext::new(
+                token_program.clone(),
+                InitializeAccount3 {
+                    account: htlc_token_account.clone(),
+                    mint: token_contract.to_account_info(),
+                    authority: htlc.clone(),
+                },
+            ))?;
+
+            let transfer_context = CpiContext::new(
+                token_program.clone(),
+                TransferChecked {
+                    from: ctx.accounts.sender_token_account.to_account_info(),
+                    mint: token_contract.to_account_info(),
+                    to: htlc_token_account.clone(),
+                    authority: sender.clone(),
+                },
+            )
+            .with_remaining_accounts(hook_accounts.to_vec());
+            transfer_checked(transfer_context, deposit, token_contract.decimals)?;
+
+            // Record what actually arrived, transfer-fee mints withhold part of the deposit.
+            let received = InterfaceAccount::<TokenAccount>::try_from(htlc_token_account)?.amount;
+            let lock_fee = deposit
+                .checked_sub(received)
+                .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+
+            let LockEntry {
+                Id,
+                hashlock,
+                timelock,
+                dst_chain,
+                dst_address,
+                dst_asset,
+                src_asset,
+                src_receiver,
+                fee_payer,
+                ..
+            } = entry;
+            HTLC {
+                dst_address: dst_address.clone(),
+                dst_chain: dst_chain.clone(),
+                dst_asset: dst_asset.clone(),
+                src_asset: src_asset.clone(),
+                sender: sender.key(),
+                src_receiver,
+                hashlock,
+                amount: received,
+                timelock,
+                token_contract: token_contract.key(),
+                token_wallet: htlc_token_account.key(),
+                token_program: token_program.key(),
+                fee_payer,
+                status: HtlcStatus::Locked,
+                ..HTLC::default()
+            }
+            .try_serialize(&mut &mut htlc.try_borrow_mut_data()?[..])?;
+
+            emit_event!(
+                ctx,
+                TokenLocked {
+                    Id,
+                    hashlock,
+                    dst_chain,
+                    dst_address,
+                    dst_asset,
+                    sender: sender.key(),
+                    src_receiver,
+                    src_asset,
+                    amount: received,
+                    lock_fee,
+                    reward: 0,
+                    reward_timelock: 0,
+                    timelock,
+                }
+            );
+            settled.push(true);
+        }
+        Ok(settled)
+    }
+
+    /// @dev Redeems several HTLCs of one token in one instruction, each as redeem
+    /// would. The sender, src_receiver, htlc, htlc_token_account,
+    /// sender_token_account, src_receiver_token_account and secret_record of
+    /// each entry are passed in the remaining accounts, in order, followed by the transfer hook
+    /// accounts of the mint if it has a hook. Unlike redeem, the token accounts
+    /// of the src_receivers must already exist.
+    /// @param entries the Id of each HTLC with its secret.
+    /// @param best_effort skip the entries that fail their checks instead of
+    ///                  failing the whole batch.
+    /// @return for each entry whether its HTLC was redeemed.
+    pub fn batch_redeem<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchRedeem<'info>>,
+        entries: Vec<([u8; 32], [u8; 32])>,
+        best_effort: bool,
+    ) -> Result<Vec<bool>> {
+        let user_signing = ctx.accounts.user_signing.to_account_info();
+        let token_contract = &ctx.accounts.token_contract;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let reward_token_account = ctx.accounts.reward_token_account.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let (accounts, hook_accounts) = batch_accounts(ctx.remaining_accounts, entries.len(), 7)?;
+        let mut settled = Vec::with_capacity(entries.len());
+        for (index, ((Id, secret), accounts)) in entries.into_iter().zip(accounts).enumerate() {
+            let result = (|| {
+                let [entry_accounts @ .., secret_record] = accounts else {
+                    return err!(HTLCError::BatchAccountsMismatch);
+                };
+                // The record is written first, so a best-effort entry is only
+                // reported as skipped before its tokens move.
+                let hashlock = hash_secret(&secret);
+                record_secret(
+                    secret_record,
+                    &user_signing,
+                    &system_program,
+                    hashlock,
+                    secret,
+                )?;
+                let payout_fee = redeem_entry(
+                    entry_accounts,
+                    Id,
+                    secret,
+                    hashlock,
+                    &user_signing,
+                    token_contract,
+                    &token_program,
+                    &reward_token_account,
+                    hook_accounts,
+                )?;
+
+                emit_event!(
+                    ctx,
+                    TokenRedeemed {
+                        Id,
+                        redeem_address: user_signing.key(),
+                        secret,
+                        hashlock,
+                        payout_fee,
+                    }
+                );
+                Ok(())
+            })();
+            settled.push(settle_entry(best_effort, index, result)?.is_some());
+        }
+        Ok(settled)
+    }
+
+    /// @dev Redeems several HTLCs of one token sharing a hashlock, e.g. the hops
+    /// of a split route, with one secret. The secret is hashed once, then each
+    /// HTLC is redeemed as redeem would. The sender, src_receiver, htlc,
+    /// htlc_token_account, sender_token_account and src_receiver_token_account
+    /// of each entry are passed in the remaining accounts, in order, followed by
+    /// the transfer hook accounts of the mint if it has a hook.
+    /// @param hashlock shared by the HTLCs.
+    /// @param secret sha256(secret) should equal the hashlock. If None, it is
+    ///               read from the SecretRecord of the hashlock, or else from
+    ///               the proof account, an HTLC already redeemed with it.
+    ///               The SecretRecord of the hashlock is written if missing,
+    ///               paid by the caller.
+    /// @param Ids of the HTLCs to redeem.
+    /// @param best_effort skip the entries that fail their checks instead of
+    ///                  failing the whole batch.
+    /// @return for each entry whether its HTLC was redeemed.
+    pub fn redeem_by_hashlock<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RedeemByHashlock<'info>>,
+        hashlock: [u8; 32],
+        secret: Option<[u8; 32]>,
+        Ids: Vec<[u8; 32]>,
+        best_effort: bool,
+    ) -> Result<Vec<bool>> {
+        let secret = match secret {
+            Some(secret) => Some(secret),
+            None => recorded_secret(&ctx.accounts.secret_record)?,
+        }
+        .or(ctx.accounts.proof.as_ref().map(|proof| proof.secret))
+        .ok_or(error!(HTLCError::SecretNotProvided))?;
+        require!(hash_secret(&secret) == hashlock, HTLCError::HashlockNoMatch);
+
+        let user_signing = ctx.accounts.user_signing.to_account_info();
+        record_secret(
+            &ctx.accounts.secret_record,
+            &user_signing,
+            &ctx.accounts.system_program,
+            hashlock,
+            secret,
+        )?;
+        let token_contract = &ctx.accounts.token_contract;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let reward_token_account = ctx.accounts.reward_token_account.to_account_info();
+        let (accounts, hook_accounts) = batch_accounts(ctx.remaining_accounts, Ids.len(), 6)?;
+        let mut settled = Vec::with_capacity(Ids.len());
+        for (index, (Id, accounts)) in Ids.into_iter().zip(accounts).enumerate() {
+            let result = (|| {
+                let payout_fee = redeem_entry(
+                    accounts,
+                    Id,
+                    secret,
+                    hashlock,
+                    &user_signing,
+                    token_contract,
+                    &token_program,
+                    &reward_token_account,
+                    hook_accounts,
+                )?;
+
+                emit_event!(
+                    ctx,
+                    TokenRedeemed {
+                        Id,
+                        redeem_address: user_signing.key(),
+                        secret,
+                        hashlock,
+                        payout_fee,
+                    }
+                );
+                Ok(())
+            })();
+            settled.push(settle_entry(best_effort, index, result)?.is_some());
+        }
+        Ok(settled)
+    }
+
+    /// @dev Refunds several HTLCs of one token past their timelock in one
+    /// instruction, each as refund would. The sender, htlc, htlc_token_account
+    /// and sender_token_account of each entry are passed in the remaining
+    /// accounts, in order, followed by the transfer hook accounts of the mint if
+    /// it has a hook.
+    /// @param Ids of the HTLCs to refund.
+    /// @param best_effort skip the entries that fail their checks instead of
+    ///                  failing the whole batch.
+    /// @return for each entry whether its HTLC was refunded.
+    pub fn batch_refund<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchRefund<'info>>,
+        Ids: Vec<[u8; 32]>,
+        best_effort: bool,
+    ) -> Result<Vec<bool>> {
+        let token_contract = &ctx.accounts.token_contract;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let (accounts, hook_accounts) = batch_accounts(ctx.remaining_accounts, Ids.len(), 4)?;
+        let mut settled = Vec::with_capacity(Ids.len());
+        for (index, (Id, accounts)) in Ids.into_iter().zip(accounts).enumerate() {
+            let [sender, htlc, htlc_token_account, sender_token_account] = accounts else {
+                return err!(HTLCError::BatchAccountsMismatch);
+            };
+            let result = (|| {
+                require_writable(sender)?;
+                let (mut htlc, htlc_bump) = load_htlc(htlc, sender.key, &Id)?;
+                require_keys_eq!(
+                    htlc.token_contract,
+                    token_contract.key(),
+                    HTLCError::NoToken
+                );
+                require_keys_eq!(
+                    htlc.token_program,
+                    token_program.key(),
+                    HTLCError::WrongTokenProgram
+                );
+                require!(current_time()? > htlc.timelock, HTLCError::NotPastTimeLock);
+                let mut htlc_token_account =
+                    load_htlc_token_account(htlc_token_account, sender.key, &Id)?;
+                load_owner_token_account(sender_token_account, sender.key, &token_contract.key())?;
+
+                let payout_fee = refund_htlc(
+                    &mut htlc,
+                    Id,
+                    htlc_bump,
+                    sender,
+                    &mut htlc_token_account,
+                    token_contract,
+                    &token_program,
+                    sender_token_account,
+                    hook_accounts,
+                )?;
+                htlc.exit(&ID)?;
+
+                emit_event!(ctx, TokenRefunded { Id, payout_fee });
+                Ok(())
+            })();
+            settled.push(settle_entry(best_effort, index, result)?.is_some());
+        }
+        Ok(settled)
+    }
+
+    /// @dev Sender reserves the next program-derived Id. The Id is derived from
+    /// the sender and its nonce stored in the SenderState PDA, which is then
+    /// incremented. Put this instruction before commit / lock in the same
+    /// transaction and pass the Id predicted with derive_htlc_id.
+    /// @return the reserved Id.
+    pub fn reserve_id(ctx: Context<ReserveId>) -> Result<[u8; 32]> {
+        let sender_state = &mut ctx.accounts.sender_state;
+        let nonce = sender_state.nonce;
+        let Id = derive_htlc_id(&ctx.accounts.sender.key(), nonce);
+        sender_state.nonce = nonce
+            .checked_add(1)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+
+        emit_event!(
+            ctx,
+            IdReserved {
+                Id,
+                sender: ctx.accounts.sender.key(),
+                nonce,
+            }
+        );
+
+        Ok(Id)
+    }
+
+    /// @dev Called by the sender to move a pending HTLC created with the legacy
+    /// [Id] seeds to its [HTLC_SEED, sender, Id] address. The tokens are moved
+    /// to the new HTLC token account and the legacy accounts are closed.
+    ///
+    /// @param Id of the legacy HTLC.
+    pub fn migrate(ctx: Context<Migrate>, Id: [u8; 32]) -> Result<[u8; 32]> {
+        let legacy = LegacyHTLC::load(&ctx.accounts.legacy_htlc)?;
+        require!(
+            legacy.sender == ctx.accounts.sender.key(),
+            HTLCError::NotSender
+        );
+        require!(legacy.claimed == 1, HTLCError::AlreadyClaimed);
+        require!(
+            legacy.token_contract == ctx.accounts.token_contract.key(),
+            HTLCError::NoToken
+        );
+
+        let htlc = &mut ctx.accounts.htlc;
+        htlc.dst_address = legacy.dst_address;
+        htlc.dst_chain = legacy.dst_chain;
+        htlc.dst_asset = legacy.dst_asset;
+        htlc.src_asset = legacy.src_asset;
+        htlc.sender = legacy.sender;
+        htlc.src_receiver = legacy.src_receiver;
+        htlc.hashlock = legacy.hashlock;
+        htlc.secret = legacy.secret;
+        htlc.amount = legacy.amount;
+        htlc.timelock = legacy.timelock;
+        htlc.reward = legacy.reward;
+        htlc.reward_timelock = legacy.reward_timelock;
+        htlc.status = if legacy.hashlock == [0u8; 32] {
+            HtlcStatus::Committed
+        } else {
+            HtlcStatus::Locked
+        };
+        htlc.token_contract = legacy.token_contract;
+        htlc.token_wallet = ctx.accounts.htlc_token_account.key();
+        htlc.token_program = ctx.accounts.token_program.key();
+
+        let legacy_bump = ctx.bumps.legacy_htlc;
+        let bump_vector = legacy_bump.to_le_bytes();
+        let inner = vec![Id.as_ref(), bump_vector.as_ref()];
+        let outer = vec![inner.as_slice()];
+        let transfer_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.legacy_htlc_token_account.to_account_info(),
+                mint: ctx.accounts.token_contract.to_account_info(),
+                to: ctx.accounts.htlc_token_account.to_account_info(),
+                authority: ctx.accounts.legacy_htlc.to_account_info(),
+            },
+            outer.as_slice(),
+        );
+        token_interface::transfer_checked(
+            transfer_context,
+            ctx.accounts.legacy_htlc_token_account.amount,
+            ctx.accounts.token_contract.decimals,
+        )?;
+
+        let close_context = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            ctx.accounts.sender_token_account.to_account_info(),
-            ctx.accounts.htlc.amount + ctx.accounts.htlc.reward,
+            CloseAccount {
+                account: ctx.accounts.legacy_htlc_token_account.to_account_info(),
+                destination: ctx.accounts.sender.to_account_info(),
+                authority: ctx.accounts.legacy_htlc.to_account_info(),
+            },
+            outer.as_slice(),
+        );
+        token_interface::close_account(close_context)?;
+        close_legacy(
+            &ctx.accounts.legacy_htlc.to_account_info(),
+            &ctx.accounts.sender.to_account_info(),
         )?;
 
+        Ok(Id)
+    }
+
+    /// @dev Called by the sender after the HTLC was redeemed or refunded to close
+    /// the HTLC account and get back the rent paid for it.
+    /// The final state, including the revealed secret, is kept in the TokenClosed event.
+    ///
+    /// @param Id of the HTLC to close.
+    pub fn close(ctx: Context<Close>, Id: [u8; 32]) -> Result<bool> {
+        let htlc = &ctx.accounts.htlc;
+        htlc.status.ensure_settled()?;
+
+        emit_event!(
+            ctx,
+            TokenClosed {
+                Id,
+                status: htlc.status,
+                hashlock: htlc.hashlock,
+                secret: htlc.secret,
+            }
+        );
+
         Ok(true)
     }
 
@@ -599,7 +2125,12 @@ pub mod anchor_htlc {
         msg!("reward_timelock: {:?}", htlc.reward_timelock);
         msg!("token_contract: {:?}", htlc.token_contract);
         msg!("token_wallet: {:?}", htlc.token_wallet);
-        msg!("claimed: {:?}", htlc.claimed);
+        msg!("token_program: {:?}", htlc.token_program);
+        msg!("fee_payer: {:?}", htlc.fee_payer);
+        msg!("status: {:?}", htlc.status);
+        msg!("hop_chains: {:?}", htlc.hop_chains);
+        msg!("hop_assets: {:?}", htlc.hop_assets);
+        msg!("hop_addresses: {:?}", htlc.hop_addresses);
 
         Ok(HTLC {
             dst_address: htlc.dst_address.clone(),
@@ -616,14 +2147,221 @@ pub mod anchor_htlc {
             reward_timelock: htlc.reward_timelock,
             token_contract: htlc.token_contract,
             token_wallet: htlc.token_wallet,
-            claimed: htlc.claimed,
+            token_program: htlc.token_program,
+            fee_payer: htlc.fee_payer,
+            status: htlc.status,
+            hop_chains: htlc.hop_chains.clone(),
+            hop_assets: htlc.hop_assets.clone(),
+            hop_addresses: htlc.hop_addresses.clone(),
+            evm_authorizer: htlc.evm_authorizer,
+            authorized_locker: htlc.authorized_locker,
+        })
+    }
+
+    /// @dev Retrieves the secret of a hashlock, recorded by its first redeem.
+    /// @param hashlock of the HTLCs.
+    /// @return the SecretRecord of the hashlock.
+    pub fn getSecret(ctx: Context<GetSecret>, hashlock: [u8; 32]) -> Result<SecretRecord> {
+        let record = &ctx.accounts.secret_record;
+        Ok(SecretRecord {
+            hashlock: record.hashlock,
+            secret: record.secret,
+            revealed_at: record.revealed_at,
         })
     }
 }
 
 #[account]
-#[derive(Default)]
+#[derive(Default, InitSpace)]
 pub struct HTLC {
+    #[max_len(MAX_ADDRESS_LEN)]
+    pub dst_address: String,
+    #[max_len(MAX_CHAIN_LEN)]
+    pub dst_chain: String,
+    #[max_len(MAX_ASSET_LEN)]
+    pub dst_asset: String,
+    #[max_len(MAX_ASSET_LEN)]
+    pub src_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    pub amount: u64,
+    pub timelock: u64,
+    pub reward: u64,
+    pub reward_timelock: u64,
+    pub token_contract: Pubkey,
+    pub token_wallet: Pubkey,
+    pub token_program: Pubkey,
+    pub fee_payer: FeePayer,
+    pub status: HtlcStatus,
+    /// The hops are not counted in INIT_SPACE, see [`HTLC::space`].
+    #[max_len(0, MAX_CHAIN_LEN)]
+    pub hop_chains: Vec<String>,
+    #[max_len(0, MAX_ASSET_LEN)]
+    pub hop_assets: Vec<String>,
+    #[max_len(0, MAX_ADDRESS_LEN)]
+    pub hop_addresses: Vec<String>,
+    /// Ethereum address that may add the hashlock with add_lock_sig_evm, zero if none.
+    pub evm_authorizer: [u8; 20],
+    /// Key that may call add_lock besides the sender, set at commit.
+    pub authorized_locker: Option<Pubkey>,
+}
+
+impl HTLC {
+    /// Space of one hop of the route, its chain, asset and address at their max lengths.
+    pub const HOP_SPACE: usize = 4 + MAX_CHAIN_LEN + 4 + MAX_ASSET_LEN + 4 + MAX_ADDRESS_LEN;
+
+    /// Space of an HTLC routed through `hops` hops, so that HTLCs without a
+    /// route do not pay rent for MAX_HOPS empty hops.
+    pub fn space(hops: usize) -> usize {
+        Self::INIT_SPACE + hops * Self::HOP_SPACE
+    }
+}
+
+/// @dev Lifecycle of an HTLC.
+///
+///  Committed -> Locked    add_lock / add_lock_sig(_legacy) / add_lock_sig_evm
+///  Locked    -> Redeemed  redeem
+///  Committed -> Refunded  refund
+///  Locked    -> Refunded  refund
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace,
+)]
+pub enum HtlcStatus {
+    /// Funds are committed and the hashlock is not set yet.
+    #[default]
+    Committed,
+    /// The hashlock is set and the funds can be redeemed.
+    Locked,
+    /// The funds were sent to the src_receiver.
+    Redeemed,
+    /// The funds were returned to the sender.
+    Refunded,
+}
+
+impl HtlcStatus {
+    /// Fails unless the HTLC is still waiting to be redeemed or refunded.
+    pub fn ensure_pending(self) -> Result<()> {
+        match self {
+            HtlcStatus::Committed | HtlcStatus::Locked => Ok(()),
+            HtlcStatus::Redeemed => err!(HTLCError::AlreadyRedeemed),
+            HtlcStatus::Refunded => err!(HTLCError::AlreadyRefunded),
+        }
+    }
+
+    /// Fails unless the HTLC was already redeemed or refunded.
+    pub fn ensure_settled(self) -> Result<()> {
+        match self {
+            HtlcStatus::Redeemed | HtlcStatus::Refunded => Ok(()),
+            HtlcStatus::Committed | HtlcStatus::Locked => err!(HTLCError::NotSettled),
+        }
+    }
+
+    /// Returns `next` if the HTLC is allowed to move there from the current status.
+    pub fn transition(self, next: HtlcStatus) -> Result<HtlcStatus> {
+        self.ensure_pending()?;
+        match (self, next) {
+            (HtlcStatus::Committed, HtlcStatus::Locked)
+            | (HtlcStatus::Locked, HtlcStatus::Redeemed)
+            | (_, HtlcStatus::Refunded) => Ok(next),
+            (HtlcStatus::Locked, HtlcStatus::Locked) => err!(HTLCError::HashlockAlreadySet),
+            (HtlcStatus::Committed, HtlcStatus::Redeemed) => err!(HTLCError::HashlockNotSet),
+            _ => err!(HTLCError::InvalidStatusTransition),
+        }
+    }
+}
+
+/// @dev Who bears the transfer fee of the payout for Token-2022 transfer-fee mints.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace,
+)]
+pub enum FeePayer {
+    /// The HTLC pays out what it holds and the destination receives it minus the fee.
+    #[default]
+    Receiver,
+    /// The sender deposits the fee on top, so the destination receives the full amount.
+    Sender,
+}
+
+/// @dev Terms of one HTLC of batch_lock, the arguments lock takes.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LockEntry {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub timelock: u64,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub src_asset: String,
+    pub src_receiver: Pubkey,
+    pub amount: u64,
+    pub fee_payer: FeePayer,
+}
+
+/// @dev Nonce of a sender, used to derive its HTLC Ids on chain.
+#[account]
+#[derive(Default, InitSpace)]
+pub struct SenderState {
+    pub nonce: u64,
+}
+
+/// @dev The secret of a hashlock, written by the first redeem that reveals it so
+/// that watchers and other redemptions find it at one address.
+#[account]
+#[derive(Default, InitSpace)]
+pub struct SecretRecord {
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+    /// Time of the redeem that revealed the secret.
+    pub revealed_at: u64,
+}
+
+/// @dev Lets a delegate key sign add_lock and lock_reward for the sender until
+/// the expiry, within a budget of token_contract approved to the account.
+#[account]
+#[derive(Default, InitSpace)]
+pub struct Session {
+    pub sender: Pubkey,
+    pub delegate: Pubkey,
+    pub token_contract: Pubkey,
+    pub expiry: u64,
+    /// Tokens the delegate may spend from the sender token account.
+    pub max_amount: u64,
+    pub spent: u64,
+    /// Bitmask of the SESSION_* instructions the delegate may call.
+    pub permissions: u8,
+    /// Number of instructions the delegate called through the session.
+    pub uses: u64,
+    pub bump: u8,
+}
+
+impl Session {
+    /// Checks the session allows `permission` now and records a use spending `amount`.
+    pub fn use_for(&mut self, permission: u8, amount: u64) -> Result<()> {
+        require!(current_time()? <= self.expiry, HTLCError::SessionExpired);
+        require!(
+            self.permissions & permission != 0,
+            HTLCError::SessionNotPermitted
+        );
+        let spent = self
+            .spent
+            .checked_add(amount)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        require!(spent <= self.max_amount, HTLCError::SessionBudgetExceeded);
+        self.spent = spent;
+        self.uses = self
+            .uses
+            .checked_add(1)
+            .ok_or(error!(HTLCError::ArithmeticOverflow))?;
+        Ok(())
+    }
+}
+
+/// @dev Layout of the HTLC accounts created with the legacy [Id] seeds,
+/// before they were namespaced by sender. Only read by migrate.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyHTLC {
     pub dst_address: String,
     pub dst_chain: String,
     pub dst_asset: String,
@@ -640,8 +2378,117 @@ pub struct HTLC {
     pub token_wallet: Pubkey,
     pub claimed: u8,
 }
+
+impl LegacyHTLC {
+    /// Reads a legacy HTLC account after checking its HTLC discriminator.
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= HTLC::DISCRIMINATOR.len()
+                && &data[..HTLC::DISCRIMINATOR.len()] == HTLC::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        LegacyHTLC::deserialize(&mut &data[HTLC::DISCRIMINATOR.len()..])
+            .map_err(|_| error!(ErrorCode::AccountDidNotDeserialize))
+    }
+}
+
+/// @dev Emitted when funds are committed to a new PHTLC.
+#[event]
+pub struct TokenCommitted {
+    pub Id: [u8; 32],
+    pub hop_chains: Vec<String>,
+    pub hop_assets: Vec<String>,
+    pub hop_addresses: Vec<String>,
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub src_asset: String,
+    pub amount: u64,
+    /// Transfer fee withheld from the deposit, `amount` is what the HTLC received.
+    pub lock_fee: u64,
+    pub timelock: u64,
+}
+
+/// @dev Emitted when funds are locked in a new HTLC.
+#[event]
+pub struct TokenLocked {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub dst_chain: String,
+    pub dst_address: String,
+    pub dst_asset: String,
+    pub sender: Pubkey,
+    pub src_receiver: Pubkey,
+    pub src_asset: String,
+    pub amount: u64,
+    /// Transfer fee withheld from the deposit, `amount` is what the HTLC received.
+    pub lock_fee: u64,
+    pub reward: u64,
+    pub reward_timelock: u64,
+    pub timelock: u64,
+}
+
+/// @dev Emitted when a reward is added to an existing HTLC.
+#[event]
+pub struct TokenRewardLocked {
+    pub Id: [u8; 32],
+    pub reward: u64,
+    /// Transfer fee withheld from the deposit, `reward` is what the HTLC received.
+    pub lock_fee: u64,
+    pub reward_timelock: u64,
+}
+
+/// @dev Emitted when a hashlock is added to a committed HTLC.
+#[event]
+pub struct TokenLockAdded {
+    pub Id: [u8; 32],
+    pub hashlock: [u8; 32],
+    pub timelock: u64,
+}
+
+/// @dev Emitted when funds are redeemed from an HTLC using the correct secret.
+#[event]
+pub struct TokenRedeemed {
+    pub Id: [u8; 32],
+    pub redeem_address: Pubkey,
+    pub secret: [u8; 32],
+    pub hashlock: [u8; 32],
+    /// Transfer fee withheld from the amount and reward paid out.
+    pub payout_fee: u64,
+}
+
+/// @dev Emitted when funds are refunded from an HTLC after the timelock expires.
+#[event]
+pub struct TokenRefunded {
+    pub Id: [u8; 32],
+    /// Transfer fee withheld from the amount and reward paid back.
+    pub payout_fee: u64,
+}
+
+/// @dev Emitted when a program-derived Id is reserved for a sender.
+#[event]
+pub struct IdReserved {
+    pub Id: [u8; 32],
+    pub sender: Pubkey,
+    pub nonce: u64,
+}
+
+/// @dev Emitted when a settled HTLC account is closed. Keeps the final state
+/// of the HTLC so the secret can still be found after the account is gone.
+#[event]
+pub struct TokenClosed {
+    pub Id: [u8; 32],
+    pub status: HtlcStatus,
+    pub hashlock: [u8; 32],
+    pub secret: [u8; 32],
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
-#[instruction(Id: [u8;32])]
+#[instruction(Id: [u8;32], hopChains: Vec<String>)]
 pub struct Commit<'info> {
     #[account(mut)]
     pub sender: Signer<'info>,
@@ -649,8 +2496,10 @@ pub struct Commit<'info> {
     #[account(
         init,
         payer = sender,
-        space = size_of::<HTLC>() + 28,
+        space = 8 + HTLC::space(hopChains.len()),
         seeds = [
+            HTLC_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
@@ -660,27 +2509,30 @@ pub struct Commit<'info> {
         init,
         payer = sender,
         seeds = [
-            b"htlc_token_account".as_ref(),
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         token::mint=token_contract,
         token::authority=htlc,
+        token::token_program=token_program,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_contract: Account<'info, Mint>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,
     )]
-    pub sender_token_account: Account<'info, TokenAccount>,
+    pub sender_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8; 32])]
 pub struct Lock<'info> {
@@ -690,9 +2542,10 @@ pub struct Lock<'info> {
     #[account(
         init,
         payer = sender,
-        space = size_of::<HTLC>() + 28,
-        // space = 256,
+        space = 8 + HTLC::space(0),
         seeds = [
+            HTLC_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
@@ -702,59 +2555,89 @@ pub struct Lock<'info> {
         init,
         payer = sender,
         seeds = [
-            b"htlc_token_account".as_ref(),
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         token::mint=token_contract,
         token::authority=htlc,
+        token::token_program=token_program,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_contract: Account<'info, Mint>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
     )]
-    pub sender_token_account: Box<Account<'info, TokenAccount>>,
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8; 32])]
 pub struct LockReward<'info> {
+    /// The sender, or the delegate of the session.
     #[account(mut)]
-    sender: Signer<'info>,
+    authority: Signer<'info>,
+    ///CHECK: The sender
+    sender: UncheckedAccount<'info>,
     #[account(
     mut,
     seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump,
-    constraint = htlc.claimed == 1 @ HTLCError::AlreadyClaimed,
     has_one = sender @ HTLCError::UnauthorizedAccess,
+    has_one = token_contract @HTLCError::NoToken,
+    has_one = token_program @HTLCError::WrongTokenProgram,
+    constraint = authority.key() == sender.key() || session.is_some() @ HTLCError::UnauthorizedAccess,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    /// Session of the sender naming the authority as delegate, if it signs through one.
+    #[account(mut,
+    seeds = [
+        SESSION_SEED,
+        sender.key().as_ref(),
+        authority.key().as_ref()
+    ],
+    bump = session.bump,
+    has_one = token_contract @HTLCError::NoToken,
+    )]
+    pub session: Option<Account<'info, Session>>,
+    #[account(
+        mut,
+        seeds = [
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
-
-    pub token_contract: Account<'info, Mint>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
     )]
-    pub sender_token_account: Box<Account<'info, TokenAccount>>,
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8;32], htlc_bump: u8)]
 pub struct Redeem<'info> {
@@ -765,57 +2648,67 @@ pub struct Redeem<'info> {
     sender: UncheckedAccount<'info>,
     ///CHECK: The reciever
     pub src_receiver: UncheckedAccount<'info>,
-    token_contract: Account<'info, Mint>,
+    #[account(mut)]
+    token_contract: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         seeds = [
+            HTLC_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
         has_one = sender @HTLCError::NotSender,
         has_one = src_receiver @HTLCError::NotReciever,
         has_one = token_contract @HTLCError::NoToken,
-        constraint = htlc.claimed == 1 @ HTLCError::AlreadyClaimed,
+        has_one = token_program @HTLCError::WrongTokenProgram,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    ///CHECK: The SecretRecord of sha256(secret), checked and created by the program.
+    #[account(mut)]
+    pub secret_record: UncheckedAccount<'info>,
     #[account(
         mut,
         seeds = [
-            b"htlc_token_account".as_ref(),
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
     )]
-    pub sender_token_account: Box<Account<'info, TokenAccount>>,
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init_if_needed,
         payer = user_signing,
         associated_token::mint = token_contract,
         associated_token::authority = src_receiver,
+        associated_token::token_program = token_program,
     )]
-    pub src_receiver_token_account: Box<Account<'info, TokenAccount>>,
+    pub src_receiver_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
         payer = user_signing,
         associated_token::mint = token_contract,
         associated_token::authority = user_signing,
+        associated_token::token_program = token_program,
     )]
-    pub reward_token_account: Box<Account<'info, TokenAccount>>,
+    pub reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
     associated_token_program: Program<'info, AssociatedToken>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8;32], htlc_bump: u8)]
 pub struct Refund<'info> {
@@ -824,74 +2717,197 @@ pub struct Refund<'info> {
 
     #[account(mut,
     seeds = [
-        //b"htlc",
+        HTLC_SEED,
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump = htlc_bump,
     has_one = sender @HTLCError::NotSender,
     has_one = token_contract @HTLCError::NoToken,
-    constraint = htlc.claimed == 1 @ HTLCError::AlreadyClaimed,
-    constraint = Clock::get().unwrap().unix_timestamp > htlc.timelock.try_into().unwrap() @ HTLCError::NotPastTimeLock,
+    has_one = token_program @HTLCError::WrongTokenProgram,
+    constraint = current_time()? > htlc.timelock @ HTLCError::NotPastTimeLock,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
     #[account(
         mut,
         seeds = [
-            b"htlc_token_account".as_ref(),
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
-    pub htlc_token_account: Box<Account<'info, TokenAccount>>,
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     ///CHECK: The sender
     #[account(mut)]
     sender: UncheckedAccount<'info>,
-    token_contract: Account<'info, Mint>,
+    #[account(mut)]
+    token_contract: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
         constraint=htlc.sender.key() == sender_token_account.owner @HTLCError::NotSender,
         constraint=sender_token_account.mint == token_contract.key() @HTLCError::NoToken,)]
-    pub sender_token_account: Account<'info, TokenAccount>,
+    pub sender_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+    rent: Sysvar<'info, Rent>,
+}
+
+/// Remaining accounts: the htlc and htlc_token_account of each entry, then the
+/// transfer hook accounts.
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct BatchLock<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
+        constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
+    )]
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Remaining accounts: the sender, src_receiver, htlc, htlc_token_account,
+/// sender_token_account, src_receiver_token_account and secret_record of each
+/// entry, then the transfer hook accounts.
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct BatchRedeem<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    #[account(mut)]
+    token_contract: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = user_signing,
+        associated_token::mint = token_contract,
+        associated_token::authority = user_signing,
+        associated_token::token_program = token_program,
+    )]
+    pub reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+}
+
+/// Remaining accounts: the sender, src_receiver, htlc, htlc_token_account,
+/// sender_token_account and src_receiver_token_account of each entry, then the
+/// transfer hook accounts.
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(hashlock: [u8; 32])]
+pub struct RedeemByHashlock<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    #[account(mut)]
+    token_contract: InterfaceAccount<'info, Mint>,
+    ///CHECK: The SecretRecord of the hashlock, read or created by the program.
+    #[account(mut,
+    seeds = [
+        SECRET_RECORD_SEED,
+        hashlock.as_ref()
+    ],
+    bump,
+    )]
+    pub secret_record: UncheckedAccount<'info>,
+    /// An HTLC already redeemed, whose secret is used when none is given.
+    pub proof: Option<Box<Account<'info, HTLC>>>,
+
+    #[account(
+        init_if_needed,
+        payer = user_signing,
+        associated_token::mint = token_contract,
+        associated_token::authority = user_signing,
+        associated_token::token_program = token_program,
+    )]
+    pub reward_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    rent: Sysvar<'info, Rent>,
+}
+
+/// Remaining accounts: the sender, htlc, htlc_token_account and
+/// sender_token_account of each entry, then the transfer hook accounts.
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct BatchRefund<'info> {
+    #[account(mut)]
+    user_signing: Signer<'info>,
+    #[account(mut)]
+    token_contract: InterfaceAccount<'info, Mint>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8;32])]
 pub struct AddLock<'info> {
-    #[account(mut)]
-    sender: Signer<'info>,
+    /// The sender, the authorized_locker of the HTLC, or the delegate of the session.
+    locker: Signer<'info>,
+    ///CHECK: The sender
+    sender: UncheckedAccount<'info>,
     #[account(mut,
     seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump,
-    constraint = htlc.claimed == 1 @ HTLCError::AlreadyClaimed,
     constraint = htlc.sender == sender.key() @ HTLCError::UnauthorizedAccess,
-    constraint = htlc.hashlock == [0u8;32] @ HTLCError::HashlockAlreadySet,
+    constraint = locker.key() == htlc.sender
+        || htlc.authorized_locker == Some(locker.key())
+        || session.is_some() @ HTLCError::UnauthorizedAccess,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
+    /// Session of the sender naming the locker as delegate, if it signs through one.
+    #[account(mut,
+    seeds = [
+        SESSION_SEED,
+        sender.key().as_ref(),
+        locker.key().as_ref()
+    ],
+    bump = session.bump,
+    )]
+    pub session: Option<Account<'info, Session>>,
 
     system_program: Program<'info, System>,
     rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
 #[instruction(Id: [u8;32])]
 pub struct AddLockSig<'info> {
     #[account(mut)]
     payer: Signer<'info>,
+    ///CHECK: The sender
+    sender: UncheckedAccount<'info>,
     #[account(mut,
     seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
         Id.as_ref()
     ],
     bump,
-    constraint = htlc.claimed == 1 @ HTLCError::AlreadyClaimed,
-    constraint = htlc.hashlock == [0u8;32] @ HTLCError::HashlockAlreadySet,
+    has_one = sender @HTLCError::NotSender,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
 
@@ -905,18 +2921,244 @@ pub struct AddLockSig<'info> {
     rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct SetAuthorizedLocker<'info> {
+    sender: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    has_one = sender @HTLCError::NotSender,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct CreateSession<'info> {
+    #[account(mut)]
+    sender: Signer<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = 8 + Session::INIT_SPACE,
+        seeds = [
+            SESSION_SEED,
+            sender.key().as_ref(),
+            delegate.as_ref()
+        ],
+        bump,
+    )]
+    pub session: Account<'info, Session>,
+
+    pub token_contract: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
+        constraint=sender_token_account.mint == token_contract.key() @ HTLCError::NoToken,
+        constraint=sender_token_account.delegate.is_none()
+            || sender_token_account.delegated_amount == 0 @ HTLCError::TokenAccountDelegated,
+    )]
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct RevokeSession<'info> {
+    #[account(mut)]
+    sender: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        SESSION_SEED,
+        sender.key().as_ref(),
+        delegate.as_ref()
+    ],
+    bump = session.bump,
+    has_one = sender @HTLCError::NotSender,
+    close = sender,
+    )]
+    pub session: Account<'info, Session>,
+
+    #[account(
+        mut,
+        constraint=sender_token_account.owner == sender.key() @HTLCError::NotSender,
+        constraint=sender_token_account.mint == session.token_contract @ HTLCError::NoToken,
+    )]
+    pub sender_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct SetEvmAuthorizer<'info> {
+    sender: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    has_one = sender @HTLCError::NotSender,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct AddLockSigEvm<'info> {
+    payer: Signer<'info>,
+    ///CHECK: The sender
+    sender: UncheckedAccount<'info>,
+    #[account(mut,
+    seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    has_one = sender @HTLCError::NotSender,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct ReserveId<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + SenderState::INIT_SPACE,
+        seeds = [
+            SENDER_STATE_SEED,
+            sender.key().as_ref()
+        ],
+        bump,
+    )]
+    pub sender_state: Account<'info, SenderState>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct Migrate<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    ///CHECK: The legacy HTLC, read by LegacyHTLC::load
+    #[account(
+        mut,
+        seeds = [
+            Id.as_ref()
+        ],
+        bump,
+        owner = crate::ID,
+    )]
+    pub legacy_htlc: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            HTLC_TOKEN_ACCOUNT_SEED,
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub legacy_htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = sender,
+        space = 8 + HTLC::space(0),
+        seeds = [
+            HTLC_SEED,
+            sender.key().as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+    #[account(
+        init,
+        payer = sender,
+        seeds = [
+            HTLC_TOKEN_ACCOUNT_SEED,
+            sender.key().as_ref(),
+            Id.as_ref()
+        ],
+        bump,
+        token::mint=token_contract,
+        token::authority=htlc,
+        token::token_program=token_program,
+    )]
+    pub htlc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_contract: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(Id: [u8; 32])]
+pub struct Close<'info> {
+    #[account(mut)]
+    sender: Signer<'info>,
+    #[account(mut,
+    seeds = [
+        HTLC_SEED,
+        sender.key().as_ref(),
+        Id.as_ref()
+    ],
+    bump,
+    has_one = sender @HTLCError::NotSender,
+    close = sender,
+    )]
+    pub htlc: Box<Account<'info, HTLC>>,
+}
+
 #[derive(Accounts)]
 #[instruction(Id: [u8;32])]
 pub struct GetDetails<'info> {
+    ///CHECK: The sender
+    sender: UncheckedAccount<'info>,
     #[account(
         seeds = [
+            HTLC_SEED,
+            sender.key().as_ref(),
             Id.as_ref()
         ],
         bump,
     )]
     pub htlc: Box<Account<'info, HTLC>>,
 }
+
+#[derive(Accounts)]
+#[instruction(hashlock: [u8; 32])]
+pub struct GetSecret<'info> {
+    #[account(
+        seeds = [
+            SECRET_RECORD_SEED,
+            hashlock.as_ref()
+        ],
+        bump,
+    )]
+    pub secret_record: Box<Account<'info, SecretRecord>>,
+}
 #[error_code]
+#[derive(num_enum::TryFromPrimitive)]
 pub enum HTLCError {
     #[msg("Invalid TimeLock.")]
     InvalidTimeLock,
@@ -948,4 +3190,46 @@ pub enum HTLCError {
     SigVerificationFailed,
     #[msg("Reward Already Exists.")]
     RewardAlreadyExists,
+    #[msg("Funds Are Already Redeemed.")]
+    AlreadyRedeemed,
+    #[msg("Funds Are Already Refunded.")]
+    AlreadyRefunded,
+    #[msg("Invalid HTLC Status Transition.")]
+    InvalidStatusTransition,
+    #[msg("String Exceeds Max Length.")]
+    StringTooLong,
+    #[msg("Hop Route Lengths Do Not Match.")]
+    HopLengthMismatch,
+    #[msg("Too Many Hops.")]
+    TooManyHops,
+    #[msg("HTLC Is Not Redeemed Or Refunded.")]
+    NotSettled,
+    #[msg("Wrong Token Program.")]
+    WrongTokenProgram,
+    #[msg("Invalid Transfer Fee.")]
+    InvalidTransferFee,
+    #[msg("Arithmetic Overflow.")]
+    ArithmeticOverflow,
+    #[msg("Clock Unavailable.")]
+    ClockUnavailable,
+    #[msg("No EVM Authorizer.")]
+    NoEvmAuthorizer,
+    #[msg("Signature Expired.")]
+    SignatureExpired,
+    #[msg("Legacy Lock Signature Disabled.")]
+    LegacyLockSigDisabled,
+    #[msg("Session Expired.")]
+    SessionExpired,
+    #[msg("Instruction Not Permitted By Session.")]
+    SessionNotPermitted,
+    #[msg("Session Budget Exceeded.")]
+    SessionBudgetExceeded,
+    #[msg("Batch Accounts Do Not Match The Entries.")]
+    BatchAccountsMismatch,
+    #[msg("HTLC Already Exists.")]
+    HtlcAlreadyExists,
+    #[msg("Secret Or Proof Not Provided.")]
+    SecretNotProvided,
+    #[msg("Token Account Already Delegated.")]
+    TokenAccountDelegated,
 }
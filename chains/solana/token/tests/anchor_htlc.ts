@@ -1,8 +1,10 @@
+import assert from "assert";
 import * as anchor from "@coral-xyz/anchor";
 import { randomBytes, createHash } from "crypto";
 import * as spl from '@solana/spl-token';
 import * as ed from '@noble/ed25519';
 import { AnchorHtlc } from '../target/types/anchor_htlc';
+import { MockHook } from '../target/types/mock_hook';
 
 interface PDAParameters {
     htlcTokenAccount: anchor.web3.PublicKey;
@@ -36,13 +38,14 @@ describe("HTLC", () => {
 
     const getPdaParams = async (
         Id: Buffer,
+        sender: anchor.web3.PublicKey = wallet.publicKey,
     ): Promise<PDAParameters> => {
         let [htlc, htlcBump] = await anchor.web3.PublicKey.findProgramAddressSync(
-            [Id],
+            [Buffer.from("htlc"), sender.toBuffer(), Id],
             program.programId
         );
         let [htlcTokenAccount, htlcTokenbump] = await anchor.web3.PublicKey.findProgramAddressSync(
-            [Buffer.from("htlc_token_account"), Id],
+            [Buffer.from("htlc_token_account"), sender.toBuffer(), Id],
             program.programId
         );
 
@@ -53,7 +56,18 @@ describe("HTLC", () => {
         };
     };
 
-    const createMint = async (): Promise<anchor.web3.PublicKey> => {
+    const getSecretRecord = (secret: Buffer): anchor.web3.PublicKey => {
+        const hashlock = createHash("sha256").update(secret).digest();
+        const [secretRecord] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("secret_record"), hashlock],
+            program.programId
+        );
+        return secretRecord;
+    };
+
+    const createMint = async (
+        programId: anchor.web3.PublicKey = spl.TOKEN_PROGRAM_ID
+    ): Promise<anchor.web3.PublicKey> => {
         const tokenMint = new anchor.web3.Keypair();
         const lamportsForMint = await provider.connection.getMinimumBalanceForRentExemption(spl.MintLayout.span);
         let tx = new anchor.web3.Transaction();
@@ -61,7 +75,7 @@ describe("HTLC", () => {
         // Allocate mint
         tx.add(
             anchor.web3.SystemProgram.createAccount({
-                programId: spl.TOKEN_PROGRAM_ID,
+                programId,
                 space: spl.MintLayout.span,
                 fromPubkey: provider.wallet.publicKey,
                 newAccountPubkey: tokenMint.publicKey,
@@ -70,7 +84,7 @@ describe("HTLC", () => {
         );
         // Allocate wallet account
         tx.add(
-            spl.createInitializeMintInstruction(tokenMint.publicKey, 6, provider.wallet.publicKey, provider.wallet.publicKey)
+            spl.createInitializeMintInstruction(tokenMint.publicKey, 6, provider.wallet.publicKey, provider.wallet.publicKey, programId)
         );
         const signature = await provider.sendAndConfirm(tx, [tokenMint]);
 
@@ -117,22 +131,24 @@ describe("HTLC", () => {
 
     const mintTokensForUser = async (
         user: anchor.web3.PublicKey,
-        mint: anchor.web3.PublicKey
+        mint: anchor.web3.PublicKey,
+        programId: anchor.web3.PublicKey = spl.TOKEN_PROGRAM_ID
     ): Promise<anchor.web3.PublicKey> => {
         let userAssociatedTokenAccount: anchor.web3.PublicKey | undefined = undefined;
 
         // Create a token account for the wallet and mint some tokens
-        userAssociatedTokenAccount = await spl.getAssociatedTokenAddress(mint, user);
+        userAssociatedTokenAccount = await spl.getAssociatedTokenAddress(mint, user, false, programId);
         const txFundTokenAccount = new anchor.web3.Transaction();
         txFundTokenAccount.add(
             spl.createAssociatedTokenAccountInstruction(
                 provider.wallet.publicKey,
                 userAssociatedTokenAccount,
                 user,
-                mint
+                mint,
+                programId
             )
         );
-        txFundTokenAccount.add(spl.createMintToInstruction(mint, userAssociatedTokenAccount, provider.wallet.publicKey, 1337000000));
+        txFundTokenAccount.add(spl.createMintToInstruction(mint, userAssociatedTokenAccount, provider.wallet.publicKey, 1337000000, [], programId));
         const txFundTokenSig = await provider.sendAndConfirm(txFundTokenAccount);
         // console.log(`[${userAssociatedTokenAccount.toBase58()}] New associated account for mint ${mint.toBase58()}: ${txFundTokenSig}`);
 
@@ -154,12 +170,13 @@ describe("HTLC", () => {
     const createPHTLC = async (Id: Buffer, amount: anchor.BN, timelock: anchor.BN) => {
         const pda = await getPdaParams(Id);
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, amount)
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, amount, { receiver: {} }, null)
             .accountsPartial({
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
                 htlcTokenAccount: pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount
             })
             .signers([wallet.payer])
@@ -170,22 +187,25 @@ describe("HTLC", () => {
         const htlc_pda = await getPdaParams(Id);
 
         const lockTx = await program.methods
-            .lock(Array.from(Id), hashlock, timelock, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, amount)
+            .lock(Array.from(Id), hashlock, timelock, DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, amount, { receiver: {} })
             .accountsPartial({
                 sender: wallet.publicKey,
                 htlc: htlc_pda.htlc,
                 htlcTokenAccount: htlc_pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount
             }).transaction();
 
         const rewardTx = await program.methods
             .lockReward(Array.from(Id), rewardTimelock, new anchor.BN(REWARD))
             .accountsPartial({
+                authority: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: htlc_pda.htlc,
                 htlcTokenAccount: htlc_pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount
             }).transaction();
 
@@ -233,6 +253,42 @@ describe("HTLC", () => {
         return [finalMessage, signature]
     }
 
+    // CI builds the programs with the devnet feature, so add_lock_sig checks the devnet genesis hash.
+    const GENESIS_HASH = new anchor.web3.PublicKey("EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG").toBuffer();
+
+    /// Signs the add_lock_sig message, bound to this program, the cluster and the deadline.
+    const signAddLock = async (Id: Buffer, hashlock: Buffer, timelock: anchor.BN, deadline: anchor.BN): Promise<[Uint8Array, Uint8Array]> => {
+        const name = Buffer.from("add_lock_sig");
+        const MSG = createHash("sha256")
+            .update(Buffer.from([1]))
+            .update(program.programId.toBuffer())
+            .update(GENESIS_HASH)
+            .update(Buffer.from([name.length]))
+            .update(name)
+            .update(Id)
+            .update(hashlock)
+            .update(timelock.toArrayLike(Buffer, "le", 8))
+            .update(deadline.toArrayLike(Buffer, "le", 8))
+            .digest();
+
+        const messageLength = Buffer.alloc(2);
+        messageLength.writeUInt16LE(MSG.length, 0);
+        const applicationDomain = Buffer.alloc(32);
+        applicationDomain.write("Train");
+        const rawMessage = Buffer.concat([
+            Buffer.from("\xffsolana offchain", "ascii"),
+            Buffer.from([0]),
+            applicationDomain,
+            Buffer.from([0]),
+            Buffer.from([1]),
+            wallet.publicKey.toBytes(),
+            messageLength,
+            MSG,
+        ]);
+        const finalMessage = new TextEncoder().encode(rawMessage.toString('hex'));
+        return [finalMessage, await ed.sign(finalMessage, wallet.payer.secretKey.slice(0, 32))];
+    }
+
     function wait(ms: number): Promise<void> {
         return new Promise((resolve) => setTimeout(resolve, ms));
     }
@@ -256,7 +312,9 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
                 htlcTokenAccount: pda.htlcTokenAccount,
                 senderTokenAccount: walletTokenAccount,
                 srcReceiverTokenAccount: bobTokenAccount,
@@ -278,6 +336,7 @@ describe("HTLC", () => {
                 htlcTokenAccount: pda.htlcTokenAccount,
                 sender: wallet.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount,
             })
             .signers([wallet.payer])
@@ -292,6 +351,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(hashlock), timelock).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -320,9 +380,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -372,7 +433,9 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
                 htlcTokenAccount: pda.htlcTokenAccount,
                 senderTokenAccount: walletTokenAccount,
                 srcReceiverTokenAccount: bobTokenAccount,
@@ -400,7 +463,9 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(wrong_secret),
                 htlcTokenAccount: pda.htlcTokenAccount,
                 senderTokenAccount: walletTokenAccount,
                 srcReceiverTokenAccount: bobTokenAccount,
@@ -443,7 +508,9 @@ describe("HTLC", () => {
                 sender: wallet.publicKey,
                 srcReceiver: bob.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
                 htlcTokenAccount: pda.htlcTokenAccount,
                 senderTokenAccount: walletTokenAccount,
                 srcReceiverTokenAccount: bobTokenAccount,
@@ -479,6 +546,7 @@ describe("HTLC", () => {
                 htlcTokenAccount: pda.htlcTokenAccount,
                 sender: wallet.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount,
             })
             .signers([wallet.payer])
@@ -505,6 +573,7 @@ describe("HTLC", () => {
                 htlcTokenAccount: pda.htlcTokenAccount,
                 sender: wallet.publicKey,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: walletTokenAccount,
             })
             .signers([wallet.payer])
@@ -624,14 +693,15 @@ describe("HTLC", () => {
             bob.publicKey
         )
 
-        const pda = await getPdaParams(Id);
+        const pda = await getPdaParams(Id, bob.publicKey);
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT))
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT), { receiver: {} }, null)
             .accountsPartial({
                 sender: bob.publicKey,
                 htlc: pda.htlc,
                 htlcTokenAccount: pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: bobTokenAccount
             })
             .signers([bob])
@@ -650,24 +720,27 @@ describe("HTLC", () => {
             bob.publicKey
         )
 
-        const htlc_pda = await getPdaParams(Id);
+        const htlc_pda = await getPdaParams(Id, bob.publicKey);
 
         const lockTx = await program.methods
-            .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT))
+            .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), { receiver: {} })
             .accountsPartial({
                 sender: bob.publicKey,
                 htlc: htlc_pda.htlc,
                 htlcTokenAccount: htlc_pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: bobTokenAccount
             }).transaction();
         const rewardTx = await program.methods
             .lockReward(Array.from(Id), new anchor.BN(rtime), new anchor.BN(REWARD))
             .accountsPartial({
+                authority: bob.publicKey,
                 sender: bob.publicKey,
                 htlc: htlc_pda.htlc,
                 htlcTokenAccount: htlc_pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: bobTokenAccount
             }).transaction();
 
@@ -758,6 +831,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -775,6 +849,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -793,6 +868,7 @@ describe("HTLC", () => {
 
         const AddLockTx1 = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -801,6 +877,7 @@ describe("HTLC", () => {
 
         const AddLockTx2 = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -819,6 +896,7 @@ describe("HTLC", () => {
         const wrong_time = (new Date().getTime() - 1000000) / 1000;
         const AddLockTx1 = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(wrong_time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -863,9 +941,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -895,6 +974,7 @@ describe("HTLC", () => {
 
         const AddLock = await program.methods.addLock(Array.from(Id), Array.from(HASHLOCK), new anchor.BN(time)).
             accountsPartial({
+                locker: wallet.publicKey,
                 sender: wallet.publicKey,
                 htlc: pda.htlc,
             })
@@ -915,9 +995,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -961,9 +1042,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), wrong_timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), wrong_timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1006,9 +1088,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1051,9 +1134,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1097,9 +1181,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: wallet.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1128,15 +1213,16 @@ describe("HTLC", () => {
         [bob, bobTokenAccount] = await createUserAndAssociatedWallet();
         bobTokenAccount = await mintTokensForUser(bob.publicKey, tokenMint);
         // Create a token account for Bob.
-        const pda = await getPdaParams(Id);
+        const pda = await getPdaParams(Id, bob.publicKey);
 
         const commitTx = await program.methods
-            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, new anchor.BN(AMOUNT))
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, timelock, new anchor.BN(AMOUNT), { receiver: {} }, null)
             .accountsPartial({
                 sender: bob.publicKey,
                 htlc: pda.htlc,
                 htlcTokenAccount: pda.htlcTokenAccount,
                 tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
                 senderTokenAccount: bobTokenAccount
             })
             .signers([bob])
@@ -1157,9 +1243,10 @@ describe("HTLC", () => {
             .add(
                 // Our instruction
                 await program.methods.
-                    addLockSig(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
+                    addLockSigLegacy(Array.from(Id), Array.from(hashlock), timelock, Array.from(signature)).
                     accountsPartial({
                         payer: wallet.publicKey,
+                        sender: bob.publicKey,
                         htlc: pda.htlc,
                         ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
                     })
@@ -1190,4 +1277,607 @@ describe("HTLC", () => {
         await T12_7()
     });
 
-});
\ No newline at end of file
+    /// Lifecycle events are emitted for lock, lockReward and redeem.
+    it("T13", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+        const bobTokenAccount = await spl.getAssociatedTokenAddress(tokenMint, bob.publicKey);
+
+        const events = [];
+        const listeners = ["tokenLocked", "tokenRewardLocked", "tokenRedeemed"].map((name) =>
+            program.addEventListener(name as any, (event) => events.push([name, event]))
+        );
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        await program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+                htlcTokenAccount: pda.htlcTokenAccount,
+                senderTokenAccount: walletTokenAccount,
+                srcReceiverTokenAccount: bobTokenAccount,
+                rewardTokenAccount: walletTokenAccount,
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        await wait(2000);
+        for (const listener of listeners) {
+            await program.removeEventListener(listener);
+        }
+
+        const names = events.map(([name, _]) => name);
+        assert.deepEqual(names, ["tokenLocked", "tokenRewardLocked", "tokenRedeemed"]);
+        const [, locked] = events[0];
+        assert.deepEqual(locked.id, Array.from(Id));
+        assert.deepEqual(locked.hashlock, Array.from(hashlock));
+        assert.equal(locked.srcReceiver.toBase58(), bob.publicKey.toBase58());
+        const [, redeemed] = events[2];
+        assert.deepEqual(redeemed.secret, Array.from(secret));
+        assert.equal(redeemed.redeemAddress.toBase58(), wallet.publicKey.toBase58());
+    });
+
+    /// A committed HTLC cannot be redeemed before the hashlock is added.
+    it("T14", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const pda = await getPdaParams(Id);
+        const bobTokenAccount = await spl.getAssociatedTokenAddress(tokenMint, bob.publicKey);
+
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        assert.deepEqual((await program.account.htlc.fetch(pda.htlc)).status, { committed: {} });
+
+        await assert.rejects(
+            program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+                accountsPartial({
+                    userSigning: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    srcReceiver: bob.publicKey,
+                    tokenContract: tokenMint,
+                    tokenProgram: spl.TOKEN_PROGRAM_ID,
+                    htlc: pda.htlc,
+                    secretRecord: getSecretRecord(secret),
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    senderTokenAccount: walletTokenAccount,
+                    srcReceiverTokenAccount: bobTokenAccount,
+                    rewardTokenAccount: walletTokenAccount,
+                })
+                .signers([wallet.payer])
+                .rpc(),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "HashlockNotSet"
+        );
+        assert.deepEqual((await program.account.htlc.fetch(pda.htlc)).status, { committed: {} });
+    });
+
+    /// Max-length destination addresses of every supported chain family fit in the HTLC account,
+    /// longer strings are rejected up front.
+    it("T15", async () => {
+        const MAX_ADDRESSES = {
+            EVM: "0x" + "ab".repeat(20),
+            STARKNET: "0x" + "ab".repeat(32),
+            FUEL: "0x" + "ab".repeat(32),
+            SOLANA: "1".repeat(44),
+            BITCOIN: "bc1" + "q".repeat(87),
+        };
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const LOCK = async (Id: Buffer, hashlock: number[], timelock: anchor.BN, dstChain: string, dstAddress: string, dstAsset: string) => {
+            const pda = await getPdaParams(Id);
+            await program.methods
+                .lock(Array.from(Id), hashlock, timelock, dstChain, dstAddress, dstAsset, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), { receiver: {} })
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    tokenContract: tokenMint,
+                    tokenProgram: spl.TOKEN_PROGRAM_ID,
+                    senderTokenAccount: walletTokenAccount
+                })
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        for (const [chain, address] of Object.entries(MAX_ADDRESSES)) {
+            const Id = randomBytes(32);
+            const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+            const pda = await getPdaParams(Id);
+            await LOCK(Id, Array.from(hashlock), new anchor.BN(time), chain, address, "A".repeat(66));
+            const htlc = await program.account.htlc.fetch(pda.htlc);
+            assert.equal(htlc.dstAddress, address);
+        }
+
+        const Id = randomBytes(32);
+        const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+        await assert.rejects(
+            LOCK(Id, Array.from(hashlock), new anchor.BN(time), DSTCHAIN, "x".repeat(91), DSTASSET),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "StringTooLong"
+        );
+    });
+
+    /// The hop route is stored on commit and returned by getDetails, mismatched routes are rejected.
+    it("T16", async () => {
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const hopChains = ["ARBITRUM_SEPOLIA", DSTCHAIN];
+        const hopAssets = ["USDC", DSTASSET];
+        const hopAddresses = ["0x" + "ab".repeat(20), DSTADDRESS];
+        const COMMIT = async (Id: Buffer, chains: string[], assets: string[], addresses: string[]) => {
+            const pda = await getPdaParams(Id);
+            await program.methods
+                .commit(Array.from(Id), chains, assets, addresses, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT), { receiver: {} }, null)
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    tokenContract: tokenMint,
+                    tokenProgram: spl.TOKEN_PROGRAM_ID,
+                    senderTokenAccount: walletTokenAccount
+                })
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        const Id = randomBytes(32);
+        const pda = await getPdaParams(Id);
+        await COMMIT(Id, hopChains, hopAssets, hopAddresses);
+        const details = await program.methods.getDetails(Array.from(Id)).accountsPartial({ sender: wallet.publicKey, htlc: pda.htlc }).view();
+        assert.deepEqual(details.hopChains, hopChains);
+        assert.deepEqual(details.hopAssets, hopAssets);
+        assert.deepEqual(details.hopAddresses, hopAddresses);
+
+        await assert.rejects(
+            COMMIT(randomBytes(32), hopChains, hopAssets, [DSTADDRESS]),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "HopLengthMismatch"
+        );
+    });
+
+    /// A settled HTLC can be closed by the sender to get the rent back, a pending one can not.
+    it("T17", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+        const close = () => program.methods.close(Array.from(Id)).
+            accountsPartial({
+                sender: wallet.publicKey,
+                htlc: pda.htlc,
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        await assert.rejects(close(), (e: anchor.AnchorError) => e.error.errorCode.code === "NotSettled");
+
+        await program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+                htlcTokenAccount: pda.htlcTokenAccount,
+                senderTokenAccount: walletTokenAccount,
+                srcReceiverTokenAccount: await spl.getAssociatedTokenAddress(tokenMint, bob.publicKey),
+                rewardTokenAccount: walletTokenAccount,
+            })
+            .signers([wallet.payer])
+            .rpc();
+        const rent = await provider.connection.getBalance(pda.htlc);
+        assert.ok(rent > 0);
+        await close();
+        assert.equal(await provider.connection.getAccountInfo(pda.htlc), null);
+    });
+
+    /// Another sender using the same Id gets a different HTLC, so Ids cannot be squatted.
+    it("T18", async () => {
+        const Id = randomBytes(32);
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const squatter = await getPdaParams(Id, bob.publicKey);
+        await program.methods
+            .commit(Array.from(Id), HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, new anchor.BN(time), new anchor.BN(AMOUNT / 10), { receiver: {} }, null)
+            .accountsPartial({
+                sender: bob.publicKey,
+                htlc: squatter.htlc,
+                htlcTokenAccount: squatter.htlcTokenAccount,
+                tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
+                senderTokenAccount: await spl.getAssociatedTokenAddress(tokenMint, bob.publicKey)
+            })
+            .signers([bob])
+            .rpc();
+
+        const pda = await getPdaParams(Id);
+        assert.notEqual(pda.htlc.toBase58(), squatter.htlc.toBase58());
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        const htlc = await program.account.htlc.fetch(pda.htlc);
+        assert.equal(htlc.sender.toBase58(), wallet.publicKey.toBase58());
+    });
+
+    /// reserve_id hands out sha256(sender || nonce) and increments the sender nonce.
+    it("T19", async () => {
+        const [senderState] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("sender_state"), wallet.publicKey.toBuffer()],
+            program.programId
+        );
+        const state = await program.account.senderState.fetchNullable(senderState);
+        const nonce = state ? state.nonce : new anchor.BN(0);
+        const Id = createHash("sha256").update(wallet.publicKey.toBuffer()).update(nonce.toArrayLike(Buffer, "le", 8)).digest();
+
+        const reserved = await program.methods.reserveId()
+            .accountsPartial({ sender: wallet.publicKey })
+            .view();
+        assert.deepEqual(reserved, Array.from(Id));
+
+        await program.methods.reserveId()
+            .accountsPartial({ sender: wallet.publicKey })
+            .signers([wallet.payer])
+            .rpc();
+        assert.equal((await program.account.senderState.fetch(senderState)).nonce.toString(), nonce.addn(1).toString());
+
+        const time = (new Date().getTime() + 10000000) / 1000;
+        await createPHTLC(Id, new anchor.BN(AMOUNT), new anchor.BN(time));
+        assert.equal((await program.account.htlc.fetch((await getPdaParams(Id)).htlc)).sender.toBase58(), wallet.publicKey.toBase58());
+    });
+
+
+    /// HTLCs can be created and settled for mints of both SPL Token and Token-2022,
+    /// and the token program of the HTLC can not be swapped afterwards.
+    it("T20", async () => {
+        for (const programId of [spl.TOKEN_PROGRAM_ID, spl.TOKEN_2022_PROGRAM_ID]) {
+            const mint = await createMint(programId);
+            const senderTokenAccount = await mintTokensForUser(wallet.publicKey, mint, programId);
+            const bobTokenAccount = spl.getAssociatedTokenAddressSync(mint, bob.publicKey, false, programId);
+            const Id = randomBytes(32);
+            const secret = randomBytes(32);
+            const hashlock = createHash("sha256").update(secret).digest();
+            const time = (new Date().getTime() + 10000000) / 1000;
+            const rtime = (new Date().getTime() + 900000) / 1000;
+            const pda = await getPdaParams(Id);
+
+            const lockTx = await program.methods
+                .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), { receiver: {} })
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    tokenContract: mint,
+                    tokenProgram: programId,
+                    senderTokenAccount,
+                }).transaction();
+            const rewardTx = await program.methods
+                .lockReward(Array.from(Id), new anchor.BN(rtime), new anchor.BN(REWARD))
+                .accountsPartial({
+                    authority: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    tokenContract: mint,
+                    tokenProgram: programId,
+                    senderTokenAccount,
+                }).transaction();
+            await anchor.web3.sendAndConfirmTransaction(provider.connection, new anchor.web3.Transaction().add(lockTx, rewardTx), [wallet.payer]);
+
+            const htlc = await program.account.htlc.fetch(pda.htlc);
+            assert.equal(htlc.tokenProgram.toBase58(), programId.toBase58());
+            const htlcTokenAccount = await spl.getAccount(provider.connection, pda.htlcTokenAccount, undefined, programId);
+            assert.equal(htlcTokenAccount.amount.toString(), (AMOUNT + REWARD).toString());
+
+            const redeem = (tokenProgram: anchor.web3.PublicKey) => program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+                accountsPartial({
+                    userSigning: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    srcReceiver: bob.publicKey,
+                    tokenContract: mint,
+                    tokenProgram,
+                    htlc: pda.htlc,
+                    secretRecord: getSecretRecord(secret),
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    senderTokenAccount,
+                    srcReceiverTokenAccount: bobTokenAccount,
+                    rewardTokenAccount: senderTokenAccount,
+                })
+                .signers([wallet.payer])
+                .rpc();
+            const otherProgram = programId.equals(spl.TOKEN_PROGRAM_ID) ? spl.TOKEN_2022_PROGRAM_ID : spl.TOKEN_PROGRAM_ID;
+            await assert.rejects(redeem(otherProgram), (e: anchor.AnchorError) => e.error.errorCode.code === "WrongTokenProgram");
+
+            await redeem(programId);
+            const bobAccount = await spl.getAccount(provider.connection, bobTokenAccount, undefined, programId);
+            assert.equal(bobAccount.amount.toString(), AMOUNT.toString());
+            assert.equal(await provider.connection.getAccountInfo(pda.htlcTokenAccount), null);
+        }
+    });
+
+    /// With a transfer-fee mint the HTLC records what it received, and the fee payer
+    /// chosen by the sender decides whether the receiver gets the amount minus the fee or in full.
+    it("T21", async () => {
+        const programId = spl.TOKEN_2022_PROGRAM_ID;
+        const FEE_BPS = 100;
+        const fee = (amount: number) => Math.ceil(amount * FEE_BPS / 10000);
+
+        const mint = anchor.web3.Keypair.generate();
+        const space = spl.getMintLen([spl.ExtensionType.TransferFeeConfig]);
+        await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+            anchor.web3.SystemProgram.createAccount({
+                fromPubkey: wallet.publicKey,
+                newAccountPubkey: mint.publicKey,
+                space,
+                lamports: await provider.connection.getMinimumBalanceForRentExemption(space),
+                programId,
+            }),
+            spl.createInitializeTransferFeeConfigInstruction(mint.publicKey, wallet.publicKey, wallet.publicKey, FEE_BPS, BigInt(AMOUNT), programId),
+            spl.createInitializeMintInstruction(mint.publicKey, 6, wallet.publicKey, null, programId),
+        ), [mint]);
+        const senderTokenAccount = await mintTokensForUser(wallet.publicKey, mint.publicKey, programId);
+        const bobTokenAccount = spl.getAssociatedTokenAddressSync(mint.publicKey, bob.publicKey, false, programId);
+        const bobBalance = async () => {
+            const account = await spl.getAccount(provider.connection, bobTokenAccount, undefined, programId).catch(() => null);
+            return account ? Number(account.amount) : 0;
+        };
+
+        for (const feePayer of [{ receiver: {} }, { sender: {} }]) {
+            const Id = randomBytes(32);
+            const secret = randomBytes(32);
+            const hashlock = createHash("sha256").update(secret).digest();
+            const time = (new Date().getTime() + 10000000) / 1000;
+            const pda = await getPdaParams(Id);
+
+            const events = [];
+            const listeners = ["tokenLocked", "tokenRedeemed"].map((name) =>
+                program.addEventListener(name as any, (event) => events.push([name, event]))
+            );
+
+            await program.methods
+                .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), feePayer)
+                .accountsPartial({
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    tokenContract: mint.publicKey,
+                    tokenProgram: programId,
+                    senderTokenAccount,
+                })
+                .signers([wallet.payer])
+                .rpc();
+
+            const htlc = await program.account.htlc.fetch(pda.htlc);
+            const held = (await spl.getAccount(provider.connection, pda.htlcTokenAccount, undefined, programId)).amount;
+            assert.equal(htlc.amount.toString(), held.toString());
+            if ("receiver" in feePayer) {
+                assert.equal(htlc.amount.toNumber(), AMOUNT - fee(AMOUNT));
+            } else {
+                assert.ok(htlc.amount.toNumber() - fee(htlc.amount.toNumber()) >= AMOUNT);
+            }
+
+            const before = await bobBalance();
+            await program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+                accountsPartial({
+                    userSigning: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    srcReceiver: bob.publicKey,
+                    tokenContract: mint.publicKey,
+                    tokenProgram: programId,
+                    htlc: pda.htlc,
+                    secretRecord: getSecretRecord(secret),
+                    htlcTokenAccount: pda.htlcTokenAccount,
+                    senderTokenAccount,
+                    srcReceiverTokenAccount: bobTokenAccount,
+                    rewardTokenAccount: senderTokenAccount,
+                })
+                .signers([wallet.payer])
+                .rpc();
+            const received = (await bobBalance()) - before;
+            if ("receiver" in feePayer) {
+                assert.equal(received, htlc.amount.toNumber() - fee(htlc.amount.toNumber()));
+            } else {
+                assert.equal(received, AMOUNT);
+            }
+            // The withheld fees are harvested to the mint so the HTLC token account can be closed.
+            assert.equal(await provider.connection.getAccountInfo(pda.htlcTokenAccount), null);
+
+            await wait(2000);
+            for (const listener of listeners) {
+                await program.removeEventListener(listener);
+            }
+            const [, locked] = events.find(([name, _]) => name === "tokenLocked");
+            assert.equal(locked.amount.toString(), htlc.amount.toString());
+            assert.ok(locked.lockFee.toNumber() > 0);
+            const [, redeemed] = events.find(([name, _]) => name === "tokenRedeemed");
+            assert.equal(redeemed.payoutFee.toNumber(), htlc.amount.toNumber() - received);
+        }
+    });
+
+    /// Transfer-hook mints get the accounts of their extra-account-meta list through the
+    /// remaining accounts, and the hook runs on every deposit and payout.
+    it("T22", async () => {
+        const hookProgram = anchor.workspace.MockHook as anchor.Program<MockHook>;
+        const programId = spl.TOKEN_2022_PROGRAM_ID;
+
+        const mint = anchor.web3.Keypair.generate();
+        const space = spl.getMintLen([spl.ExtensionType.TransferHook]);
+        await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+            anchor.web3.SystemProgram.createAccount({
+                fromPubkey: wallet.publicKey,
+                newAccountPubkey: mint.publicKey,
+                space,
+                lamports: await provider.connection.getMinimumBalanceForRentExemption(space),
+                programId,
+            }),
+            spl.createInitializeTransferHookInstruction(mint.publicKey, wallet.publicKey, hookProgram.programId, programId),
+            spl.createInitializeMintInstruction(mint.publicKey, 6, wallet.publicKey, null, programId),
+        ), [mint]);
+        const [extraAccountMetaList] = anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("extra-account-metas"), mint.publicKey.toBuffer()],
+            hookProgram.programId
+        );
+        const [calls] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("calls")], hookProgram.programId);
+        await hookProgram.methods.initializeExtraAccountMetaList()
+            .accountsPartial({ payer: wallet.publicKey, extraAccountMetaList, mint: mint.publicKey })
+            .rpc();
+        const hookAccounts = [
+            { pubkey: calls, isSigner: false, isWritable: true },
+            { pubkey: hookProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: extraAccountMetaList, isSigner: false, isWritable: false },
+        ];
+        const callCount = async () => (await hookProgram.account.calls.fetch(calls)).count.toNumber();
+
+        const senderTokenAccount = await mintTokensForUser(wallet.publicKey, mint.publicKey, programId);
+        const bobTokenAccount = spl.getAssociatedTokenAddressSync(mint.publicKey, bob.publicKey, false, programId);
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+
+        const lock = (remainingAccounts: anchor.web3.AccountMeta[]) => program.methods
+            .lock(Array.from(Id), Array.from(hashlock), new anchor.BN(time), DSTCHAIN, DSTADDRESS, DSTASSET, SRCASSET, bob.publicKey, new anchor.BN(AMOUNT), { receiver: {} })
+            .accountsPartial({
+                sender: wallet.publicKey,
+                htlc: pda.htlc,
+                htlcTokenAccount: pda.htlcTokenAccount,
+                tokenContract: mint.publicKey,
+                tokenProgram: programId,
+                senderTokenAccount,
+            })
+            .remainingAccounts(remainingAccounts)
+            .signers([wallet.payer])
+            .rpc();
+        await assert.rejects(lock([]));
+
+        let count = await callCount();
+        await lock(hookAccounts);
+        assert.equal(await callCount(), count + 1);
+        const locked = await hookProgram.account.calls.fetch(calls);
+        assert.equal(locked.lastSource.toBase58(), senderTokenAccount.toBase58());
+        assert.equal(locked.lastDestination.toBase58(), pda.htlcTokenAccount.toBase58());
+        assert.equal(locked.lastAmount.toNumber(), AMOUNT);
+
+        await program.methods
+            .lockReward(Array.from(Id), new anchor.BN(rtime), new anchor.BN(REWARD))
+            .accountsPartial({
+                authority: wallet.publicKey,
+                sender: wallet.publicKey,
+                htlc: pda.htlc,
+                htlcTokenAccount: pda.htlcTokenAccount,
+                tokenContract: mint.publicKey,
+                tokenProgram: programId,
+                senderTokenAccount,
+            })
+            .remainingAccounts(hookAccounts)
+            .signers([wallet.payer])
+            .rpc();
+        assert.equal(await callCount(), count + 2);
+
+        // Redeeming before the reward timelock pays the amount to bob and the reward back to the sender.
+        await program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                tokenContract: mint.publicKey,
+                tokenProgram: programId,
+                htlc: pda.htlc,
+                secretRecord: getSecretRecord(secret),
+                htlcTokenAccount: pda.htlcTokenAccount,
+                senderTokenAccount,
+                srcReceiverTokenAccount: bobTokenAccount,
+                rewardTokenAccount: senderTokenAccount,
+            })
+            .remainingAccounts(hookAccounts)
+            .signers([wallet.payer])
+            .rpc();
+        assert.equal(await callCount(), count + 4);
+        const bobAccount = await spl.getAccount(provider.connection, bobTokenAccount, undefined, programId);
+        assert.equal(bobAccount.amount.toString(), AMOUNT.toString());
+    });
+
+    /// The first redeem records the secret of the hashlock, so it can be read without the HTLC.
+    it("T23", async () => {
+        const Id = randomBytes(32);
+        const secret = randomBytes(32);
+        const hashlock = createHash("sha256").update(secret).digest();
+        const time = (new Date().getTime() + 10000000) / 1000;
+        const rtime = (new Date().getTime() + 900000) / 1000;
+        const pda = await getPdaParams(Id);
+        const secretRecord = getSecretRecord(secret);
+
+        await createHTLC(Id, new anchor.BN(rtime), new anchor.BN(time), new anchor.BN(AMOUNT), Array.from(hashlock));
+        assert.equal(await provider.connection.getAccountInfo(secretRecord), null);
+        await program.methods.redeem(Array.from(Id), Array.from(secret), pda.htlcBump).
+            accountsPartial({
+                userSigning: wallet.publicKey,
+                sender: wallet.publicKey,
+                srcReceiver: bob.publicKey,
+                tokenContract: tokenMint,
+                tokenProgram: spl.TOKEN_PROGRAM_ID,
+                htlc: pda.htlc,
+                secretRecord,
+                htlcTokenAccount: pda.htlcTokenAccount,
+                senderTokenAccount: walletTokenAccount,
+                srcReceiverTokenAccount: await spl.getAssociatedTokenAddress(tokenMint, bob.publicKey),
+                rewardTokenAccount: walletTokenAccount,
+            })
+            .signers([wallet.payer])
+            .rpc();
+
+        const record = await program.account.secretRecord.fetch(secretRecord);
+        assert.deepEqual(record.hashlock, Array.from(hashlock));
+        assert.deepEqual(record.secret, Array.from(secret));
+        const recorded = await program.methods.getSecret(Array.from(hashlock))
+            .accountsPartial({ secretRecord })
+            .view();
+        assert.deepEqual(recorded.secret, Array.from(secret));
+    });
+
+    /// Can add lock with a signature bound to the program, the cluster and a deadline, until the deadline.
+    it("T24", async () => {
+        const now = Math.floor(new Date().getTime() / 1000);
+        const timelock = new anchor.BN(now + 1000);
+        const hashlock = createHash("sha256").update(randomBytes(32)).digest();
+        const addLockSig = async (Id: Buffer, deadline: anchor.BN) => {
+            const pda = await getPdaParams(Id);
+            const [message, signature] = await signAddLock(Id, hashlock, timelock, deadline);
+            return program.methods.addLockSig(Array.from(Id), Array.from(hashlock), timelock, deadline, Array.from(signature))
+                .accountsPartial({
+                    payer: wallet.publicKey,
+                    sender: wallet.publicKey,
+                    htlc: pda.htlc,
+                    ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+                })
+                .preInstructions([anchor.web3.Ed25519Program.createInstructionWithPublicKey({
+                    publicKey: wallet.publicKey.toBytes(),
+                    message,
+                    signature,
+                })])
+                .signers([wallet.payer])
+                .rpc();
+        };
+
+        const expired = randomBytes(32);
+        await createPHTLC(expired, new anchor.BN(AMOUNT), timelock);
+        await assert.rejects(
+            addLockSig(expired, new anchor.BN(now - 1)),
+            (e: anchor.AnchorError) => e.error.errorCode.code === "SignatureExpired"
+        );
+
+        const Id = randomBytes(32);
+        await createPHTLC(Id, new anchor.BN(AMOUNT), timelock);
+        await addLockSig(Id, new anchor.BN(now + 600));
+        const htlc = await program.account.htlc.fetch((await getPdaParams(Id)).htlc);
+        assert.deepEqual(htlc.hashlock, Array.from(hashlock));
+        assert.equal(htlc.timelock.toString(), timelock.toString());
+    });
+});
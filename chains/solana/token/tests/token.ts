@@ -53,11 +53,11 @@ describe("HTLC", () => {
   ): Promise<PDAParameters> => {
     // let pseed = ID.toBuffer('le', 8);
     let [htlc, htlcBump] = await anchor.web3.PublicKey.findProgramAddressSync(
-      [Id],
+      [Buffer.from("htlc"), wallet.publicKey.toBuffer(), Id],
       program.programId
     );
     let [htlcTokenAccount, htlcTokenbump] = await anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("htlc_token_account"), Id],
+      [Buffer.from("htlc_token_account"), wallet.publicKey.toBuffer(), Id],
       program.programId
     );
     console.log(`[${htlc}] derived htlc`);
@@ -69,6 +69,15 @@ describe("HTLC", () => {
     };
   };
 
+  const getSecretRecord = (secret: Buffer): anchor.web3.PublicKey => {
+    const hashlock = createHash("sha256").update(secret).digest();
+    const [secretRecord] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("secret_record"), hashlock],
+      program.programId
+    );
+    return secretRecord;
+  };
+
   const createMint = async (): Promise<anchor.web3.PublicKey> => {
     const tokenMint = new anchor.web3.Keypair();
     const lamportsForMint = await provider.connection.getMinimumBalanceForRentExemption(spl.MintLayout.span);
@@ -189,12 +198,13 @@ describe("HTLC", () => {
     console.log(`[${TIMELOCK * 1000}] the Timelock`);
 
     const commitTx = await program.methods
-      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, TIMELOCK, new anchor.BN(AMOUNT))
+      .commit(IDArray, HOPCHAINS, HOPASSETS, HOPADDRESSES, DSTCHAIN, DSTASSET, DSTADDRESS, SRCASSET, bob.publicKey, TIMELOCK, new anchor.BN(AMOUNT), { receiver: {} }, null)
       .accountsPartial({
         sender: wallet.publicKey,
         htlc: pda.htlc,
         htlcTokenAccount: pda.htlcTokenAccount,
         tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
         senderTokenAccount: walletTokenAccount
       })
       .signers([wallet.payer])
@@ -264,9 +274,10 @@ describe("HTLC", () => {
       .add(
         // Our instruction
         await program.methods.
-          addLockSig(IDArray, HASHLOCKArray, TIMELOCK, Array.from(signature)).
+          addLockSigLegacy(IDArray, HASHLOCKArray, TIMELOCK, Array.from(signature)).
           accountsPartial({
             payer: wallet.publicKey,
+            sender: wallet.publicKey,
             htlc: pda.htlc,
             ixSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
           })
@@ -302,7 +313,9 @@ describe("HTLC", () => {
         sender: wallet.publicKey,
         srcReceiver: bob.publicKey,
         tokenContract: tokenMint,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
         htlc: pda.htlc,
+        secretRecord: getSecretRecord(SECRET),
         htlcTokenAccount: pda.htlcTokenAccount,
         senderTokenAccount: walletTokenAccount,
         srcReceiverTokenAccount: bobTokenAccount,